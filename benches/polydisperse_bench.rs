@@ -0,0 +1,59 @@
+#![feature(test)]
+
+extern crate test;
+
+extern crate boltzmann;
+extern crate rand;
+
+use boltzmann::loose_quadtree::LooseQuadtree;
+use boltzmann::bvh::Bvh;
+use boltzmann::collision::*;
+use boltzmann::vector::Vector;
+
+// radii from 0.5 to 5
+fn insert<T: SpatialPartition>(spatial_partition: &mut T) {
+    for i in 0..10000 {
+        let x = rand::random::<f64>() * 512.0;
+        let y = rand::random::<f64>() * 512.0;
+        let r = 0.5 * 10.0f64.powf(rand::random::<f64>());
+        spatial_partition.insert_with_radius( i, Vector::new(x, y), r );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test::Bencher;
+
+    #[bench]
+    fn bench_loose_quadtree(b: &mut Bencher) {
+        let mut loose_quadtree = LooseQuadtree::new(512.0, 512.0, 2.0);
+        insert(&mut loose_quadtree);
+        
+        b.iter(|| {let _ = loose_quadtree.collision_check(); });
+    }
+    
+    #[bench]
+    fn bench_loose_quadtree_parallel(b: &mut Bencher) {
+        let mut loose_quadtree = LooseQuadtree::new(512.0, 512.0, 2.0);
+        insert(&mut loose_quadtree);
+        
+        b.iter(|| {let _ = loose_quadtree.collision_check_parallel(); });
+    }
+
+    #[bench]
+    fn bench_bvh(b: &mut Bencher) {
+        let mut bvh = Bvh::new(2.0);
+        insert(&mut bvh);
+        
+        b.iter(|| {let _ = bvh.collision_check(); });
+    }
+    
+    #[bench]
+    fn bench_bvh_parallel(b: &mut Bencher) {
+        let mut bvh = Bvh::new(2.0);
+        insert(&mut bvh);
+        
+        b.iter(|| {let _ = bvh.collision_check_parallel(); });
+    }
+}
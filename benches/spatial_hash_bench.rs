@@ -6,6 +6,7 @@ extern crate boltzmann;
 extern crate rand;
 
 use boltzmann::spatial_hash::SpatialHash;
+use boltzmann::cell_list::CellList;
 use boltzmann::collision::*;
 use boltzmann::vector::Vector;
 
@@ -43,4 +44,32 @@ mod tests {
         
         b.iter(|| {let _ = spatial_hash.collision_check_parallel();} );
     }
+
+    #[bench]
+    fn bench_cell_list(b: &mut Bencher) {
+        let mut cell_list = CellList::new(512.0, 512.0, 32, 32, 2.0).unwrap();
+                
+        for i in 0..10000 {
+            let x = rand::random::<f64>() * 512.0;
+            let y = rand::random::<f64>() * 512.0;
+            let p = Vector::new(x, y);
+            cell_list.insert( i, p );
+        }
+        
+        b.iter(|| {let _ = cell_list.collision_check(); });
+    }
+
+    #[bench]
+    fn bench_cell_list_parallel(b: &mut Bencher) {
+        let mut cell_list = CellList::new(512.0, 512.0, 32, 32, 2.0).unwrap();
+                
+        for i in 0..10000 {
+            let x = rand::random::<f64>() * 512.0;
+            let y = rand::random::<f64>() * 512.0;
+            let p = Vector::new(x, y);
+            cell_list.insert( i, p );
+        }
+        
+        b.iter(|| {let _ = cell_list.collision_check_parallel();} );
+    }
 }
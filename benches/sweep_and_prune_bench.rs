@@ -0,0 +1,45 @@
+#![feature(test)]
+
+extern crate test;
+
+extern crate boltzmann;
+extern crate rand;
+
+use boltzmann::sweep_and_prune::SweepAndPrune;
+use boltzmann::collision::*;
+use boltzmann::vector::Vector;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test::Bencher;
+
+    #[bench]
+    fn bench_sweep_and_prune(b: &mut Bencher) {
+        let mut sweep_and_prune = SweepAndPrune::new(512.0, 512.0, 2.0);
+                
+        for i in 0..10000 {
+            let x = rand::random::<f64>() * 512.0;
+            let y = rand::random::<f64>() * 512.0;
+            let p = Vector::new(x, y);
+            sweep_and_prune.insert( i, p );
+        }
+        
+        b.iter(|| {let _ = sweep_and_prune.collision_check(); });
+    }
+    
+    // long thin channel, the case a square grid handles badly
+    #[bench]
+    fn bench_sweep_and_prune_channel_parallel(b: &mut Bencher) {
+        let mut sweep_and_prune = SweepAndPrune::new(8192.0, 32.0, 2.0);
+                
+        for i in 0..10000 {
+            let x = rand::random::<f64>() * 8192.0;
+            let y = rand::random::<f64>() * 32.0;
+            let p = Vector::new(x, y);
+            sweep_and_prune.insert( i, p );
+        }
+        
+        b.iter(|| {let _ = sweep_and_prune.collision_check_parallel();} );
+    }
+}
@@ -1,96 +1,452 @@
+use std::any::Any;
+
 use particle::Particle;
+use vector::Vector;
 use common::scale;
+use simulator::SimulatorRng;
+
+/// What an attribute stores for each particle. `to_scalar` is what the particles
+/// are coloured and histogrammed by, `from_scalar` is what `Attribute::set` stores.
+pub trait State: Clone + Default + 'static {
+    fn to_scalar(&self) -> f64;
+    fn from_scalar(f: f64) -> Self;
+}
+
+impl State for f64 {
+    fn to_scalar(&self) -> f64 { *self }
+    fn from_scalar(f: f64) -> f64 { f }
+}
+
+impl State for i64 {
+    fn to_scalar(&self) -> f64 { *self as f64 }
+    fn from_scalar(f: f64) -> i64 { f.round() as i64 }
+}
+
+impl State for usize {
+    fn to_scalar(&self) -> f64 { *self as f64 }
+    fn from_scalar(f: f64) -> usize { f.max(0.0).round() as usize }
+}
+
+impl State for bool {
+    fn to_scalar(&self) -> f64 { if *self { 1.0 } else { 0.0 } }
+    fn from_scalar(f: f64) -> bool { f != 0.0 }
+}
+
+// projects to its length, set gives a vector along x
+impl State for Vector {
+    fn to_scalar(&self) -> f64 { self.magnitude() }
+    fn from_scalar(f: f64) -> Vector { Vector::new(f, 0.0) }
+}
+
+/// A collision as the simulator resolved it. `normal` points from the first particle
+/// to the second, `impact_speed` is how fast they were closing along it, negative if
+/// they were already separating, and `impulse` is the momentum each received along
+/// it, 0 if they were separating. `kinetic_energy_lost` is 0 unless the collision
+/// was inelastic, see `Response`. When deciding the response the collision hasn't
+/// been resolved yet and both are 0.
+///
+/// `reduced_mass` is m1 m2 / (m1 + m2), a fixed particle counting as infinitely
+/// heavy, so `0.5 * reduced_mass * impact_speed^2` is the energy of the impact.
+#[derive(Copy, Clone)]
+pub struct Impact {
+    pub normal: Vector,
+    pub penetration: f64,
+    pub impact_speed: f64,
+    pub impulse: f64,
+    pub kinetic_energy_lost: f64,
+    pub reduced_mass: f64,
+}
+
+/// How a collision is resolved, `Bounce` with a restitution of 1 is elastic and
+/// 0 leaves the particles moving together along the normal, so sticky particles
+/// clump. `Pass` lets them go through each other, the collision didn't happen.
+/// `Merge` puts both particles' mass and momentum into the first of the pair at
+/// their centre of mass, the second is removed once every collision is resolved.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Response {
+    Bounce(f64),
+    Pass,
+    Merge
+}
+
+/// The parts of a particle an attribute can change in `act`. `fixed` holds it in
+/// place, see `ParticleStore`.
+#[derive(Copy, Clone)]
+pub struct Body {
+    pub velocity: Vector,
+    pub mass: f64,
+    pub fixed: bool,
+}
+
+/// What an attribute sees besides its own data. `time` is `step * dt`, `impact` is
+/// only set for collision updates, and other attributes can be read but not the
+/// one being updated. Random numbers should come from `rng` so a seed reproduces
+/// the run.
+pub struct Context<'a> {
+    pub time: f64,
+    pub dt: f64,
+    pub step: usize,
+    pub rng: &'a mut SimulatorRng,
+    pub impact: Option<Impact>,
+    attributes: &'a AttributeRegistry,
+}
+
+impl<'a> Context<'a> {
+    pub fn attribute<A: 'static + Attribute>(&self) -> Option<&A> {
+        self.attributes.of_type::<A>()
+    }
+
+    pub fn attribute_by_name(&self, name: &str) -> Option<&Attribute> {
+        self.attributes.by_name(name)
+    }
+}
 
 pub trait Attribute {
     fn new() -> Self where Self: Sized; 
-    fn get_data(&self) -> &Vec<f64>;
-    fn collision_update(&mut self, usize, usize, _: &Particle, _: &Particle) {  }
-    fn update(&mut self, usize, _: &Particle) {  }
+    // what bind_attribute registers it under, the name of the type
+    fn name() -> &'static str where Self: Sized;
+    // for finding an attribute by type, see AttributeRegistry
+    fn as_any(&self) -> &Any;
+    fn as_any_mut(&mut self) -> &mut Any;
+    // every particle's state projected to a scalar, see State
+    fn scalars(&self) -> Vec<f64>;
+    fn collision_update(&mut self, usize, usize, _: &Particle, _: &Particle, _: &mut Context) {  }
+    fn update(&mut self, usize, _: &Particle, _: &mut Context) {  }
+    // once a step, after every particle has been updated
+    fn step(&mut self, _: &mut Context) {  }
+    // how the collision between i and j is resolved, None leaves it to the other
+    // attributes and an elastic bounce if none of them decide. Every attribute is
+    // asked, Pass and Merge win over Bounce and otherwise the first bound does. The
+    // context has the impact as it is before being resolved
+    fn collision_response(&mut self, usize, usize, _: &mut Context) -> Option<Response> { None }
+    // changes particle i after the collisions are resolved and before it is moved
+    fn act(&mut self, usize, _: &mut Body, _: &mut Context) {  }
     fn collision_listener(&self) -> bool;
-    fn initialise(&mut self, t: Vec<f64>);
+    // every particle starts in the default state
+    fn initialise(&mut self, number_of_particles: usize);
     fn set(&mut self, f: f64, i: f64);
+    // follows the particles when the simulator reorders them, value order[k] moves to k
+    fn reorder(&mut self, order: &[usize]);
+    // a particle added after the attribute was bound, in the default state
+    fn push(&mut self);
+    // follows the simulator removing particle i, the last value takes its place
+    fn swap_remove(&mut self, i: usize);
     fn data_bounds(&self) -> (f64, f64) {
-        let max = self.get_data().iter().cloned().fold(0./0., f64::max);
+        let max = self.scalars().iter().cloned().fold(0./0., f64::max);
         (0.0, max)
     }
 }
 
+/// The struct and the parts of the Attribute impl that don't depend on what it listens
+/// to, `data()` and `data_mut()` give the typed state. `impl_collision_attribute!`
+/// and `impl_attribute!` are built on it and cover an attribute with one update, for
+/// anything else, such as `collision_response`, `act`, `step` or several of them, the
+/// methods are given in the braces and `collision_listener` must be one of them:
+///
+/// ```ignore
+/// impl_attribute_common! { ghost_attr, f64, {
+///     fn collision_listener(&self) -> bool { false }
+///     fn collision_response(&mut self, i: usize, j: usize, _: &mut Context) -> Option<Response> {
+///         if self.data()[i] == 1.0 || self.data()[j] == 1.0 { Some(Response::Pass) } else { None }
+///     }
+/// } }
+/// ```
+///
+/// As with the other two, `Attribute`, `State`, `Particle`, `Context` and `scale` need
+/// to be in scope, and whatever else the methods use.
 #[macro_export]
-macro_rules! impl_collision_attribute {
-    ( $c:ident, $self_:ident, $i:ident, $j:ident, $p1:ident, $p2:ident, $l:expr, $u:expr, $b:block ) => { 
+macro_rules! impl_attribute_common {
+    ( $c:ident, $t:ty, { $($listener:tt)* } ) => {
         pub struct $c { 
-            t: Vec<f64> 
+            t: Vec<$t> 
+        }
+        impl $c {
+            pub fn data(&self) -> &Vec<$t> { &self.t }
+            pub fn data_mut(&mut self) -> &mut Vec<$t> { &mut self.t }
         }
         impl Attribute for $c { 
             fn new() -> $c { $c { t: vec![] } }
-            fn get_data(&self) -> &Vec<f64> { &self.t } 
+            fn name() -> &'static str { stringify!($c) }
+            fn as_any(&self) -> &::std::any::Any { self }
+            fn as_any_mut(&mut self) -> &mut ::std::any::Any { self }
+            fn scalars(&self) -> Vec<f64> { self.t.iter().map(|s| s.to_scalar()).collect() }
+            fn initialise(&mut self, number_of_particles: usize) { self.t = vec![<$t>::default(); number_of_particles] }
+            fn reorder(&mut self, order: &[usize]) { self.t = order.iter().map(|&k| self.t[k].clone()).collect(); }
+            fn push(&mut self) { self.t.push(<$t>::default()); }
+            fn swap_remove(&mut self, i: usize) { self.t.swap_remove(i); }
+            fn set(&mut self, f: f64, i: f64) { let l = self.t.len() as f64; self.t[scale(i, [0.0, 1.0], [0.0, l - 1.0]) as usize] = <$t as State>::from_scalar(f); } 
+            $($listener)*
+        }
+    };
+}
+
+// the state is f64 unless a type is given after the name, `name: Type, ...`, and the
+// Context can be named after the particles, `..., p1, p2, context, ...`
+#[macro_export]
+macro_rules! impl_collision_attribute {
+    ( $c:ident : $t:ty, $self_:ident, $i:ident, $j:ident, $p1:ident, $p2:ident, $context:ident, $l:expr, $u:expr, $b:block ) => { 
+        impl_attribute_common! { $c, $t, {
             fn collision_listener(&self) -> bool { true }
-            fn collision_update(&mut $self_, $i: usize, $j: usize, $p1: &Particle, $p2: &Particle) { $b }
-            fn initialise(&mut self, t: Vec<f64>) { self.t = t }
-            fn set(&mut self, f: f64, i: f64) { let l = self.t.len() as f64; self.t[scale(i, [0.0, 1.0], [0.0, l - 1.0]) as usize] = f; } 
+            fn collision_update(&mut $self_, $i: usize, $j: usize, $p1: &Particle, $p2: &Particle, $context: &mut Context) { $b }
             fn data_bounds(&self) -> (f64, f64) { ($l, $u) }
-        }
+        } }
     };
-    ( $c:ident, $self_:ident, $i:ident, $j:ident, $p1:ident, $p2:ident, $b:block ) => { 
-        pub struct $c { 
-            t: Vec<f64> 
-        }
-        impl Attribute for $c { 
-            fn new() -> $c { $c { t: vec![] } }
-            fn get_data(&self) -> &Vec<f64> { &self.t } 
+    ( $c:ident : $t:ty, $self_:ident, $i:ident, $j:ident, $p1:ident, $p2:ident, $context:ident, $b:block ) => { 
+        impl_attribute_common! { $c, $t, {
             fn collision_listener(&self) -> bool { true }
-            fn collision_update(&mut $self_, $i: usize, $j: usize, $p1: &Particle, $p2: &Particle) { $b }
-            fn initialise(&mut self, t: Vec<f64>) { self.t = t }
-            fn set(&mut self, f: f64, i: f64) { let l = self.t.len() as f64; self.t[scale(i, [0.0, 1.0], [0.0, l - 1.0]) as usize] = f; } 
-        }
+            fn collision_update(&mut $self_, $i: usize, $j: usize, $p1: &Particle, $p2: &Particle, $context: &mut Context) { $b }
+        } }
+    };
+    ( $c:ident : $t:ty, $self_:ident, $i:ident, $j:ident, $p1:ident, $p2:ident, $l:expr, $u:expr, $b:block ) => { 
+        impl_collision_attribute! { $c : $t, $self_, $i, $j, $p1, $p2, _context, $l, $u, $b }
+    };
+    ( $c:ident : $t:ty, $self_:ident, $i:ident, $j:ident, $p1:ident, $p2:ident, $b:block ) => { 
+        impl_collision_attribute! { $c : $t, $self_, $i, $j, $p1, $p2, _context, $b }
+    };
+    ( $c:ident, $($rest:tt)* ) => { 
+        impl_collision_attribute! { $c : f64, $($rest)* }
     };
 }
 
+#[macro_export]
 macro_rules! impl_attribute {
-    ( $c:ident, $self_:ident, $i:ident, $p:ident, $l:expr, $u:expr, $b:block ) => { 
-        pub struct $c { 
-            t: Vec<f64> 
-        }
-        impl Attribute for $c { 
-            fn new() -> $c { $c { t: vec![] } }
-            fn get_data(&self) -> &Vec<f64> { &self.t } 
+    ( $c:ident : $t:ty, $self_:ident, $i:ident, $p:ident, $context:ident, $l:expr, $u:expr, $b:block ) => { 
+        impl_attribute_common! { $c, $t, {
             fn collision_listener(&self) -> bool { false }
-            fn update(&mut $self_, $i: usize, $p: &Particle) { $b }
-            fn initialise(&mut self, t: Vec<f64>) { self.t = t }
-            fn set(&mut self, f: f64, i: f64) { let l = self.t.len() as f64; self.t[scale(i, [0.0, 1.0], [0.0, l - 1.0]) as usize] = f; } 
+            fn update(&mut $self_, $i: usize, $p: &Particle, $context: &mut Context) { $b }
             fn data_bounds(&self) -> (f64, f64) { ($l, $u) }
-        }
+        } }
     };
-    ( $c:ident, $self_:ident, $i:ident, $p:ident, $b:block ) => { 
-        pub struct $c { 
-            t: Vec<f64> 
-        }
-        impl Attribute for $c { 
-            fn new() -> $c { $c { t: vec![] } }
-            fn get_data(&self) -> &Vec<f64> { &self.t } 
+    ( $c:ident : $t:ty, $self_:ident, $i:ident, $p:ident, $context:ident, $b:block ) => { 
+        impl_attribute_common! { $c, $t, {
             fn collision_listener(&self) -> bool { false }
-            fn update(&mut $self_, $i: usize, $p: &Particle) { $b }
-            fn initialise(&mut self, t: Vec<f64>) { self.t = t }
-            fn set(&mut self, f: f64, i: f64) { let l = self.t.len() as f64; self.t[scale(i, [0.0, 1.0], [0.0, l - 1.0]) as usize] = f; } 
-        }
+            fn update(&mut $self_, $i: usize, $p: &Particle, $context: &mut Context) { $b }
+        } }
+    };
+    ( $c:ident : $t:ty, $self_:ident, $i:ident, $p:ident, $l:expr, $u:expr, $b:block ) => { 
+        impl_attribute! { $c : $t, $self_, $i, $p, _context, $l, $u, $b }
+    };
+    ( $c:ident : $t:ty, $self_:ident, $i:ident, $p:ident, $b:block ) => { 
+        impl_attribute! { $c : $t, $self_, $i, $p, _context, $b }
     };
+    ( $c:ident, $($rest:tt)* ) => { 
+        impl_attribute! { $c : f64, $($rest)* }
+    };
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct AttributeId(usize);
+
+struct Entry {
+    name: String,
+    attribute: Box<Attribute>,
 }
 
-impl_collision_attribute! { virus_attr, self, i, j, p1, p2, 0.0, 1.0,
+/// The attributes bound to a simulator, found by the id `bind` returned, by name
+/// or by type. Names are unique but there can be any number of attributes of a
+/// type under different names. Ids aren't reused once an attribute is removed,
+/// attributes are updated in the order they were bound.
+pub struct AttributeRegistry {
+    entries: Vec<Option<Entry>>,
+}
+
+impl AttributeRegistry {
+    pub fn new() -> AttributeRegistry {
+        AttributeRegistry {
+            entries: vec![]
+        }
+    }
+
+    // None if the name is taken, every particle starts in the default state
+    pub fn bind<A: 'static + Attribute>(&mut self, name: &str, number_of_particles: usize) -> Option<AttributeId> {
+        if self.id(name).is_some() {
+            return None;
+        }
+
+        let mut attribute = Box::new( A::new() );
+        attribute.initialise(number_of_particles);
+        self.entries.push( Some(Entry { name: name.to_string(), attribute: attribute }) );
+        Some( AttributeId(self.entries.len() - 1) )
+    }
+
+    // false if there was no such attribute
+    pub fn remove(&mut self, id: AttributeId) -> bool {
+        match self.entries.get_mut(id.0) {
+            Some(entry) => entry.take().is_some(),
+            None => false
+        }
+    }
+
+    pub fn id(&self, name: &str) -> Option<AttributeId> {
+        self.entries.iter().position(|e| e.as_ref().map_or(false, |e| e.name == name)).map(AttributeId)
+    }
+
+    pub fn name(&self, id: AttributeId) -> Option<&str> {
+        self.entry(id).map(|e| &*e.name)
+    }
+
+    // ids in the order they were bound
+    pub fn ids(&self) -> Vec<AttributeId> {
+        (0..self.entries.len()).filter(|&i| self.entries[i].is_some()).map(AttributeId).collect()
+    }
+
+    pub fn get(&self, id: AttributeId) -> Option<&Attribute> {
+        self.entry(id).map(|e| &*e.attribute)
+    }
+
+    pub fn get_mut(&mut self, id: AttributeId) -> Option<&mut Attribute> {
+        match self.entries.get_mut(id.0) {
+            Some(&mut Some(ref mut e)) => Some(&mut *e.attribute),
+            _ => None
+        }
+    }
+
+    pub fn by_name(&self, name: &str) -> Option<&Attribute> {
+        self.id(name).and_then(|id| self.get(id))
+    }
+
+    // the first attribute of type A that was bound
+    pub fn of_type<A: 'static + Attribute>(&self) -> Option<&A> {
+        self.entries.iter().filter_map(|e| e.as_ref()).filter_map(|e| e.attribute.as_any().downcast_ref::<A>()).next()
+    }
+
+    pub fn of_type_mut<A: 'static + Attribute>(&mut self) -> Option<&mut A> {
+        self.entries.iter_mut().filter_map(|e| e.as_mut()).filter_map(|e| e.attribute.as_any_mut().downcast_mut::<A>()).next()
+    }
+
+    // every attribute of type A, in the order they were bound
+    pub fn all_of_type<A: 'static + Attribute>(&self) -> Vec<(AttributeId, &A)> {
+        self.entries.iter().enumerate()
+            .filter_map(|(i, e)| e.as_ref().and_then(|e| e.attribute.as_any().downcast_ref::<A>()).map(|a| (AttributeId(i), a)))
+            .collect()
+    }
+
+    fn entry(&self, id: AttributeId) -> Option<&Entry> {
+        self.entries.get(id.0).and_then(|e| e.as_ref())
+    }
+
+    fn attributes_mut<'a>(&'a mut self) -> Box<Iterator<Item=&'a mut Box<Attribute>> + 'a> {
+        Box::new( self.entries.iter_mut().filter_map(|e| e.as_mut()).map(|e| &mut e.attribute) )
+    }
+
+    // runs f on each attribute with a context lending out the others, until f returns
+    // something. The attribute is taken out while f runs
+    fn with_context<R, F>(&mut self, step: usize, dt: f64, rng: &mut SimulatorRng, impact: Option<Impact>, mut f: F) -> Option<R>
+        where F: FnMut(&mut Attribute, &mut Context) -> Option<R>
     {
-        let max = self.t[i].max(self.t[j]);
-        
-        if self.t[i] == 1.0 && self.t[j] == 0.0 {
-            self.t[j] = 0.5;
+        for k in 0..self.entries.len() {
+            if let Some(mut entry) = self.entries[k].take() {
+                let result = {
+                    let mut context = Context { time: step as f64 * dt, dt: dt, step: step, rng: &mut *rng, impact: impact, attributes: &*self };
+                    f(&mut *entry.attribute, &mut context)
+                };
+                self.entries[k] = Some(entry);
+                if result.is_some() {
+                    return result;
+                }
+            }
+        }
+        None
+    }
+
+    pub fn collision_update(&mut self, i: usize, j: usize, p1: &Particle, p2: &Particle, impact: Impact, step: usize, dt: f64, rng: &mut SimulatorRng) {
+        self.with_context(step, dt, rng, Some(impact), |a, context| {
+            if a.collision_listener() {
+                a.collision_update(i, j, p1, p2, context);
+            }
+            None::<()>
+        });
+    }
+
+    // collision listeners are updated too, an attribute can do both
+    pub fn update(&mut self, i: usize, p: &Particle, step: usize, dt: f64, rng: &mut SimulatorRng) {
+        self.with_context(step, dt, rng, None, |a, context| { a.update(i, p, context); None::<()> });
+    }
+
+    pub fn step(&mut self, step: usize, dt: f64, rng: &mut SimulatorRng) {
+        self.with_context(step, dt, rng, None, |a, context| { a.step(context); None::<()> });
+    }
+
+    // every attribute is asked, so each sees the collision. Pass and Merge win over
+    // Bounce, otherwise the first bound to decide does
+    pub fn collision_response(&mut self, i: usize, j: usize, impact: Impact, step: usize, dt: f64, rng: &mut SimulatorRng) -> Option<Response> {
+        let mut response = None;
+        self.with_context(step, dt, rng, Some(impact), |a, context| {
+            match (response, a.collision_response(i, j, context)) {
+                (None, r) => response = r,
+                (Some(Response::Bounce(_)), Some(r @ Response::Pass)) |
+                (Some(Response::Bounce(_)), Some(r @ Response::Merge)) => response = Some(r),
+                _ => {}
+            }
+            None::<()>
+        });
+        response
+    }
+
+    pub fn act(&mut self, i: usize, body: &mut Body, step: usize, dt: f64, rng: &mut SimulatorRng) {
+        self.with_context(step, dt, rng, None, |a, context| { a.act(i, body, context); None::<()> });
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.iter().all(|e| e.is_none())
+    }
+
+    pub fn reorder(&mut self, order: &[usize]) {
+        for a in self.attributes_mut() {
+            a.reorder(order);
+        }
+    }
+
+    pub fn push(&mut self) {
+        for a in self.attributes_mut() {
+            a.push();
         }
-        else if self.t[i] == 1.0 && self.t[j] == 0.5 {
-            self.t[j] = 1.0;
+    }
+
+    pub fn swap_remove(&mut self, i: usize) {
+        for a in self.attributes_mut() {
+            a.swap_remove(i);
         }
-        else if self.t[j] == 1.0 && self.t[i] == 0.0 {
-            self.t[i] = 0.5;
+    }
+}
+
+/// State of a particle in virus_attr. A healthy particle hit by an infected one
+/// is exposed, and infected the next time it is hit.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Infection {
+    Healthy,
+    Exposed,
+    Infected
+}
+
+impl Default for Infection {
+    fn default() -> Infection { Infection::Healthy }
+}
+
+impl State for Infection {
+    fn to_scalar(&self) -> f64 {
+        match *self {
+            Infection::Healthy => 0.0,
+            Infection::Exposed => 0.5,
+            Infection::Infected => 1.0
         }
-        else if self.t[j] == 1.0 && self.t[i] == 0.5 {
-            self.t[i] = 1.0;
+    }
+
+    fn from_scalar(f: f64) -> Infection {
+        if f >= 1.0 { Infection::Infected }
+        else if f > 0.0 { Infection::Exposed }
+        else { Infection::Healthy }
+    }
+}
+
+impl_collision_attribute! { virus_attr: Infection, self, i, j, p1, p2, 0.0, 1.0,
+    {
+        match (self.t[i], self.t[j]) {
+            (Infection::Infected, Infection::Healthy) => self.t[j] = Infection::Exposed,
+            (Infection::Infected, Infection::Exposed) => self.t[j] = Infection::Infected,
+            (Infection::Healthy, Infection::Infected) => self.t[i] = Infection::Exposed,
+            (Infection::Exposed, Infection::Infected) => self.t[i] = Infection::Infected,
+            _ => {}
         }
     }
 }
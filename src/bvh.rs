@@ -0,0 +1,476 @@
+use std::cmp;
+use std::usize;
+
+use collision::*;
+use vector::Vector;
+use common::{balanced_ranges, start_pool};
+use defaults::DEFAULT_NUMBER_OF_THREADS;
+use scoped_pool::Pool;
+
+const NULL: usize = usize::MAX;
+
+#[derive(Copy, Clone)]
+struct Node {
+    min: Vector,
+    max: Vector,
+    parent: usize,
+    left: usize,
+    right: usize,
+    // object index of a leaf
+    object: usize,
+    // leaves are 0
+    height: usize,
+}
+
+impl Node {
+    fn is_leaf(&self) -> bool {
+        self.left == NULL
+    }
+
+    fn contains(&self, min: Vector, max: Vector) -> bool {
+        self.min.x <= min.x && self.min.y <= min.y && self.max.x >= max.x && self.max.y >= max.y
+    }
+
+    fn overlaps(&self, min: Vector, max: Vector) -> bool {
+        self.min.x <= max.x && self.max.x >= min.x && self.min.y <= max.y && self.max.y >= min.y
+    }
+}
+
+fn union(a: &Node, b: &Node) -> (Vector, Vector) {
+    (Vector::new(a.min.x.min(b.min.x), a.min.y.min(b.min.y)), Vector::new(a.max.x.max(b.max.x), a.max.y.max(b.max.y)))
+}
+
+// the 2d equivalent of surface area, what the tree tries to keep small
+fn perimeter(min: Vector, max: Vector) -> f64 {
+    2.0 * (max.x - min.x + max.y - min.y)
+}
+
+// dynamic bounding volume hierarchy, each object is one leaf holding a box a margin
+// bigger than the object. The tree is kept between frames and a leaf is only moved
+// once its object leaves that box, so slowly moving objects cost almost nothing to
+// update. Leaves go where they grow the tree's perimeter the least and the tree is
+// rebalanced with rotations, radii may differ between objects, see insert_with_radius.
+// Each check starts from an empty list, a pair is only ever found once.
+pub struct Bvh {
+    radius: f64,
+    margin: f64,
+    nodes: Vec<Node>,
+    free: Vec<usize>,
+    root: usize,
+    // per object index
+    leaves: Vec<usize>,
+    positions: Vec<Vector>,
+    radii: Vec<f64>,
+    present: Vec<bool>,
+    collisions: Vec<Collision>,
+    pool: Option<Pool>,
+    number_of_threads: usize,
+}
+
+impl Bvh {
+    // radius is used for objects added with insert, the margin defaults to it
+    pub fn new(radius: f64) -> Bvh {
+        Bvh {
+            radius: radius,
+            margin: radius,
+            nodes: vec![],
+            free: vec![],
+            root: NULL,
+            leaves: vec![],
+            positions: vec![],
+            radii: vec![],
+            present: vec![],
+            collisions: Vec::with_capacity(10000),
+            pool: None,
+            number_of_threads: DEFAULT_NUMBER_OF_THREADS
+        }
+    }
+
+    // how far an object can move before its leaf has to be moved, bigger margins mean
+    // fewer updates but more boxes overlapping in the checks
+    pub fn margin(mut self, margin: f64) -> Self {
+        self.margin = margin;
+        self
+    }
+
+    // height of the tree, 0 for a single leaf
+    pub fn height(&self) -> usize {
+        if self.root == NULL { 0 } else { self.nodes[self.root].height }
+    }
+
+    fn allocate(&mut self, node: Node) -> usize {
+        match self.free.pop() {
+            Some(n) => { self.nodes[n] = node; n },
+            None => { self.nodes.push(node); self.nodes.len() - 1 }
+        }
+    }
+
+    fn bounds(&self, index: usize) -> (Vector, Vector) {
+        let r = Vector::new(self.radii[index], self.radii[index]);
+        (self.positions[index] - r, self.positions[index] + r)
+    }
+
+    // refits the boxes and heights from n up to the root, rebalancing on the way
+    fn refit(&mut self, mut n: usize) {
+        while n != NULL {
+            n = self.balance(n);
+
+            let (left, right) = (self.nodes[self.nodes[n].left], self.nodes[self.nodes[n].right]);
+            let (min, max) = union(&left, &right);
+            let node = &mut self.nodes[n];
+            node.min = min;
+            node.max = max;
+            node.height = 1 + cmp::max(left.height, right.height);
+
+            n = node.parent;
+        }
+    }
+
+    fn insert_leaf(&mut self, leaf: usize) {
+        if self.root == NULL {
+            self.root = leaf;
+            self.nodes[leaf].parent = NULL;
+            return;
+        }
+
+        // walks down to the sibling that makes the new parent cheapest
+        let new = self.nodes[leaf];
+        let mut n = self.root;
+        while !self.nodes[n].is_leaf() {
+            let node = self.nodes[n];
+            let (min, max) = union(&node, &new);
+            let combined = perimeter(min, max);
+
+            let cost = 2.0 * combined;
+            let inheritance = 2.0 * (combined - perimeter(node.min, node.max));
+
+            let child_cost = |c: &Node| {
+                let (min, max) = union(c, &new);
+                if c.is_leaf() { perimeter(min, max) + inheritance }
+                else { perimeter(min, max) - perimeter(c.min, c.max) + inheritance }
+            };
+            let left_cost = child_cost(&self.nodes[node.left]);
+            let right_cost = child_cost(&self.nodes[node.right]);
+
+            if cost < left_cost && cost < right_cost {
+                break;
+            }
+            n = if left_cost < right_cost { node.left } else { node.right };
+        }
+
+        let sibling = n;
+        let old_parent = self.nodes[sibling].parent;
+        let (min, max) = union(&self.nodes[sibling], &new);
+        let parent = self.allocate(Node {
+            min: min,
+            max: max,
+            parent: old_parent,
+            left: sibling,
+            right: leaf,
+            object: NULL,
+            height: self.nodes[sibling].height + 1
+        });
+
+        if old_parent == NULL {
+            self.root = parent;
+        }
+        else if self.nodes[old_parent].left == sibling {
+            self.nodes[old_parent].left = parent;
+        }
+        else {
+            self.nodes[old_parent].right = parent;
+        }
+        self.nodes[sibling].parent = parent;
+        self.nodes[leaf].parent = parent;
+
+        self.refit(parent);
+    }
+
+    fn remove_leaf(&mut self, leaf: usize) {
+        if leaf == self.root {
+            self.root = NULL;
+            return;
+        }
+
+        let parent = self.nodes[leaf].parent;
+        let grandparent = self.nodes[parent].parent;
+        let sibling = if self.nodes[parent].left == leaf { self.nodes[parent].right } else { self.nodes[parent].left };
+
+        self.free.push(parent);
+        self.nodes[sibling].parent = grandparent;
+
+        if grandparent == NULL {
+            self.root = sibling;
+        }
+        else {
+            if self.nodes[grandparent].left == parent {
+                self.nodes[grandparent].left = sibling;
+            }
+            else {
+                self.nodes[grandparent].right = sibling;
+            }
+            self.refit(grandparent);
+        }
+    }
+
+    // rotates the taller child of a up if the children's heights differ by more than one,
+    // returns the node now in a's place
+    fn balance(&mut self, a: usize) -> usize {
+        if self.nodes[a].is_leaf() || self.nodes[a].height < 2 {
+            return a;
+        }
+
+        let (b, c) = (self.nodes[a].left, self.nodes[a].right);
+        let difference = self.nodes[c].height as i64 - self.nodes[b].height as i64;
+
+        if difference > 1 {
+            self.rotate(a, c, b, false)
+        }
+        else if difference < -1 {
+            self.rotate(a, b, c, true)
+        }
+        else {
+            a
+        }
+    }
+
+    // moves child `up` of a into a's place, a keeps `other` and the shorter of up's children
+    fn rotate(&mut self, a: usize, up: usize, other: usize, up_is_left: bool) -> usize {
+        let (f, g) = (self.nodes[up].left, self.nodes[up].right);
+
+        // up takes a's place under a's parent
+        let parent = self.nodes[a].parent;
+        self.nodes[up].parent = parent;
+        self.nodes[a].parent = up;
+        if parent == NULL {
+            self.root = up;
+        }
+        else if self.nodes[parent].left == a {
+            self.nodes[parent].left = up;
+        }
+        else {
+            self.nodes[parent].right = up;
+        }
+
+        let (taller, shorter) = if self.nodes[f].height > self.nodes[g].height { (f, g) } else { (g, f) };
+
+        // a replaces `up` among its own children and keeps the shorter grandchild
+        self.nodes[up].left = a;
+        self.nodes[up].right = taller;
+        if up_is_left { self.nodes[a].left = shorter; } else { self.nodes[a].right = shorter; }
+        self.nodes[shorter].parent = a;
+
+        let (min, max) = union(&self.nodes[other], &self.nodes[shorter]);
+        let height = 1 + cmp::max(self.nodes[other].height, self.nodes[shorter].height);
+        {
+            let node = &mut self.nodes[a];
+            node.min = min;
+            node.max = max;
+            node.height = height;
+        }
+
+        let (min, max) = union(&self.nodes[a], &self.nodes[taller]);
+        let height = 1 + cmp::max(self.nodes[a].height, self.nodes[taller].height);
+        let node = &mut self.nodes[up];
+        node.min = min;
+        node.max = max;
+        node.height = height;
+
+        up
+    }
+
+    // brings the tree up to date with the objects inserted since the last check
+    fn update(&mut self) {
+        for index in 0..self.positions.len() {
+            let leaf = self.leaves[index];
+
+            if !self.present[index] {
+                if leaf != NULL {
+                    self.remove_leaf(leaf);
+                    self.free.push(leaf);
+                    self.leaves[index] = NULL;
+                }
+                continue;
+            }
+
+            let (min, max) = self.bounds(index);
+            if leaf != NULL {
+                if self.nodes[leaf].contains(min, max) {
+                    continue;
+                }
+                self.remove_leaf(leaf);
+                self.free.push(leaf);
+            }
+
+            let margin = Vector::new(self.margin, self.margin);
+            let leaf = self.allocate(Node {
+                min: min - margin,
+                max: max + margin,
+                parent: NULL,
+                left: NULL,
+                right: NULL,
+                object: index,
+                height: 0
+            });
+            self.insert_leaf(leaf);
+            self.leaves[index] = leaf;
+        }
+    }
+
+    // checks object index1 against every object with a bigger index, stack is reused between calls
+    fn check_object(&self, index1: usize, stack: &mut Vec<usize>, collisions: &mut Vec<Collision>, mut comparisons: Option<&mut Vec<(usize, usize)>>) {
+        let (min, max) = self.bounds(index1);
+        let p_position = self.positions[index1];
+        let p_radius = self.radii[index1];
+
+        stack.clear();
+        stack.push(self.root);
+        while let Some(n) = stack.pop() {
+            let node = &self.nodes[n];
+            if !node.overlaps(min, max) {
+                continue;
+            }
+
+            if !node.is_leaf() {
+                stack.push(node.left);
+                stack.push(node.right);
+                continue;
+            }
+
+            let index2 = node.object;
+            if index2 <= index1 {
+                continue;
+            }
+            let q_position = self.positions[index2];
+
+            let normal = (q_position - p_position).normalise();
+            let penetration = p_radius + self.radii[index2] - p_position.distance( q_position );
+
+            if let Some(ref mut comparisons) = comparisons { comparisons.push((index1, index2)); }
+
+            // if circles are overlapping
+            if penetration > 0.0 {
+                // add collision
+                collisions.push( Collision::new(index1, index2, penetration, normal) );
+            }
+        }
+    }
+}
+
+impl SpatialPartition for Bvh {
+    fn insert(&mut self, index: usize, v: Vector) {
+        let radius = self.radius;
+        self.insert_with_radius(index, v, radius);
+    }
+
+    // the tree itself is only updated at the next collision check
+    fn insert_with_radius(&mut self, index: usize, v: Vector, radius: f64) {
+        if index >= self.positions.len() {
+            self.positions.resize(index + 1, Vector::zero());
+            self.radii.resize(index + 1, 0.0);
+            self.present.resize(index + 1, false);
+            self.leaves.resize(index + 1, NULL);
+        }
+
+        self.positions[index] = v;
+        self.radii[index] = radius;
+        self.present[index] = true;
+    }
+
+    // keeps the tree for the next frame, objects not inserted again are removed at the next check
+    fn clear(&mut self) {
+        self.collisions.clear();
+        for p in &mut self.present {
+            *p = false;
+        }
+    }
+
+    fn collision_check(&mut self) -> &Vec<Collision> {
+        self.update();
+
+        let mut collisions = Vec::new();
+        if self.root != NULL {
+            let mut stack = Vec::new();
+            for index in 0..self.positions.len() {
+                if self.present[index] {
+                    self.check_object(index, &mut stack, &mut collisions, None);
+                }
+            }
+        }
+
+        self.collisions = collisions;
+        self.collisions.sort();
+
+        &self.collisions
+    }
+
+    // every object is a separate query, they are split into ranges and appended in order
+    fn collision_check_parallel(&mut self) -> &Vec<Collision> {
+        self.update();
+
+        let costs = if self.root == NULL { vec![] } else { self.present.iter().map(|&p| if p { 1 } else { 0 }).collect() };
+        let ranges = balanced_ranges(&costs, self.number_of_threads * TASKS_PER_THREAD);
+        let mut results = vec![Vec::new(); ranges.len()];
+
+        start_pool(&mut self.pool, self.number_of_threads);
+        if let Some(ref p) = self.pool {
+            let bvh = &*self;
+            p.scoped(|scoped| {
+                for (result, &(start, end)) in results.iter_mut().zip( ranges.iter() ) {
+                    scoped.execute(move || {
+                        let mut stack = Vec::new();
+                        for index in start..end {
+                            if bvh.present[index] {
+                                bvh.check_object(index, &mut stack, result, None);
+                            }
+                        }
+                    });
+                }
+            });
+        }
+
+        self.collisions.clear();
+        for mut result in results {
+            self.collisions.append( &mut result );
+        }
+
+        self.collisions.sort();
+
+        &self.collisions
+    }
+
+    fn collision_check_with_comparisons(&mut self) -> (&Vec<Collision>, Vec<(usize, usize)>) {
+        self.update();
+
+        let mut collisions = Vec::new();
+        let mut comparisons = Vec::new();
+        if self.root != NULL {
+            let mut stack = Vec::new();
+            for index in 0..self.positions.len() {
+                if self.present[index] {
+                    self.check_object(index, &mut stack, &mut collisions, Some(&mut comparisons));
+                }
+            }
+        }
+
+        self.collisions = collisions;
+        self.collisions.sort();
+
+        (&self.collisions, comparisons)
+    }
+
+    fn set_number_of_threads(&mut self, number_of_threads: usize) {
+        let number_of_threads = cmp::max(number_of_threads, 1);
+        // the new pool is started by the next parallel check
+        if let Some(ref pool) = self.pool { pool.shutdown(); }
+        self.pool = None;
+        self.number_of_threads = number_of_threads;
+    }
+}
+
+// the pool's threads would otherwise outlive it
+impl Drop for Bvh {
+    fn drop(&mut self) {
+        if let Some(ref pool) = self.pool { pool.shutdown(); }
+    }
+}
@@ -0,0 +1,207 @@
+use std::cmp;
+use std::mem;
+
+use collision::*;
+use vector::Vector;
+use common::{balanced_ranges, start_pool};
+use defaults::DEFAULT_NUMBER_OF_THREADS;
+use config::{Config, partition_geometry};
+use scoped_pool::Pool;
+
+// cells a cell is checked against, half of its neighbours so every neighbouring
+// pair of cells is only visited once
+const STENCIL: [(i32, i32); 4] = [(0, 1), (1, -1), (1, 0), (1, 1)];
+
+// grid where each object is stored in the one cell owning its centre, cells are at
+// least a diameter wide so colliding objects are always in the same or neighbouring
+// cells. Unlike SpatialHash no pair is found twice, so there is nothing to sort or dedup,
+// the collisions come out in cell order. Each check starts from an empty list, since
+// nothing would remove a pair found twice.
+pub struct CellList {
+    pub cells: Vec<Vec<(usize, Vector)>>,
+    pub number_of_columns: usize,
+    pub number_of_rows: usize,
+    cell_width: f64,
+    cell_height: f64,
+    radius: f64,
+    collisions: Vec<Collision>,
+    pool: Option<Pool>,
+    number_of_threads: usize,
+}
+
+impl CellList {
+    // None if the cells would be narrower than a particle
+    pub fn new(width: f64, height: f64, number_of_columns: usize, number_of_rows: usize, radius: f64) -> Option<CellList> {
+        let (max_number_of_columns, max_number_of_rows) = CellList::maximum_grid(width, height, radius);
+
+        if number_of_rows == 0 || number_of_columns == 0 || number_of_rows > max_number_of_rows || number_of_columns > max_number_of_columns {
+            None
+        }
+        else {
+            Some(CellList {
+                cells: vec![vec![]; number_of_columns*number_of_rows],
+                number_of_columns: number_of_columns,
+                number_of_rows: number_of_rows,
+                cell_width: width / number_of_columns as f64,
+                cell_height: height / number_of_rows as f64,
+                radius: radius,
+                collisions: Vec::with_capacity(10000),
+                pool: None,
+                number_of_threads: DEFAULT_NUMBER_OF_THREADS
+            })
+        }
+    }
+
+    // finest grid whose cells are still a diameter wide, (columns, rows)
+    pub fn maximum_grid(width: f64, height: f64, radius: f64) -> (usize, usize) {
+        ((width / (2.0*radius)) as usize, (height / (2.0*radius)) as usize)
+    }
+
+    // the [cell_list] rows and columns are within maximum_grid
+    pub fn validate_config(config: &Config) -> Vec<(&'static str, usize, String)> {
+        let mut errors = vec![];
+        if let (Some(c), Some((width, height, radius))) = (config.cell_list.as_ref(), partition_geometry(config)) {
+            let (max_number_of_columns, max_number_of_rows) = CellList::maximum_grid(width, height, radius);
+
+            if let Some(rows) = c.number_of_rows {
+                if rows == 0 || rows > max_number_of_rows {
+                    errors.push( ("cell_list.number_of_rows", 0,
+                        format!("`cell_list.number_of_rows` must be between 1 and {} for radius {} and height {}, got {}", max_number_of_rows, radius, height, rows)) );
+                }
+            }
+            if let Some(columns) = c.number_of_columns {
+                if columns == 0 || columns > max_number_of_columns {
+                    errors.push( ("cell_list.number_of_columns", 0,
+                        format!("`cell_list.number_of_columns` must be between 1 and {} for radius {} and width {}, got {}", max_number_of_columns, radius, width, columns)) );
+                }
+            }
+        }
+        errors
+    }
+
+    // objects outside the box are clamped into the border cells
+    fn in_cell(&self, v: Vector) -> (i32, i32) {
+        let c = cmp::max(cmp::min((v.x / self.cell_width) as i32, self.number_of_columns as i32 - 1), 0);
+        let r = cmp::max(cmp::min((v.y / self.cell_height) as i32, self.number_of_rows as i32 - 1), 0);
+
+        (r, c)
+    }
+
+    fn check_pair(&self, index1: usize, p_position: Vector, index2: usize, q_position: Vector, collisions: &mut Vec<Collision>) {
+        let normal = (q_position - p_position).normalise();
+        let penetration = 2.0*self.radius - p_position.distance( q_position );
+
+        // if circles are overlapping
+        if penetration > 0.0 {
+            // add collision
+            collisions.push( Collision::new(index1, index2, penetration, normal) );
+        }
+    }
+
+    // checks cells [start, end) against themselves and their half of the neighbours
+    fn check_collisions_in_cells(&self, start: usize, end: usize, collisions: &mut Vec<Collision>, mut comparisons: Option<&mut Vec<(usize, usize)>>) {
+        for cell_index in start..end {
+            let r = (cell_index / self.number_of_columns) as i32;
+            let c = (cell_index % self.number_of_columns) as i32;
+            let cell = &self.cells[cell_index];
+
+            for i in 0..cell.len() {
+                let (index1, p_position) = cell[i];
+
+                for j in (i+1)..cell.len() {
+                    let (index2, q_position) = cell[j];
+                    if let Some(ref mut comparisons) = comparisons { comparisons.push((index1, index2)); }
+                    self.check_pair(index1, p_position, index2, q_position, collisions);
+                }
+            }
+
+            for &(dr, dc) in &STENCIL {
+                let (nr, nc) = (r + dr, c + dc);
+                if nr < 0 || nc < 0 || nr >= self.number_of_rows as i32 || nc >= self.number_of_columns as i32 {
+                    continue;
+                }
+                let neighbour = &self.cells[nr as usize * self.number_of_columns + nc as usize];
+
+                for &(index1, p_position) in cell {
+                    for &(index2, q_position) in neighbour {
+                        if let Some(ref mut comparisons) = comparisons { comparisons.push((index1, index2)); }
+                        self.check_pair(index1, p_position, index2, q_position, collisions);
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl SpatialPartition for CellList {
+    fn insert(&mut self, index: usize, v: Vector) {
+        let (r, c) = self.in_cell(v);
+        let cell_index = r as usize * self.number_of_columns + c as usize;
+        self.cells[cell_index].push((index, v));
+    }
+
+    fn clear(&mut self) {
+        self.collisions.clear();
+        for c in &mut self.cells {
+            c.clear();
+        }
+    }
+
+    fn collision_check(&mut self) -> &Vec<Collision> {
+        let mut collisions = mem::replace(&mut self.collisions, Vec::new());
+        collisions.clear();
+        self.check_collisions_in_cells(0, self.cells.len(), &mut collisions, None);
+        self.collisions = collisions;
+
+        &self.collisions
+    }
+
+    // ranges of cells with about the same number of pairs to check, appended in cell order
+    fn collision_check_parallel(&mut self) -> &Vec<Collision> {
+        let costs = self.cells.iter().map(|c| c.len() * c.len() * (STENCIL.len() + 1)).collect();
+        let ranges = balanced_ranges(&costs, self.number_of_threads * TASKS_PER_THREAD);
+        let mut results = vec![Vec::new(); ranges.len()];
+        self.collisions.clear();
+
+        start_pool(&mut self.pool, self.number_of_threads);
+        if let Some(ref p) = self.pool {
+            let cell_list = &*self;
+            p.scoped(|scoped| {
+                for (result, &(start, end)) in results.iter_mut().zip( ranges.iter() ) {
+                    scoped.execute(move || { cell_list.check_collisions_in_cells(start, end, result, None) });
+                }
+            });
+        }
+
+        for mut result in results {
+            self.collisions.append( &mut result );
+        }
+
+        &self.collisions
+    }
+
+    fn collision_check_with_comparisons(&mut self) -> (&Vec<Collision>, Vec<(usize, usize)>) {
+        let mut collisions = mem::replace(&mut self.collisions, Vec::new());
+        collisions.clear();
+        let mut comparisons = Vec::new();
+        self.check_collisions_in_cells(0, self.cells.len(), &mut collisions, Some(&mut comparisons));
+        self.collisions = collisions;
+
+        (&self.collisions, comparisons)
+    }
+
+    fn set_number_of_threads(&mut self, number_of_threads: usize) {
+        let number_of_threads = cmp::max(number_of_threads, 1);
+        // the new pool is started by the next parallel check
+        if let Some(ref pool) = self.pool { pool.shutdown(); }
+        self.pool = None;
+        self.number_of_threads = number_of_threads;
+    }
+}
+
+// the pool's threads would otherwise outlive it
+impl Drop for CellList {
+    fn drop(&mut self) {
+        if let Some(ref pool) = self.pool { pool.shutdown(); }
+    }
+}
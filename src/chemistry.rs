@@ -0,0 +1,300 @@
+use std::any::Any;
+
+use rand::Rng;
+
+use particle::Particle;
+use attribute::{Attribute, State, Context, Response, Body};
+use common::scale;
+use config::*;
+
+/// What the reactants of a reaction become. With one product the pair merges into
+/// a single particle, see `Response::Merge`, with two each reactant changes species
+/// in place and they bounce as usual, the first reactant becoming the first product.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Products {
+    One(usize),
+    Two(usize, usize)
+}
+
+/// Species are indices into `Chemistry::species`. A collision between the two
+/// reactants, in either order, reacts if the energy of the impact along the
+/// normal, `0.5 * reduced_mass * impact_speed^2`, is at least `activation_energy`.
+/// Those collisions are a fraction exp(-Ea/kT) of all of them in a gas at
+/// temperature T, so the rate follows the Arrhenius law.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Reaction {
+    pub reactants: (usize, usize),
+    pub products: Products,
+    pub activation_energy: f64,
+}
+
+impl Reaction {
+    pub fn new(reactants: (usize, usize), products: Products) -> Reaction {
+        Reaction {
+            reactants: reactants,
+            products: products,
+            activation_energy: 0.0
+        }
+    }
+
+    pub fn activation_energy(mut self, activation_energy: f64) -> Self { self.activation_energy = activation_energy; self }
+}
+
+/// A particle's species. `merged` is set on a particle made by merging until its
+/// mass has been set, see `Chemistry::product_mass`.
+#[derive(Copy, Clone, Default, Debug, PartialEq)]
+pub struct Molecule {
+    pub species: usize,
+    pub merged: bool,
+}
+
+// the species index
+impl State for Molecule {
+    fn to_scalar(&self) -> f64 {
+        self.species as f64
+    }
+
+    fn from_scalar(f: f64) -> Molecule {
+        Molecule { species: f.max(0.0).round() as usize, merged: false }
+    }
+}
+
+/// Number of particles of each species at `time`, and how many times each reaction
+/// happened in the step that ended then.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Sample {
+    pub time: f64,
+    pub counts: Vec<usize>,
+    pub reactions: Vec<usize>,
+}
+
+/// Particles of several species reacting on collision. Every particle starts as
+/// species 0, `mix` or `set` change that. A sample is recorded every step in
+/// `history`.
+///
+/// Merging conserves momentum and mass. When `product_mass` is set a merged
+/// particle is given that mass instead before it next moves, its velocity scaled
+/// to keep its momentum.
+pub struct Chemistry {
+    pub species: Vec<String>,
+    pub reactions: Vec<Reaction>,
+    pub product_mass: Option<f64>,
+    molecules: Vec<Molecule>,
+    history: Vec<Sample>,
+    // reactions in the current step
+    events: Vec<usize>,
+    // the pair and reaction collision_response decided on, for collision_update
+    pending: Option<(usize, usize, usize)>,
+}
+
+impl Chemistry {
+    // for a bound attribute, expects a config that has been through read_config
+    pub fn configure<R: Rng>(&mut self, rng: &mut R, config: &Config) {
+        if let Some(ref c) = config.chemistry {
+            for name in c.species.as_ref().unwrap() {
+                self.add_species(name);
+            }
+            for r in c.reactions.iter().flat_map(|reactions| reactions.iter()) {
+                let id = |name: &String| self.species_id(name).unwrap();
+                let (reactants, products) = (r.reactants.as_ref().unwrap(), r.products.as_ref().unwrap());
+                let reactants = (id(&reactants[0]), id(&reactants[1]));
+                let products: Vec<usize> = products.iter().map(id).collect();
+                let products = if products.len() == 1 { Products::One(products[0]) } else { Products::Two(products[0], products[1]) };
+                self.add_reaction( Reaction::new(reactants, products).activation_energy(r.activation_energy.unwrap_or(DEFAULT_ACTIVATION_ENERGY)) );
+            }
+            if !c.conserve_mass.unwrap_or(DEFAULT_CONSERVE_MASS) {
+                self.product_mass = Some(1.0);
+            }
+            if let Some(ref fractions) = c.fractions {
+                self.mix(rng, fractions);
+            }
+        }
+    }
+
+    // returns the species' index, the existing one if the name is taken
+    pub fn add_species(&mut self, name: &str) -> usize {
+        match self.species_id(name) {
+            Some(id) => id,
+            None => {
+                self.species.push(name.to_string());
+                self.species.len() - 1
+            }
+        }
+    }
+
+    pub fn species_id(&self, name: &str) -> Option<usize> {
+        self.species.iter().position(|s| s == name)
+    }
+
+    pub fn add_reaction(&mut self, reaction: Reaction) {
+        self.reactions.push(reaction);
+        self.events.push(0);
+    }
+
+    pub fn data(&self) -> &Vec<Molecule> {
+        &self.molecules
+    }
+
+    pub fn data_mut(&mut self) -> &mut Vec<Molecule> {
+        &mut self.molecules
+    }
+
+    pub fn history(&self) -> &[Sample] {
+        &self.history
+    }
+
+    pub fn counts(&self) -> Vec<usize> {
+        let mut counts = vec![0; self.species.len()];
+        for m in &self.molecules {
+            if m.species < counts.len() {
+                counts[m.species] += 1;
+            }
+        }
+        counts
+    }
+
+    // reactions per unit time over the last `steps` samples, 0 with no samples
+    pub fn rate(&self, reaction: usize, steps: usize) -> f64 {
+        let samples = &self.history[self.history.len() - steps.min(self.history.len())..];
+        if samples.len() < 2 {
+            return 0.0;
+        }
+        let events: usize = samples[1..].iter().map(|s| s.reactions[reaction]).sum();
+        events as f64 / (samples[samples.len() - 1].time - samples[0].time)
+    }
+
+    // gives each particle a species at random, in proportion to fractions
+    pub fn mix<R: Rng>(&mut self, rng: &mut R, fractions: &[f64]) {
+        let total: f64 = fractions.iter().sum();
+        for m in &mut self.molecules {
+            let mut r = rng.gen::<f64>() * total;
+            let mut species = fractions.len() - 1;
+            for (k, &f) in fractions.iter().enumerate() {
+                if r < f {
+                    species = k;
+                    break;
+                }
+                r -= f;
+            }
+            *m = Molecule { species: species, merged: false };
+        }
+    }
+
+    // the reaction the pair undergoes and whether they are the other way round to its
+    // reactants, the first that matches
+    fn reaction(&self, i: usize, j: usize, energy: f64) -> Option<(usize, bool)> {
+        let (a, b) = (self.molecules[i].species, self.molecules[j].species);
+        self.reactions.iter().enumerate()
+            .filter(|&(_, r)| energy >= r.activation_energy)
+            .filter_map(|(k, r)| {
+                if r.reactants == (a, b) { Some((k, false)) }
+                else if r.reactants == (b, a) { Some((k, true)) }
+                else { None }
+            })
+            .next()
+    }
+}
+
+impl Attribute for Chemistry {
+    fn new() -> Chemistry {
+        Chemistry {
+            species: vec![],
+            reactions: vec![],
+            product_mass: None,
+            molecules: vec![],
+            history: vec![],
+            events: vec![],
+            pending: None
+        }
+    }
+
+    fn name() -> &'static str { "chemistry" }
+    fn as_any(&self) -> &Any { self }
+    fn as_any_mut(&mut self) -> &mut Any { self }
+    fn scalars(&self) -> Vec<f64> { self.molecules.iter().map(|m| m.to_scalar()).collect() }
+    fn collision_listener(&self) -> bool { true }
+
+    fn data_bounds(&self) -> (f64, f64) {
+        (0.0, self.species.len().saturating_sub(1) as f64)
+    }
+
+    // only particles that are closing can react
+    fn collision_response(&mut self, i: usize, j: usize, context: &mut Context) -> Option<Response> {
+        self.pending = None;
+        let impact = match context.impact {
+            Some(impact) if impact.impact_speed > 0.0 => impact,
+            _ => return None
+        };
+
+        let energy = 0.5 * impact.reduced_mass * impact.impact_speed * impact.impact_speed;
+        match self.reaction(i, j, energy) {
+            Some((k, swapped)) => {
+                self.pending = Some( if swapped { (j, i, k) } else { (i, j, k) } );
+                match self.reactions[k].products {
+                    Products::One(_) => Some(Response::Merge),
+                    Products::Two(..) => None
+                }
+            }
+            None => None
+        }
+    }
+
+    fn collision_update(&mut self, i: usize, j: usize, _: &Particle, _: &Particle, _: &mut Context) {
+        let (a, b, k) = match self.pending.take() {
+            Some((a, b, k)) if (a, b) == (i, j) || (a, b) == (j, i) => (a, b, k),
+            _ => return
+        };
+
+        match self.reactions[k].products {
+            // the first of the pair is the one that is kept
+            Products::One(c) => self.molecules[i] = Molecule { species: c, merged: true },
+            Products::Two(c, d) => {
+                self.molecules[a].species = c;
+                self.molecules[b].species = d;
+            }
+        }
+        self.events[k] += 1;
+    }
+
+    fn act(&mut self, i: usize, body: &mut Body, _: &mut Context) {
+        if !self.molecules[i].merged {
+            return;
+        }
+        self.molecules[i].merged = false;
+
+        if let Some(mass) = self.product_mass {
+            body.velocity = body.velocity * (body.mass / mass);
+            body.mass = mass;
+        }
+    }
+
+    fn step(&mut self, context: &mut Context) {
+        let sample = Sample { time: context.time, counts: self.counts(), reactions: self.events.clone() };
+        self.history.push(sample);
+        for e in &mut self.events {
+            *e = 0;
+        }
+        self.pending = None;
+    }
+
+    fn initialise(&mut self, number_of_particles: usize) {
+        self.molecules = vec![Molecule::default(); number_of_particles];
+    }
+
+    fn set(&mut self, f: f64, i: f64) {
+        let l = self.molecules.len() as f64;
+        self.molecules[scale(i, [0.0, 1.0], [0.0, l - 1.0]) as usize] = Molecule::from_scalar(f);
+    }
+
+    fn reorder(&mut self, order: &[usize]) {
+        self.molecules = order.iter().map(|&k| self.molecules[k]).collect();
+    }
+
+    fn push(&mut self) {
+        self.molecules.push(Molecule::default());
+    }
+
+    fn swap_remove(&mut self, i: usize) {
+        self.molecules.swap_remove(i);
+    }
+}
@@ -14,14 +14,15 @@ pub struct Collision {
 impl Collision {
     pub fn new(p1: usize, p2: usize, penetration: f64, normal: Vector) -> Collision {
         
-        let (i, j) = if p2 < p1 { (p2, p1) }
-                     else { (p1, p2) };
+        // the normal always points from p1 to p2
+        let (i, j, n) = if p2 < p1 { (p2, p1, -normal) }
+                        else { (p1, p2, normal) };
         
         Collision {
             p1: i,
             p2: j,
             penetration: penetration,
-            normal: normal
+            normal: n
         }
     }
 }
@@ -52,16 +53,38 @@ impl PartialOrd for Collision {
     }
 }
 
+// parallel checks hand the pool this many tasks per thread, idle threads take the
+// next task off the pool's queue so uneven tasks don't leave threads waiting
+pub const TASKS_PER_THREAD: usize = 8;
+
+/// Collision checks return each colliding pair once, in an order that only
+/// depends on what was inserted and not on how the work was split between
+/// threads. Most partitions sort by `(p1, p2)`, `CellList` returns them in
+/// cell order. A pair is computed from the same inserted positions whichever
+/// cell or node finds it.
 pub trait SpatialPartition {
     fn insert(&mut self, index: usize, position: Vector);
+    // for objects of different sizes, partitions built for a single radius ignore it
+    fn insert_with_radius(&mut self, index: usize, position: Vector, _: f64) { self.insert(index, position) }
     fn clear(&mut self);
     fn collision_check(&mut self) -> &Vec<Collision>;
     fn collision_check_parallel(&mut self) -> &Vec<Collision>;
     fn collision_check_with_comparisons(&mut self) -> (&Vec<Collision>, Vec<(usize, usize)>);
+    fn set_number_of_threads(&mut self, _: usize) {  }
 }
 
+// penetration of the circle at (x, y) with each of the circles at xs, ys, written to out.
+// Uses the same arithmetic as Vector::distance so results match the other partitions
+// exactly, and has no branches so the loop vectorises
+pub fn penetrations(x: f64, y: f64, xs: &[f64], ys: &[f64], diameter: f64, out: &mut Vec<f64>) {
+    out.clear();
+    out.extend( xs.iter().zip( ys.iter() ).map(|(&qx, &qy)| {
+        diameter - ( ((x - qx) * (x - qx)) + ((y - qy) * (y - qy)) ).sqrt()
+    }) );
+}
 
-fn naive_collision_check(radius: f64, particles: &Vec<Particle>) -> Vec<Collision> {
+// every pair checked, what the partitions are tested against, already sorted
+pub fn naive_collision_check(radius: f64, particles: &Vec<Particle>) -> Vec<Collision> {
     let mut collisions = Vec::new();
 
     for i in 0..particles.len() {
@@ -2,6 +2,8 @@ use std::io::prelude::*;
 use std::fs::File;
 use std::cmp;
 
+use scoped_pool::Pool;
+
 
 // [start1, end1] => [start2, end2]
 // [start1 - start1, end1 - start1 ] => [start2, end2]
@@ -114,3 +116,35 @@ pub fn read_file(file_name: &str) -> Option<String> {
     }
     r
 }
+
+// partitions start their pool the first time a parallel check needs it, so one that
+// is never checked in parallel doesn't keep idle threads
+pub fn start_pool(pool: &mut Option<Pool>, number_of_threads: usize) {
+    if pool.is_none() {
+        *pool = Some(Pool::new(number_of_threads));
+    }
+}
+
+// splits items into about number_of_tasks contiguous [start, end) ranges of roughly equal total cost
+pub fn balanced_ranges(costs: &Vec<usize>, number_of_tasks: usize) -> Vec<(usize, usize)> {
+    let total = costs.iter().fold(0, |sum, c| sum + c);
+    let target = cmp::max(total / cmp::max(number_of_tasks, 1), 1);
+
+    let mut ranges = Vec::with_capacity(number_of_tasks);
+    let mut start = 0;
+    let mut cost = 0;
+
+    for (i, c) in costs.iter().enumerate() {
+        cost += *c;
+        if cost >= target {
+            ranges.push((start, i + 1));
+            start = i + 1;
+            cost = 0;
+        }
+    }
+    if start < costs.len() {
+        ranges.push((start, costs.len()));
+    }
+
+    ranges
+}
@@ -7,10 +7,43 @@
 
 extern crate toml;
 
+use std::f64::consts::PI;
+use std::fmt;
+
+use rustc_serialize::Decodable;
+use self::toml::{Parser, Decoder, Value, Table};
+
 use common::read_file;
 
+// the defaults are kept in their own module so the modules that use them don't need this one
+pub use defaults::*;
+
+// valid values of `collisions`
+pub const COLLISIONS: &'static [&'static str] = &["naive", "spatial_hash", "quadtree", "sweep_and_prune", "cell_list", "loose_quadtree", "bvh"];
+
+// valid values of `flow.inflow` and `flow.outflow`
+pub const WALLS: &'static [&'static str] = &["left", "right", "bottom", "top"];
+
+// valid values of `reordering.curve`
+pub const CURVES: &'static [&'static str] = &["morton", "hilbert"];
+
+// valid values of `initial_conditions.placement` and `initial_conditions.velocities`
+pub const PLACEMENTS: &'static [&'static str] = &["random", "non_overlapping", "square_lattice", "hexagonal_lattice"];
+pub const VELOCITY_DISTRIBUTIONS: &'static [&'static str] = &["uniform", "maxwellian", "monoenergetic"];
+
+// names the [epidemic], [heat] and [chemistry] attributes are bound under, which
+// `[[attributes]]` can't use
+pub const BUILT_IN_ATTRIBUTES: &'static [&'static str] = &["epidemic", "heat", "chemistry"];
+
+// densest packing of equal circles in the plane (hexagonal)
+const MAXIMUM_PACKING_DENSITY: f64 = 0.9069;
+
 /// This is what we're going to decode into. Each field is optional, meaning
 /// that it doesn't have to be present in TOML.
+///
+/// `number_of_particles`, `dt`, `radius`, `width` and `height` are required,
+/// `read_config` fills in the `DEFAULT_*` value for any other top level field
+/// except `seed`, which is picked at random by the caller when left out.
 #[derive(Debug, RustcDecodable)]
 pub struct Config {
     pub number_of_particles: Option<usize>,
@@ -24,18 +57,667 @@ pub struct Config {
     pub density_number_of_rows: Option<usize>,
     pub density_number_of_columns: Option<usize>,
     pub collisions: Option<String>,
-    pub spatial_hash: Option<SpatialHashConfig>
+    pub seed: Option<u64>,
+    pub number_of_threads: Option<usize>,
+    pub print_statistics: Option<bool>,
+    pub spatial_hash: Option<SpatialHashConfig>,
+    pub cell_list: Option<CellListConfig>,
+    pub quadtree: Option<QuadtreeConfig>,
+    pub neighbour_list: Option<NeighbourListConfig>,
+    pub reordering: Option<ReorderingConfig>,
+    pub flow: Option<FlowConfig>,
+    pub epidemic: Option<EpidemicConfig>,
+    pub heat: Option<HeatConfig>,
+    pub chemistry: Option<ChemistryConfig>,
+    pub attributes: Option<Vec<AttributeConfig>>,
+    pub initial_conditions: Option<InitialConditionsConfig>
 }
 
+/// Rows and columns left out are sized from the density of particles,
+/// `auto_tune` keeps resizing the grid while the simulation runs.
 #[derive(Debug, RustcDecodable)]
 pub struct SpatialHashConfig {
     pub number_of_rows: Option<usize>,
     pub number_of_columns: Option<usize>,
+    pub auto_tune: Option<bool>,
+}
+
+/// Defaults to the finest grid whose cells are a diameter wide.
+#[derive(Debug, RustcDecodable)]
+pub struct CellListConfig {
+    pub number_of_rows: Option<usize>,
+    pub number_of_columns: Option<usize>,
+}
+
+#[derive(Debug, RustcDecodable)]
+pub struct QuadtreeConfig {
+    pub object_limit: Option<usize>,
+    pub merge_limit: Option<usize>,
+    pub maximum_depth: Option<usize>,
+    pub maximum_objects_per_volume: Option<f64>,
+}
+
+/// When the section is present the chosen partition is wrapped in a
+/// `NeighbourList` caching pairs within `2*radius + skin`.
+#[derive(Debug, RustcDecodable)]
+pub struct NeighbourListConfig {
+    pub skin: Option<f64>,
+}
+
+/// When the section is present the particles are sorted along `curve` every
+/// `period` steps, `read_config` fills in the defaults.
+#[derive(Debug, RustcDecodable)]
+pub struct ReorderingConfig {
+    pub curve: Option<String>,
+    pub period: Option<usize>,
+}
+
+/// Opens the box up: `inflow` is a wall particles are fed in through at `density`
+/// particles per unit area, `outflow` walls remove the particles reaching them and
+/// `obstacles` are `[x, y, radius]` discs, see `flow::Inflow` and `flow::Obstacle`.
+/// `density` is required with `inflow`.
+#[derive(Debug, RustcDecodable)]
+pub struct FlowConfig {
+    pub inflow: Option<String>,
+    pub density: Option<f64>,
+    pub temperature: Option<f64>,
+    pub drift: Option<Vec<f64>>,
+    pub depth: Option<f64>,
+    pub outflow: Option<Vec<String>>,
+    pub obstacles: Option<Vec<Vec<f64>>>,
+}
+
+/// An SEIR epidemic spread by collisions, see `epidemic::Epidemic`. `infected`
+/// particles start infected and a fraction `vaccinated` of the rest can't be,
+/// times are in simulation time, the same units as `dt`.
+#[derive(Debug, RustcDecodable)]
+pub struct EpidemicConfig {
+    pub transmission: Option<f64>,
+    pub incubation_time: Option<f64>,
+    pub recovery_time: Option<f64>,
+    pub infected: Option<usize>,
+    pub vaccinated: Option<f64>,
+}
+
+/// Internal energy exchanged on collision, see `heat::Heat`. Every particle starts
+/// with `internal_energy`, and `restitution` applies to collisions between particles
+/// where the top level one applies to the walls.
+#[derive(Debug, RustcDecodable)]
+pub struct HeatConfig {
+    pub conductance: Option<f64>,
+    pub restitution: Option<f64>,
+    pub inelastic_heating: Option<bool>,
+    pub internal_energy: Option<f64>,
+}
+
+/// Species of particle and the reactions between them, see `chemistry::Chemistry`.
+/// `species` is required, particles are given one at random in proportion to
+/// `fractions`, or are all the first one when it is left out. Without
+/// `conserve_mass` every product of a merger has unit mass like the rest.
+#[derive(Debug, RustcDecodable)]
+pub struct ChemistryConfig {
+    pub species: Option<Vec<String>>,
+    pub fractions: Option<Vec<f64>>,
+    pub conserve_mass: Option<bool>,
+    pub reactions: Option<Vec<ReactionConfig>>,
+}
+
+/// A `[[chemistry.reactions]]` entry, two `reactants` becoming one or two `products`,
+/// all named in `chemistry.species`.
+#[derive(Debug, RustcDecodable)]
+pub struct ReactionConfig {
+    pub reactants: Option<Vec<String>>,
+    pub products: Option<Vec<String>>,
+    pub activation_energy: Option<f64>,
+}
+
+/// An `[[attributes]]` entry, an attribute bound under `name` that follows `rules`,
+/// see `rules::Rule` for how they are written. Particles are given one of `values`
+/// at random in proportion to `fractions`, equal shares when left out, and
+/// `bounds` is the range the values are coloured over.
+#[derive(Debug, RustcDecodable)]
+pub struct AttributeConfig {
+    pub name: Option<String>,
+    pub values: Option<Vec<f64>>,
+    pub fractions: Option<Vec<f64>>,
+    pub bounds: Option<Vec<f64>>,
+    pub rules: Option<Vec<String>>,
+}
+
+/// `region` is `[x_min, y_min, x_max, y_max]`, the whole box if left out.
+/// `speed` is the half-width of the uniform distribution or the speed of
+/// every particle for monoenergetic, `temperature` is used by maxwellian.
+#[derive(Debug, RustcDecodable)]
+pub struct InitialConditionsConfig {
+    pub placement: Option<String>,
+    pub region: Option<Vec<f64>>,
+    pub velocities: Option<String>,
+    pub speed: Option<f64>,
+    pub temperature: Option<f64>,
+    pub zero_momentum: Option<bool>,
+}
+
+/// A problem with the config, `line` is 1-based and `None` when the field
+/// isn't in the file at all.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConfigError {
+    pub line: Option<usize>,
+    pub message: String
+}
+
+impl ConfigError {
+    pub fn new(line: Option<usize>, message: String) -> ConfigError {
+        ConfigError {
+            line: line,
+            message: message
+        }
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "line {}: {}", line, self.message),
+            None => write!(f, "{}", self.message)
+        }
+    }
+}
+
+/// A check a module makes of its own section of the config, returning the dotted
+/// field, the entry of the array of tables it is in (0 for a plain table) and the
+/// message of each problem. `settings::parse_config` runs those of every module,
+/// so that this one doesn't need to know about them.
+pub type Validator = fn(&Config) -> Vec<(&'static str, usize, String)>;
+
+pub fn read_config_with(config_path: &str, validators: &[Validator]) -> Result<Config, Vec<ConfigError>> {
+    match read_file(config_path) {
+        Some(toml_str) => parse_config_with(&toml_str, validators),
+        None => Err( vec![ConfigError::new(None, format!("couldn't open {}", config_path))] )
+    }
+}
+
+/// Decodes and validates a config, every problem found is reported rather
+/// than just the first. The validators are run once the config has decoded.
+pub fn parse_config_with(toml_str: &str, validators: &[Validator]) -> Result<Config, Vec<ConfigError>> {
+    let mut parser = Parser::new(toml_str);
+    let mut table = match parser.parse() {
+        Some(table) => table,
+        None => {
+            let errors = parser.errors.iter()
+                .map(|e| ConfigError::new(Some(parser.to_linecol(e.lo).0 + 1), e.desc.clone()))
+                .collect();
+            return Err(errors);
+        }
+    };
+
+    let mut errors = Vec::new();
+    negative_integers(toml_str, "", &table, 0, &mut errors);
+
+    // decoding stops at the first bad field, drop it and go again to find the rest
+    let mut config = None;
+    while config.is_none() {
+        let mut decoder = Decoder::new(Value::Table(table.clone()));
+        match Config::decode(&mut decoder) {
+            Ok(c) => {
+                // whatever the decoder didn't consume isn't a field we know about
+                if let Some(Value::Table(ref leftover)) = decoder.toml {
+                    unknown_fields(toml_str, "", leftover, &mut errors);
+                }
+                config = Some(c);
+            }
+            Err(e) => {
+                let field = e.field.clone().unwrap_or(String::new());
+                errors.push( ConfigError::new(line_of(toml_str, &field), format!("{}", e)) );
+                if !remove_field(&mut table, &field) {
+                    return Err(errors);
+                }
+            }
+        }
+    }
+
+    let mut config = config.unwrap();
+    validate(toml_str, &config, &mut errors);
+    for validator in validators {
+        for (field, entry, message) in validator(&config) {
+            errors.push( ConfigError::new(line_of_entry(toml_str, field, entry), message) );
+        }
+    }
+
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    config.number_of_data_points = Some( config.number_of_data_points.unwrap_or(DEFAULT_NUMBER_OF_DATA_POINTS) );
+    config.gravity = Some( config.gravity.unwrap_or(DEFAULT_GRAVITY) );
+    config.restitution = Some( config.restitution.unwrap_or(DEFAULT_RESTITUTION) );
+    config.density_number_of_rows = Some( config.density_number_of_rows.unwrap_or(DEFAULT_DENSITY_NUMBER_OF_ROWS) );
+    config.density_number_of_columns = Some( config.density_number_of_columns.unwrap_or(DEFAULT_DENSITY_NUMBER_OF_COLUMNS) );
+    config.number_of_threads = Some( config.number_of_threads.unwrap_or(DEFAULT_NUMBER_OF_THREADS) );
+    config.print_statistics = Some( config.print_statistics.unwrap_or(DEFAULT_PRINT_STATISTICS) );
+    if config.collisions.is_none() {
+        config.collisions = Some( DEFAULT_COLLISIONS.to_string() );
+    }
+    if let Some(ref mut reordering) = config.reordering {
+        if reordering.curve.is_none() {
+            reordering.curve = Some( DEFAULT_REORDERING_CURVE.to_string() );
+        }
+        reordering.period = Some( reordering.period.unwrap_or(DEFAULT_REORDERING_PERIOD) );
+    }
+    if let Some(ref mut epidemic) = config.epidemic {
+        epidemic.transmission = Some( epidemic.transmission.unwrap_or(DEFAULT_TRANSMISSION) );
+        epidemic.incubation_time = Some( epidemic.incubation_time.unwrap_or(DEFAULT_INCUBATION_TIME) );
+        epidemic.recovery_time = Some( epidemic.recovery_time.unwrap_or(DEFAULT_RECOVERY_TIME) );
+        epidemic.infected = Some( epidemic.infected.unwrap_or(DEFAULT_INITIALLY_INFECTED) );
+        epidemic.vaccinated = Some( epidemic.vaccinated.unwrap_or(DEFAULT_VACCINATED) );
+    }
+    if let Some(ref mut heat) = config.heat {
+        heat.conductance = Some( heat.conductance.unwrap_or(DEFAULT_CONDUCTANCE) );
+        heat.restitution = Some( heat.restitution.unwrap_or(DEFAULT_HEAT_RESTITUTION) );
+        heat.inelastic_heating = Some( heat.inelastic_heating.unwrap_or(DEFAULT_INELASTIC_HEATING) );
+        heat.internal_energy = Some( heat.internal_energy.unwrap_or(DEFAULT_INTERNAL_ENERGY) );
+    }
+    if let Some(ref mut chemistry) = config.chemistry {
+        chemistry.conserve_mass = Some( chemistry.conserve_mass.unwrap_or(DEFAULT_CONSERVE_MASS) );
+        for reaction in chemistry.reactions.iter_mut().flat_map(|reactions| reactions.iter_mut()) {
+            reaction.activation_energy = Some( reaction.activation_energy.unwrap_or(DEFAULT_ACTIVATION_ENERGY) );
+        }
+    }
+    for attribute in config.attributes.iter_mut().flat_map(|attributes| attributes.iter_mut()) {
+        if attribute.values.is_none() {
+            attribute.values = Some( vec![DEFAULT_ATTRIBUTE_VALUE] );
+        }
+        if attribute.rules.is_none() {
+            attribute.rules = Some( vec![] );
+        }
+    }
+
+    Ok(config)
+}
+
+fn validate(source: &str, config: &Config, errors: &mut Vec<ConfigError>) {
+    let error = |field: &str, message: String| ConfigError::new(line_of(source, field), message);
+    // for fields of the entry'th table in an array of tables
+    let entry_error = |field: &str, entry: usize, message: String| ConfigError::new(line_of_entry(source, field, entry), message);
+
+    // a required field that is in the file but unset has already been reported as badly typed
+    let required = [
+        ("number_of_particles", config.number_of_particles.is_some()),
+        ("dt", config.dt.is_some()),
+        ("radius", config.radius.is_some()),
+        ("width", config.width.is_some()),
+        ("height", config.height.is_some())
+    ];
+    for &(field, set) in &required {
+        if !set && line_of(source, field).is_none() {
+            errors.push( ConfigError::new(None, format!("missing required field `{}`", field)) );
+        }
+    }
+
+    if let Some(dt) = config.dt {
+        if !(dt > 0.0) { errors.push( error("dt", format!("`dt` must be positive, got {}", dt)) ); }
+    }
+    if let Some(radius) = config.radius {
+        if !(radius > 0.0) { errors.push( error("radius", format!("`radius` must be positive, got {}", radius)) ); }
+    }
+    if let Some(restitution) = config.restitution {
+        if !(restitution >= 0.0 && restitution <= 1.0) {
+            errors.push( error("restitution", format!("`restitution` must be between 0 and 1, got {}", restitution)) );
+        }
+    }
+    if config.width == Some(0) { errors.push( error("width", "`width` must be positive".to_string()) ); }
+    if config.height == Some(0) { errors.push( error("height", "`height` must be positive".to_string()) ); }
+
+    // the velocity plot interpolates between at least two points
+    if let Some(n) = config.number_of_data_points {
+        if n < 2 { errors.push( error("number_of_data_points", format!("`number_of_data_points` must be at least 2, got {}", n)) ); }
+    }
+    if config.number_of_threads == Some(0) {
+        errors.push( error("number_of_threads", "`number_of_threads` must be at least 1".to_string()) );
+    }
+    if config.density_number_of_rows == Some(0) {
+        errors.push( error("density_number_of_rows", "`density_number_of_rows` must be positive".to_string()) );
+    }
+    if config.density_number_of_columns == Some(0) {
+        errors.push( error("density_number_of_columns", "`density_number_of_columns` must be positive".to_string()) );
+    }
+
+    if let Some(ref collisions) = config.collisions {
+        if !COLLISIONS.contains(&&**collisions) {
+            errors.push( error("collisions", format!("`collisions` must be one of {}, got \"{}\"", COLLISIONS.join(", "), collisions)) );
+        }
+    }
+
+    if let Some(ref quadtree) = config.quadtree {
+        if let Some(m) = quadtree.maximum_objects_per_volume {
+            if !(m > 0.0) {
+                errors.push( error("quadtree.maximum_objects_per_volume", format!("`quadtree.maximum_objects_per_volume` must be positive, got {}", m)) );
+            }
+        }
+
+        // a node merged straight after dividing would divide again on the next insert
+        let object_limit = quadtree.object_limit.unwrap_or(DEFAULT_QUADTREE_OBJECT_LIMIT);
+        let merge_limit = quadtree.merge_limit.unwrap_or(object_limit / 2);
+        if merge_limit >= object_limit {
+            let field = if quadtree.merge_limit.is_some() { "quadtree.merge_limit" } else { "quadtree.object_limit" };
+            errors.push( error(field, format!("`quadtree.merge_limit` must be less than `quadtree.object_limit`, got {} and {}", merge_limit, object_limit)) );
+        }
+    }
+
+    if let Some(ref reordering) = config.reordering {
+        if let Some(ref curve) = reordering.curve {
+            if !CURVES.contains(&&**curve) {
+                errors.push( error("reordering.curve", format!("`reordering.curve` must be one of {}, got \"{}\"", CURVES.join(", "), curve)) );
+            }
+        }
+        if reordering.period == Some(0) {
+            errors.push( error("reordering.period", "`reordering.period` must be at least 1".to_string()) );
+        }
+    }
+
+    if let Some(ref flow) = config.flow {
+        if let Some(ref inflow) = flow.inflow {
+            if !WALLS.contains(&&**inflow) {
+                errors.push( error("flow.inflow", format!("`flow.inflow` must be one of {}, got \"{}\"", WALLS.join(", "), inflow)) );
+            }
+            if flow.density.is_none() && line_of(source, "flow.density").is_none() {
+                errors.push( error("flow.inflow", "`flow.density` is required with `flow.inflow`".to_string()) );
+            }
+        }
+        if let Some(density) = flow.density {
+            if !(density > 0.0) { errors.push( error("flow.density", format!("`flow.density` must be positive, got {}", density)) ); }
+        }
+        if let Some(temperature) = flow.temperature {
+            if !(temperature >= 0.0) { errors.push( error("flow.temperature", format!("`flow.temperature` must not be negative, got {}", temperature)) ); }
+        }
+        if let Some(depth) = flow.depth {
+            if !(depth > 0.0) { errors.push( error("flow.depth", format!("`flow.depth` must be positive, got {}", depth)) ); }
+        }
+        if let Some(ref drift) = flow.drift {
+            if drift.len() != 2 { errors.push( error("flow.drift", format!("`flow.drift` must be [x, y], got {} values", drift.len())) ); }
+        }
+        for wall in flow.outflow.iter().flat_map(|walls| walls.iter()) {
+            if !WALLS.contains(&&**wall) {
+                errors.push( error("flow.outflow", format!("`flow.outflow` must only contain {}, got \"{}\"", WALLS.join(", "), wall)) );
+            }
+        }
+        for obstacle in flow.obstacles.iter().flat_map(|obstacles| obstacles.iter()) {
+            if obstacle.len() != 3 {
+                errors.push( error("flow.obstacles", format!("each of `flow.obstacles` must be [x, y, radius], got {} values", obstacle.len())) );
+            }
+            else if !(obstacle[2] > 0.0) {
+                errors.push( error("flow.obstacles", format!("an obstacle's radius must be positive, got {}", obstacle[2])) );
+            }
+        }
+    }
+
+    if let Some(ref epidemic) = config.epidemic {
+        if let Some(transmission) = epidemic.transmission {
+            if !(transmission >= 0.0 && transmission <= 1.0) {
+                errors.push( error("epidemic.transmission", format!("`epidemic.transmission` must be between 0 and 1, got {}", transmission)) );
+            }
+        }
+        if let Some(incubation_time) = epidemic.incubation_time {
+            if !(incubation_time >= 0.0) {
+                errors.push( error("epidemic.incubation_time", format!("`epidemic.incubation_time` must not be negative, got {}", incubation_time)) );
+            }
+        }
+        if let Some(recovery_time) = epidemic.recovery_time {
+            if !(recovery_time > 0.0) {
+                errors.push( error("epidemic.recovery_time", format!("`epidemic.recovery_time` must be positive, got {}", recovery_time)) );
+            }
+        }
+        if let Some(vaccinated) = epidemic.vaccinated {
+            if !(vaccinated >= 0.0 && vaccinated <= 1.0) {
+                errors.push( error("epidemic.vaccinated", format!("`epidemic.vaccinated` must be between 0 and 1, got {}", vaccinated)) );
+            }
+        }
+    }
+
+    if let Some(ref heat) = config.heat {
+        if let Some(conductance) = heat.conductance {
+            if !(conductance >= 0.0 && conductance <= 1.0) {
+                errors.push( error("heat.conductance", format!("`heat.conductance` must be between 0 and 1, got {}", conductance)) );
+            }
+        }
+        if let Some(restitution) = heat.restitution {
+            if !(restitution >= 0.0 && restitution <= 1.0) {
+                errors.push( error("heat.restitution", format!("`heat.restitution` must be between 0 and 1, got {}", restitution)) );
+            }
+        }
+        if let Some(internal_energy) = heat.internal_energy {
+            if !(internal_energy >= 0.0) {
+                errors.push( error("heat.internal_energy", format!("`heat.internal_energy` must not be negative, got {}", internal_energy)) );
+            }
+        }
+    }
+
+    if let Some(ref chemistry) = config.chemistry {
+        let species = chemistry.species.clone().unwrap_or(vec![]);
+        if species.is_empty() {
+            errors.push( error("chemistry.species", "`chemistry.species` must name at least one species".to_string()) );
+        }
+        if let Some(ref fractions) = chemistry.fractions {
+            if fractions.len() != species.len() {
+                errors.push( error("chemistry.fractions", format!("`chemistry.fractions` must have one fraction for each of the {} species, got {}", species.len(), fractions.len())) );
+            }
+            else if !(fractions.iter().all(|&f| f >= 0.0) && fractions.iter().sum::<f64>() > 0.0) {
+                errors.push( error("chemistry.fractions", "`chemistry.fractions` must not be negative and can't all be 0".to_string()) );
+            }
+        }
+
+        let known = |names: &Vec<String>| names.iter().all(|name| species.contains(name));
+        for (n, reaction) in chemistry.reactions.iter().flat_map(|reactions| reactions.iter()).enumerate() {
+            let reactants = reaction.reactants.clone().unwrap_or(vec![]);
+            let products = reaction.products.clone().unwrap_or(vec![]);
+            if reactants.len() != 2 || !known(&reactants) {
+                errors.push( entry_error("chemistry.reactions.reactants", n, format!("a reaction's `reactants` must be two of {}, got {:?}", species.join(", "), reactants)) );
+            }
+            if products.is_empty() || products.len() > 2 || !known(&products) {
+                errors.push( entry_error("chemistry.reactions.products", n, format!("a reaction's `products` must be one or two of {}, got {:?}", species.join(", "), products)) );
+            }
+            if let Some(activation_energy) = reaction.activation_energy {
+                if !(activation_energy >= 0.0) {
+                    errors.push( entry_error("chemistry.reactions.activation_energy", n, format!("a reaction's `activation_energy` must not be negative, got {}", activation_energy)) );
+                }
+            }
+        }
+    }
+
+    let mut names = vec![];
+    for (n, attribute) in config.attributes.iter().flat_map(|attributes| attributes.iter()).enumerate() {
+        let name = attribute.name.clone().unwrap_or(String::new());
+        if name.is_empty() {
+            errors.push( entry_error("attributes.name", n, "every `[[attributes]]` entry needs a `name`".to_string()) );
+        }
+        else if BUILT_IN_ATTRIBUTES.contains(&&*name) {
+            errors.push( entry_error("attributes.name", n, format!("an attribute can't be named \"{}\", one of {}", name, BUILT_IN_ATTRIBUTES.join(", "))) );
+        }
+        else if names.contains(&name) {
+            errors.push( entry_error("attributes.name", n, format!("there is more than one attribute named \"{}\"", name)) );
+        }
+        names.push(name.clone());
+
+        let values = attribute.values.clone().unwrap_or(vec![DEFAULT_ATTRIBUTE_VALUE]);
+        if values.is_empty() {
+            errors.push( entry_error("attributes.values", n, format!("`values` of attribute \"{}\" must have at least one value", name)) );
+        }
+        if let Some(ref fractions) = attribute.fractions {
+            if fractions.len() != values.len() {
+                errors.push( entry_error("attributes.fractions", n, format!("`fractions` of attribute \"{}\" must have one fraction for each of the {} values, got {}", name, values.len(), fractions.len())) );
+            }
+            else if !(fractions.iter().all(|&f| f >= 0.0) && fractions.iter().sum::<f64>() > 0.0) {
+                errors.push( entry_error("attributes.fractions", n, format!("`fractions` of attribute \"{}\" must not be negative and can't all be 0", name)) );
+            }
+        }
+        if let Some(ref bounds) = attribute.bounds {
+            if bounds.len() != 2 || !(bounds[0] < bounds[1]) {
+                errors.push( entry_error("attributes.bounds", n, format!("`bounds` of attribute \"{}\" must be [lower, upper] with lower < upper, got {:?}", name, bounds)) );
+            }
+        }
+    }
+
+    if let Some(ref initial_conditions) = config.initial_conditions {
+        if let Some(ref placement) = initial_conditions.placement {
+            if !PLACEMENTS.contains(&&**placement) {
+                errors.push( error("initial_conditions.placement",
+                    format!("`initial_conditions.placement` must be one of {}, got \"{}\"", PLACEMENTS.join(", "), placement)) );
+            }
+        }
+        if let Some(ref velocities) = initial_conditions.velocities {
+            if !VELOCITY_DISTRIBUTIONS.contains(&&**velocities) {
+                errors.push( error("initial_conditions.velocities",
+                    format!("`initial_conditions.velocities` must be one of {}, got \"{}\"", VELOCITY_DISTRIBUTIONS.join(", "), velocities)) );
+            }
+        }
+        if let Some(speed) = initial_conditions.speed {
+            if !(speed >= 0.0) { errors.push( error("initial_conditions.speed", format!("`initial_conditions.speed` must not be negative, got {}", speed)) ); }
+        }
+        if let Some(temperature) = initial_conditions.temperature {
+            if !(temperature >= 0.0) {
+                errors.push( error("initial_conditions.temperature", format!("`initial_conditions.temperature` must not be negative, got {}", temperature)) );
+            }
+        }
+    }
+
+    // the rest only makes sense for a valid box and radius
+    let (radius, width, height) = match (config.radius, config.width, config.height) {
+        (Some(r), Some(w), Some(h)) if r > 0.0 && w > 0 && h > 0 => (r, w as f64, h as f64),
+        _ => return
+    };
+
+    if 2.0*radius > width || 2.0*radius > height {
+        errors.push( error("radius", format!("a particle of radius {} doesn't fit in a {}x{} box", radius, width, height)) );
+    }
+
+    // particles are placed in the initial conditions region, if there is a valid one
+    let mut area = (width, height);
+
+    if let Some(ref initial_conditions) = config.initial_conditions {
+        if let Some(ref r) = initial_conditions.region {
+            let field = "initial_conditions.region";
+            if r.len() != 4 {
+                errors.push( error(field, format!("`{}` must be [x_min, y_min, x_max, y_max], got {} values", field, r.len())) );
+            }
+            else if !(r[0] >= 0.0 && r[1] >= 0.0 && r[2] <= width && r[3] <= height) {
+                errors.push( error(field, format!("`{}` must be inside the {}x{} box", field, width, height)) );
+            }
+            else if r[2] - r[0] < 2.0*radius || r[3] - r[1] < 2.0*radius {
+                errors.push( error(field, format!("`{}` is too small to hold a particle of radius {}", field, radius)) );
+            }
+            else {
+                area = (r[2] - r[0], r[3] - r[1]);
+            }
+        }
+    }
+
+    if let Some(n) = config.number_of_particles {
+        let maximum = (MAXIMUM_PACKING_DENSITY * area.0 * area.1 / (PI * radius * radius)) as usize;
+        if n > maximum {
+            errors.push( error("number_of_particles", format!("{} particles of radius {} can't fit in a {}x{} region, at most {}", n, radius, area.0, area.1, maximum)) );
+        }
+    }
+
+    if let Some(ref neighbour_list) = config.neighbour_list {
+        let skin = neighbour_list.skin.unwrap_or(DEFAULT_NEIGHBOUR_LIST_SKIN);
+        if !(skin > 0.0) {
+            errors.push( error("neighbour_list.skin", format!("`neighbour_list.skin` must be positive, got {}", skin)) );
+        }
+    }
+}
+
+/// The box's width and height and the radius the partition looks for pairs within,
+/// for the partitions checking their section. None unless the box and radius are valid.
+pub fn partition_geometry(config: &Config) -> Option<(f64, f64, f64)> {
+    let (radius, width, height) = match (config.radius, config.width, config.height) {
+        (Some(r), Some(w), Some(h)) if r > 0.0 && w > 0 && h > 0 => (r, w as f64, h as f64),
+        _ => return None
+    };
+
+    // the partition under a neighbour list looks for pairs within the skin as well
+    let skin = config.neighbour_list.as_ref().map_or(0.0, |n| n.skin.unwrap_or(DEFAULT_NEIGHBOUR_LIST_SKIN));
+    Some( (width, height, if skin > 0.0 { radius + skin / 2.0 } else { radius }) )
+}
+
+// every integer in the config is a count or a size, the decoder would silently wrap negatives
+// entry is the index of the table in its array of tables, 0 for a plain table
+fn negative_integers(source: &str, prefix: &str, table: &Table, entry: usize, errors: &mut Vec<ConfigError>) {
+    for (key, value) in table {
+        let field = format!("{}{}", prefix, key);
+        match *value {
+            Value::Integer(i) if i < 0 => {
+                errors.push( ConfigError::new(line_of_entry(source, &field, entry), format!("`{}` must not be negative, got {}", field, i)) );
+            }
+            Value::Table(ref t) => negative_integers(source, &format!("{}.", field), t, 0, errors),
+            Value::Array(ref entries) => {
+                for (n, value) in entries.iter().enumerate() {
+                    if let Value::Table(ref t) = *value {
+                        negative_integers(source, &format!("{}.", field), t, n, errors);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn unknown_fields(source: &str, prefix: &str, table: &Table, errors: &mut Vec<ConfigError>) {
+    for (key, value) in table {
+        let field = format!("{}{}", prefix, key);
+        match *value {
+            Value::Table(ref t) if !t.is_empty() => unknown_fields(source, &format!("{}.", field), t, errors),
+            _ => errors.push( ConfigError::new(line_of(source, &field), format!("unknown field `{}`", field)) )
+        }
+    }
+}
+
+// removes a dotted field such as `spatial_hash.number_of_rows`, false if it wasn't there
+fn remove_field(table: &mut Table, field: &str) -> bool {
+    match field.find('.') {
+        Some(i) => {
+            match table.get_mut(&field[..i]) {
+                Some(&mut Value::Table(ref mut t)) => remove_field(t, &field[i+1..]),
+                _ => false
+            }
+        }
+        None => table.remove(field).is_some()
+    }
+}
+
+// 1-based line a dotted field is set on, searching within its [table] section
+fn line_of(source: &str, field: &str) -> Option<usize> {
+    line_of_entry(source, field, 0)
 }
 
-pub fn read_config(config_path: &str) -> Config {
-    let toml_str = &*read_file(config_path).unwrap();
+// line of a dotted field in the entry'th table of its section, for an array of
+// tables such as [[attributes]] each header starts the next entry
+fn line_of_entry(source: &str, field: &str, entry: usize) -> Option<usize> {
+    let (section, key) = match field.rfind('.') {
+        Some(i) => (&field[..i], &field[i+1..]),
+        None => ("", field)
+    };
+    if key.is_empty() {
+        return None;
+    }
 
-    let decoded: Config = toml::decode_str(toml_str).unwrap();
-    decoded
+    // the keys before the first header are in the root table, there is only one
+    let wanted = if section.is_empty() { 0 } else { entry + 1 };
+    let mut current = "";
+    let mut entries = 0;
+    for (i, line) in source.lines().enumerate() {
+        let line = line.trim();
+        if line.starts_with('[') {
+            current = line.trim_matches(|c| c == '[' || c == ']').trim();
+            if current == section {
+                entries += 1;
+            }
+        }
+        else if current == section && entries == wanted {
+            if let Some(j) = line.find('=') {
+                if line[..j].trim() == key {
+                    return Some(i + 1);
+                }
+            }
+        }
+    }
+    None
 }
@@ -0,0 +1,34 @@
+// defaults used for fields left out of the config, and by the modules that own them
+// when they are built without one. This depends on nothing so any module can use it
+pub const DEFAULT_NUMBER_OF_DATA_POINTS: usize = 1000;
+pub const DEFAULT_GRAVITY: f64 = 0.0;
+pub const DEFAULT_RESTITUTION: f64 = 1.0;
+pub const DEFAULT_DENSITY_NUMBER_OF_ROWS: usize = 20;
+pub const DEFAULT_DENSITY_NUMBER_OF_COLUMNS: usize = 20;
+pub const DEFAULT_COLLISIONS: &'static str = "spatial_hash";
+pub const DEFAULT_NUMBER_OF_THREADS: usize = 4;
+pub const DEFAULT_PRINT_STATISTICS: bool = false;
+pub const DEFAULT_QUADTREE_OBJECT_LIMIT: usize = 16;
+pub const DEFAULT_QUADTREE_MAXIMUM_OBJECTS_PER_VOLUME: f64 = 16.0;
+pub const DEFAULT_QUADTREE_MAXIMUM_DEPTH: usize = 16;
+pub const DEFAULT_NEIGHBOUR_LIST_SKIN: f64 = 0.5;
+pub const DEFAULT_REORDERING_CURVE: &'static str = "hilbert";
+pub const DEFAULT_REORDERING_PERIOD: usize = 100;
+pub const DEFAULT_INFLOW_DEPTH: f64 = 8.0;
+pub const DEFAULT_TRANSMISSION: f64 = 1.0;
+pub const DEFAULT_INCUBATION_TIME: f64 = 0.0;
+pub const DEFAULT_RECOVERY_TIME: f64 = 1.0;
+pub const DEFAULT_INITIALLY_INFECTED: usize = 1;
+pub const DEFAULT_VACCINATED: f64 = 0.0;
+pub const DEFAULT_CONDUCTANCE: f64 = 0.5;
+pub const DEFAULT_HEAT_RESTITUTION: f64 = 1.0;
+pub const DEFAULT_INELASTIC_HEATING: bool = true;
+pub const DEFAULT_INTERNAL_ENERGY: f64 = 0.0;
+pub const DEFAULT_ACTIVATION_ENERGY: f64 = 0.0;
+pub const DEFAULT_CONSERVE_MASS: bool = true;
+pub const DEFAULT_ATTRIBUTE_VALUE: f64 = 0.0;
+pub const DEFAULT_PLACEMENT: &'static str = "random";
+pub const DEFAULT_VELOCITIES: &'static str = "uniform";
+pub const DEFAULT_INITIAL_SPEED: f64 = 150.0;
+// same mean kinetic energy as uniform velocities in [-150, 150]
+pub const DEFAULT_TEMPERATURE: f64 = 7500.0;
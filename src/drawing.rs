@@ -136,8 +136,14 @@ impl<'a> Particles<'a> {
                 colour: [ r, g, b, 1.0 ]
             } );
         }
+
+        // inflows, absorbing walls and merges change the number of particles, and a
+        // write has to fill the whole buffer
+        if vertices.len() != self.buffer.len() {
+            self.buffer = VertexBuffer::empty_dynamic(&self.context.0, vertices.len()).unwrap();
+        }
         self.buffer.write(&*vertices);
-    } 
+    }
     
     pub fn draw(&self) {
         let mut target = self.context.0.draw();
@@ -0,0 +1,320 @@
+use std::any::Any;
+use std::f64;
+
+use rand::Rng;
+use rand::distributions::{Exp, IndependentSample};
+
+use particle::Particle;
+use attribute::{Attribute, State, Context};
+use common::scale;
+use config::*;
+
+/// Compartments of the SEIR model, and vaccinated particles, which can't be infected.
+/// With no incubation time nobody stays exposed and it is the SIR model.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Compartment {
+    Susceptible,
+    Exposed,
+    Infected,
+    Recovered,
+    Vaccinated
+}
+
+impl Default for Compartment {
+    fn default() -> Compartment { Compartment::Susceptible }
+}
+
+/// A particle's compartment and when it moves on to the next one. `until` is drawn
+/// on entering the exposed and infected compartments, None until then.
+#[derive(Copy, Clone, Default, Debug, PartialEq)]
+pub struct Host {
+    pub compartment: Compartment,
+    pub until: Option<f64>,
+}
+
+// 0 to 4 in the order of Compartment
+impl State for Host {
+    fn to_scalar(&self) -> f64 {
+        self.compartment as usize as f64
+    }
+
+    fn from_scalar(f: f64) -> Host {
+        let compartment = match f.round() as i64 {
+            1 => Compartment::Exposed,
+            2 => Compartment::Infected,
+            3 => Compartment::Recovered,
+            4 => Compartment::Vaccinated,
+            _ => Compartment::Susceptible
+        };
+        Host { compartment: compartment, until: None }
+    }
+}
+
+/// Number of particles in each compartment at `time`. They are floats so that
+/// `seir_ode` can give its solution in the same form.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Counts {
+    pub time: f64,
+    pub susceptible: f64,
+    pub exposed: f64,
+    pub infected: f64,
+    pub recovered: f64,
+    pub vaccinated: f64,
+}
+
+impl Counts {
+    pub fn total(&self) -> f64 {
+        self.susceptible + self.exposed + self.infected + self.recovered + self.vaccinated
+    }
+}
+
+/// `transmission` is the chance a collision between an infected and a susceptible
+/// particle infects it, `incubation_time` and `recovery_time` are the mean times
+/// spent exposed and infected. The times are exponentially distributed as in the
+/// ODE model, an infinite recovery time is the SI model.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct EpidemicParameters {
+    pub transmission: f64,
+    pub incubation_time: f64,
+    pub recovery_time: f64,
+}
+
+impl EpidemicParameters {
+    pub fn new(transmission: f64, incubation_time: f64, recovery_time: f64) -> EpidemicParameters {
+        EpidemicParameters {
+            transmission: transmission,
+            incubation_time: incubation_time,
+            recovery_time: recovery_time
+        }
+    }
+
+    // expects a config that has been through read_config
+    pub fn from_config(config: &Config) -> EpidemicParameters {
+        let mut parameters = EpidemicParameters::default();
+        if let Some(ref c) = config.epidemic {
+            parameters.transmission = c.transmission.unwrap_or(DEFAULT_TRANSMISSION);
+            parameters.incubation_time = c.incubation_time.unwrap_or(DEFAULT_INCUBATION_TIME);
+            parameters.recovery_time = c.recovery_time.unwrap_or(DEFAULT_RECOVERY_TIME);
+        }
+        parameters
+    }
+}
+
+impl Default for EpidemicParameters {
+    fn default() -> EpidemicParameters {
+        EpidemicParameters::new(DEFAULT_TRANSMISSION, DEFAULT_INCUBATION_TIME, DEFAULT_RECOVERY_TIME)
+    }
+}
+
+/// An agent based SEIR epidemic spread by collisions. Every particle starts
+/// susceptible, `seed_outbreak` or `set` infect some. The counts in each
+/// compartment are recorded every step in `history`.
+///
+/// `transmission_rate` is the β of the ODE model the run corresponds to, the
+/// transmission chance times the rate particles have been colliding, so the
+/// history can be compared with `seir_ode`.
+pub struct Epidemic {
+    pub parameters: EpidemicParameters,
+    hosts: Vec<Host>,
+    history: Vec<Counts>,
+    // collisions seen, and the number of particles integrated over time
+    contacts: usize,
+    exposure: f64,
+}
+
+impl Epidemic {
+    pub fn data(&self) -> &Vec<Host> {
+        &self.hosts
+    }
+
+    pub fn history(&self) -> &[Counts] {
+        &self.history
+    }
+
+    pub fn counts(&self, time: f64) -> Counts {
+        let mut counts = Counts { time: time, susceptible: 0.0, exposed: 0.0, infected: 0.0, recovered: 0.0, vaccinated: 0.0 };
+        for host in &self.hosts {
+            match host.compartment {
+                Compartment::Susceptible => counts.susceptible += 1.0,
+                Compartment::Exposed => counts.exposed += 1.0,
+                Compartment::Infected => counts.infected += 1.0,
+                Compartment::Recovered => counts.recovered += 1.0,
+                Compartment::Vaccinated => counts.vaccinated += 1.0
+            }
+        }
+        counts
+    }
+
+    // collisions per particle per unit time so far, 0 before the first step
+    pub fn contact_rate(&self) -> f64 {
+        if self.exposure > 0.0 { 2.0 * self.contacts as f64 / self.exposure } else { 0.0 }
+    }
+
+    pub fn transmission_rate(&self) -> f64 {
+        self.parameters.transmission * self.contact_rate()
+    }
+
+    // infects `infected` particles and vaccinates a fraction of the rest, chosen at random
+    // from the susceptible ones
+    pub fn seed_outbreak<R: Rng>(&mut self, rng: &mut R, infected: usize, vaccinated: f64) {
+        let mut susceptible: Vec<usize> = (0..self.hosts.len()).filter(|&i| self.hosts[i].compartment == Compartment::Susceptible).collect();
+        rng.shuffle(&mut susceptible);
+
+        let infected = infected.min(susceptible.len());
+        let vaccinated = ((susceptible.len() - infected) as f64 * vaccinated).round() as usize;
+        for (k, &i) in susceptible.iter().enumerate().take(infected + vaccinated) {
+            let compartment = if k < infected { Compartment::Infected } else { Compartment::Vaccinated };
+            self.hosts[i] = Host { compartment: compartment, until: None };
+        }
+    }
+
+    // exponentially distributed with the given mean
+    fn duration<R: Rng>(rng: &mut R, mean: f64) -> f64 {
+        if mean.is_infinite() {
+            f64::INFINITY
+        }
+        else if mean > 0.0 {
+            Exp::new(1.0 / mean).ind_sample(rng)
+        }
+        else {
+            0.0
+        }
+    }
+
+    // moves host i on through every compartment whose time is up
+    fn progress(&mut self, i: usize, context: &mut Context) {
+        loop {
+            let host = self.hosts[i];
+            let mean = match host.compartment {
+                Compartment::Exposed => self.parameters.incubation_time,
+                Compartment::Infected => self.parameters.recovery_time,
+                _ => return
+            };
+            match host.until {
+                None => self.hosts[i].until = Some(context.time + Epidemic::duration(context.rng, mean)),
+                Some(until) if until <= context.time => {
+                    let next = if host.compartment == Compartment::Exposed { Compartment::Infected } else { Compartment::Recovered };
+                    self.hosts[i] = Host { compartment: next, until: None };
+                }
+                _ => return
+            }
+        }
+    }
+}
+
+impl Attribute for Epidemic {
+    fn new() -> Epidemic {
+        Epidemic {
+            parameters: EpidemicParameters::default(),
+            hosts: vec![],
+            history: vec![],
+            contacts: 0,
+            exposure: 0.0
+        }
+    }
+
+    fn name() -> &'static str { "epidemic" }
+    fn as_any(&self) -> &Any { self }
+    fn as_any_mut(&mut self) -> &mut Any { self }
+    fn scalars(&self) -> Vec<f64> { self.hosts.iter().map(|h| h.to_scalar()).collect() }
+    fn collision_listener(&self) -> bool { true }
+    fn data_bounds(&self) -> (f64, f64) { (0.0, 4.0) }
+
+    fn collision_update(&mut self, i: usize, j: usize, _: &Particle, _: &Particle, context: &mut Context) {
+        self.contacts += 1;
+
+        let target = match (self.hosts[i].compartment, self.hosts[j].compartment) {
+            (Compartment::Infected, Compartment::Susceptible) => j,
+            (Compartment::Susceptible, Compartment::Infected) => i,
+            _ => return
+        };
+        if context.rng.gen::<f64>() < self.parameters.transmission {
+            self.hosts[target] = Host { compartment: Compartment::Exposed, until: None };
+            self.progress(target, context);
+        }
+    }
+
+    fn update(&mut self, i: usize, _: &Particle, context: &mut Context) {
+        self.progress(i, context);
+    }
+
+    fn step(&mut self, context: &mut Context) {
+        self.exposure += self.hosts.len() as f64 * context.dt;
+        let counts = self.counts(context.time);
+        self.history.push(counts);
+    }
+
+    fn initialise(&mut self, number_of_particles: usize) {
+        self.hosts = vec![Host::default(); number_of_particles];
+    }
+
+    fn set(&mut self, f: f64, i: f64) {
+        let l = self.hosts.len() as f64;
+        self.hosts[scale(i, [0.0, 1.0], [0.0, l - 1.0]) as usize] = Host::from_scalar(f);
+    }
+
+    fn reorder(&mut self, order: &[usize]) {
+        self.hosts = order.iter().map(|&k| self.hosts[k]).collect();
+    }
+
+    fn push(&mut self) {
+        self.hosts.push(Host::default());
+    }
+
+    fn swap_remove(&mut self, i: usize) {
+        self.hosts.swap_remove(i);
+    }
+}
+
+/// The SEIR equations for a well mixed population starting from `initial`, solved
+/// with fourth order Runge-Kutta and returned every `dt` for `steps` steps after
+/// it. `beta` is the transmission rate, see `Epidemic::transmission_rate`, with
+/// no incubation time it is the SIR model. Vaccinated particles take no part
+/// other than diluting the contacts.
+pub fn seir_ode(initial: Counts, beta: f64, incubation_time: f64, recovery_time: f64, dt: f64, steps: usize) -> Vec<Counts> {
+    let n = initial.total();
+    let gamma = 1.0 / recovery_time;
+
+    // rates of change of s, e, i and r
+    let derivative = |y: [f64; 4]| -> [f64; 4] {
+        let infections = if n > 0.0 { beta * y[0] * y[2] / n } else { 0.0 };
+        let recoveries = gamma * y[2];
+        if incubation_time > 0.0 {
+            let onsets = y[1] / incubation_time;
+            [-infections, infections - onsets, onsets - recoveries, recoveries]
+        }
+        else {
+            [-infections, 0.0, infections - recoveries, recoveries]
+        }
+    };
+    let add = |y: [f64; 4], k: [f64; 4], h: f64| [y[0] + h*k[0], y[1] + h*k[1], y[2] + h*k[2], y[3] + h*k[3]];
+
+    // anyone exposed at the start is infected straight away in the SIR model
+    let mut y = if incubation_time > 0.0 {
+        [initial.susceptible, initial.exposed, initial.infected, initial.recovered]
+    }
+    else {
+        [initial.susceptible, 0.0, initial.exposed + initial.infected, initial.recovered]
+    };
+
+    let mut solution = Vec::with_capacity(steps);
+    for step in 1..steps + 1 {
+        let k1 = derivative(y);
+        let k2 = derivative(add(y, k1, dt / 2.0));
+        let k3 = derivative(add(y, k2, dt / 2.0));
+        let k4 = derivative(add(y, k3, dt));
+        for c in 0..4 {
+            y[c] += dt / 6.0 * (k1[c] + 2.0*k2[c] + 2.0*k3[c] + k4[c]);
+        }
+
+        solution.push( Counts {
+            time: initial.time + step as f64 * dt,
+            susceptible: y[0],
+            exposed: y[1],
+            infected: y[2],
+            recovered: y[3],
+            vaccinated: initial.vaccinated
+        } );
+    }
+    solution
+}
@@ -0,0 +1,153 @@
+use rand::Rng;
+use rand::distributions::{Normal, IndependentSample};
+
+use vector::Vector;
+use particles::ParticleStore;
+// the walls are defined with the particles, which check them, so particles doesn't need flow
+pub use particles::{Wall, Boundary};
+use config::*;
+
+/// Keeps a strip `depth` deep along `wall` at `density` particles per unit area,
+/// topping it up every step with particles moving at `drift` plus a Maxwellian
+/// spread at `temperature`. New particles are placed at random in the strip and
+/// may overlap, the collisions push them apart. Particles drifting back out
+/// through the wall are removed, as if they rejoined the reservoir behind it.
+#[derive(Copy, Clone)]
+pub struct Inflow {
+    pub wall: Wall,
+    pub density: f64,
+    pub temperature: f64,
+    pub drift: Vector,
+    pub depth: f64,
+}
+
+impl Inflow {
+    pub fn new(wall: Wall, density: f64, temperature: f64, drift: Vector) -> Inflow {
+        Inflow {
+            wall: wall,
+            density: density,
+            temperature: temperature,
+            drift: drift,
+            depth: DEFAULT_INFLOW_DEPTH
+        }
+    }
+
+    pub fn depth(mut self, depth: f64) -> Self { self.depth = depth; self }
+
+    // corners of the strip in a width x height box
+    pub fn region(&self, width: f64, height: f64) -> (Vector, Vector) {
+        match self.wall {
+            Wall::Left => (Vector::zero(), Vector::new(self.depth.min(width), height)),
+            Wall::Right => (Vector::new((width - self.depth).max(0.0), 0.0), Vector::new(width, height)),
+            Wall::Bottom => (Vector::zero(), Vector::new(width, self.depth.min(height))),
+            Wall::Top => (Vector::new(0.0, (height - self.depth).max(0.0)), Vector::new(width, height))
+        }
+    }
+
+    // positions and velocities of the particles that bring the strip back up to density
+    pub fn inject<R: Rng>(&self, rng: &mut R, particles: &ParticleStore, width: f64, height: f64) -> Vec<(Vector, Vector)> {
+        let (min, max) = self.region(width, height);
+
+        let inside = (0..particles.len()).filter(|&i| {
+            let (x, y) = (particles.x[i], particles.y[i]);
+            x >= min.x && x <= max.x && y >= min.y && y <= max.y
+        }).count();
+        let target = (self.density * (max.x - min.x) * (max.y - min.y)).round() as usize;
+
+        let normal = Normal::new(0.0, self.temperature.sqrt());
+        (inside..target).map(|_| {
+            let position = Vector::new( min.x + rng.gen::<f64>() * (max.x - min.x), min.y + rng.gen::<f64>() * (max.y - min.y) );
+            let velocity = self.drift + Vector::new( normal.ind_sample(rng), normal.ind_sample(rng) );
+            (position, velocity)
+        }).collect()
+    }
+}
+
+/// A fixed disc particles bounce off elastically. The momentum they give it is
+/// summed, `force` is the average rate since the last `reset`, which is the drag
+/// when the flow is along the x axis.
+#[derive(Copy, Clone)]
+pub struct Obstacle {
+    pub centre: Vector,
+    pub radius: f64,
+    impulse: Vector,
+    time: f64,
+}
+
+impl Obstacle {
+    pub fn new(centre: Vector, radius: f64) -> Obstacle {
+        Obstacle {
+            centre: centre,
+            radius: radius,
+            impulse: Vector::zero(),
+            time: 0.0
+        }
+    }
+
+    pub fn force(&self) -> Vector {
+        if self.time > 0.0 { self.impulse / self.time } else { Vector::zero() }
+    }
+
+    pub fn reset(&mut self) {
+        self.impulse = Vector::zero();
+        self.time = 0.0;
+    }
+
+    // pushes overlapping particles out to the surface and reflects the ones moving in
+    pub fn collide(&mut self, particles: &mut ParticleStore, dt: f64) {
+        for i in 0..particles.len() {
+            if particles.fixed[i] {
+                continue;
+            }
+            let offset = particles.position(i) - self.centre;
+            let distance = offset.magnitude();
+            let surface = self.radius + particles.radius[i];
+            if distance >= surface {
+                continue;
+            }
+
+            // a particle right on the centre is pushed out along x
+            let normal = if distance > 0.0 { offset / distance } else { Vector::new(1.0, 0.0) };
+            particles.set_position(i, self.centre + normal * surface);
+
+            let velocity = particles.velocity(i);
+            let speed = velocity.dot(normal);
+            if speed < 0.0 {
+                particles.set_velocity(i, velocity - 2.0 * speed * normal);
+                self.impulse = self.impulse + 2.0 * speed * particles.mass[i] * normal;
+            }
+        }
+        self.time += dt;
+    }
+}
+
+/// Everything the `[flow]` section of the config sets up, walls not listed keep
+/// reflecting.
+pub struct Flow {
+    pub inflow: Option<Inflow>,
+    pub outflows: Vec<Wall>,
+    pub obstacles: Vec<Obstacle>,
+}
+
+impl Flow {
+    // expects a config that has been through read_config
+    pub fn from_config(config: &Config) -> Flow {
+        let mut flow = Flow { inflow: None, outflows: vec![], obstacles: vec![] };
+
+        if let Some(ref c) = config.flow {
+            if let Some(wall) = c.inflow.as_ref().and_then(|name| Wall::from_name(name)) {
+                let drift = c.drift.as_ref().map(|d| Vector::new(d[0], d[1])).unwrap_or(Vector::zero());
+                let inflow = Inflow::new(wall, c.density.unwrap(), c.temperature.unwrap_or(DEFAULT_TEMPERATURE), drift);
+                flow.inflow = Some( inflow.depth(c.depth.unwrap_or(DEFAULT_INFLOW_DEPTH)) );
+            }
+            if let Some(ref outflow) = c.outflow {
+                flow.outflows = outflow.iter().filter_map(|name| Wall::from_name(name)).collect();
+            }
+            if let Some(ref obstacles) = c.obstacles {
+                flow.obstacles = obstacles.iter().map(|o| Obstacle::new(Vector::new(o[0], o[1]), o[2])).collect();
+            }
+        }
+
+        flow
+    }
+}
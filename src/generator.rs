@@ -0,0 +1,229 @@
+use std::f64::consts::PI;
+
+use rand::Rng;
+use rand::distributions::{Normal, IndependentSample};
+
+use vector::Vector;
+use config::*;
+
+// how many times a random position is redrawn before giving up on a particle
+const MAXIMUM_ATTEMPTS: usize = 1000;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Placement {
+    // uniformly random, particles may overlap
+    Random,
+    // uniformly random, redrawn until it doesn't overlap an earlier particle
+    NonOverlapping,
+    SquareLattice,
+    HexagonalLattice
+}
+
+// velocities are in units where particle mass and the Boltzmann constant are 1
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum VelocityDistribution {
+    // each component uniform in [-max, max]
+    Uniform(f64),
+    // each component normal with variance equal to the temperature
+    Maxwellian(f64),
+    // fixed speed in a random direction
+    Monoenergetic(f64)
+}
+
+/// Describes how particles are laid out and set moving at the start of a run.
+/// Positions are generated within `region` (the whole box by default), inset
+/// by the particle radius so no particle starts against a wall.
+#[derive(Copy, Clone)]
+pub struct InitialConditions {
+    pub placement: Placement,
+    pub region: (Vector, Vector),
+    pub velocities: VelocityDistribution,
+    pub zero_momentum: bool
+}
+
+impl InitialConditions {
+    pub fn new(width: f64, height: f64) -> InitialConditions {
+        InitialConditions {
+            placement: Placement::Random,
+            region: (Vector::zero(), Vector::new(width, height)),
+            velocities: VelocityDistribution::Uniform(DEFAULT_INITIAL_SPEED),
+            zero_momentum: false
+        }
+    }
+
+    // expects a config that has been through read_config
+    pub fn from_config(config: &Config, width: f64, height: f64) -> InitialConditions {
+        let mut initial_conditions = InitialConditions::new(width, height);
+
+        if let Some(ref c) = config.initial_conditions {
+            initial_conditions.placement = match c.placement.as_ref().map(|s| &**s).unwrap_or(DEFAULT_PLACEMENT) {
+                "non_overlapping" => Placement::NonOverlapping,
+                "square_lattice" => Placement::SquareLattice,
+                "hexagonal_lattice" => Placement::HexagonalLattice,
+                _ => Placement::Random
+            };
+
+            if let Some(ref r) = c.region {
+                initial_conditions.region = (Vector::new(r[0], r[1]), Vector::new(r[2], r[3]));
+            }
+
+            let speed = c.speed.unwrap_or(DEFAULT_INITIAL_SPEED);
+            initial_conditions.velocities = match c.velocities.as_ref().map(|s| &**s).unwrap_or(DEFAULT_VELOCITIES) {
+                "maxwellian" => VelocityDistribution::Maxwellian(c.temperature.unwrap_or(DEFAULT_TEMPERATURE)),
+                "monoenergetic" => VelocityDistribution::Monoenergetic(speed),
+                _ => VelocityDistribution::Uniform(speed)
+            };
+
+            initial_conditions.zero_momentum = c.zero_momentum.unwrap_or(false);
+        }
+
+        initial_conditions
+    }
+
+    pub fn placement(mut self, placement: Placement) -> Self { self.placement = placement; self }
+    pub fn region(mut self, min: Vector, max: Vector) -> Self { self.region = (min, max); self }
+    pub fn velocities(mut self, velocities: VelocityDistribution) -> Self { self.velocities = velocities; self }
+    pub fn zero_momentum(mut self, zero_momentum: bool) -> Self { self.zero_momentum = zero_momentum; self }
+
+    // None if the particles can't be fitted into the region
+    pub fn generate_positions<R: Rng>(&self, rng: &mut R, number_of_particles: usize, radius: f64) -> Option<Vec<Vector>> {
+        let min = self.region.0 + Vector::new(radius, radius);
+        let max = self.region.1 - Vector::new(radius, radius);
+
+        if max.x < min.x || max.y < min.y {
+            return None;
+        }
+
+        match self.placement {
+            Placement::Random => Some( (0..number_of_particles).map(|_| random_position(rng, min, max)).collect() ),
+            Placement::NonOverlapping => non_overlapping(rng, min, max, number_of_particles, radius),
+            Placement::SquareLattice => lattice(min, max, number_of_particles, radius, false),
+            Placement::HexagonalLattice => lattice(min, max, number_of_particles, radius, true)
+        }
+    }
+
+    pub fn generate_velocities<R: Rng>(&self, rng: &mut R, number_of_particles: usize) -> Vec<Vector> {
+        let mut velocities: Vec<Vector> = match self.velocities {
+            VelocityDistribution::Uniform(max) => {
+                (0..number_of_particles).map(|_| Vector::new( (rng.gen::<f64>()*2.0 - 1.0) * max, (rng.gen::<f64>()*2.0 - 1.0) * max )).collect()
+            }
+            VelocityDistribution::Maxwellian(temperature) => {
+                let normal = Normal::new(0.0, temperature.sqrt());
+                (0..number_of_particles).map(|_| Vector::new( normal.ind_sample(rng), normal.ind_sample(rng) )).collect()
+            }
+            VelocityDistribution::Monoenergetic(speed) => {
+                (0..number_of_particles).map(|_| {
+                    let angle = rng.gen::<f64>() * 2.0 * PI;
+                    Vector::new( speed * angle.cos(), speed * angle.sin() )
+                }).collect()
+            }
+        };
+
+        if self.zero_momentum && number_of_particles > 1 {
+            remove_drift(&mut velocities);
+        }
+
+        velocities
+    }
+}
+
+fn random_position<R: Rng>(rng: &mut R, min: Vector, max: Vector) -> Vector {
+    Vector::new( min.x + rng.gen::<f64>() * (max.x - min.x), min.y + rng.gen::<f64>() * (max.y - min.y) )
+}
+
+// rejection sampling, earlier particles are bucketed in a grid of cells one diameter wide
+// so each candidate is only compared with its neighbours
+fn non_overlapping<R: Rng>(rng: &mut R, min: Vector, max: Vector, number_of_particles: usize, radius: f64) -> Option<Vec<Vector>> {
+    let cell_size = 2.0 * radius;
+    let number_of_columns = ((max.x - min.x) / cell_size) as usize + 1;
+    let number_of_rows = ((max.y - min.y) / cell_size) as usize + 1;
+    let mut cells: Vec<Vec<Vector>> = vec![vec![]; number_of_columns*number_of_rows];
+    let mut positions = Vec::with_capacity(number_of_particles);
+
+    let cell = |p: Vector| (((p.y - min.y) / cell_size) as usize, ((p.x - min.x) / cell_size) as usize);
+
+    for _ in 0..number_of_particles {
+        let mut placed = false;
+
+        for _ in 0..MAXIMUM_ATTEMPTS {
+            let p = random_position(rng, min, max);
+            let (r, c) = cell(p);
+
+            let mut overlapping = false;
+            for i in r.saturating_sub(1)..(r+2) {
+                for j in c.saturating_sub(1)..(c+2) {
+                    if i < number_of_rows && j < number_of_columns {
+                        overlapping |= cells[i*number_of_columns + j].iter().any(|q| p.distance(*q) < 2.0*radius);
+                    }
+                }
+            }
+
+            if !overlapping {
+                cells[r*number_of_columns + c].push(p);
+                positions.push(p);
+                placed = true;
+                break;
+            }
+        }
+
+        if !placed {
+            return None;
+        }
+    }
+
+    Some(positions)
+}
+
+// starts from the spacing that would exactly tile the region and shrinks it until
+// enough lattice points fit, fails once neighbours would overlap
+fn lattice(min: Vector, max: Vector, number_of_particles: usize, radius: f64, hexagonal: bool) -> Option<Vec<Vector>> {
+    let width = max.x - min.x + 2.0*radius;
+    let height = max.y - min.y + 2.0*radius;
+    let area = width * height / number_of_particles as f64;
+
+    let mut spacing = if hexagonal { (2.0 * area / 3f64.sqrt()).sqrt() } else { area.sqrt() };
+
+    while spacing >= 2.0 * radius {
+        let row_spacing = if hexagonal { spacing * 3f64.sqrt() / 2.0 } else { spacing };
+        let mut positions = Vec::with_capacity(number_of_particles);
+
+        let mut row = 0;
+        let mut y = min.y;
+        while y <= max.y && positions.len() < number_of_particles {
+            let mut x = if hexagonal && row % 2 == 1 { min.x + spacing / 2.0 } else { min.x };
+            while x <= max.x && positions.len() < number_of_particles {
+                positions.push( Vector::new(x, y) );
+                x += spacing;
+            }
+            y += row_spacing;
+            row += 1;
+        }
+
+        if positions.len() == number_of_particles {
+            return Some(positions);
+        }
+
+        spacing *= 0.99;
+    }
+
+    None
+}
+
+// subtracts the mean velocity and rescales so the kinetic energy is unchanged
+fn remove_drift(velocities: &mut Vec<Vector>) {
+    let n = velocities.len() as f64;
+    let mean = velocities.iter().fold(Vector::zero(), |sum, v| sum + *v) / n;
+    let energy_before = velocities.iter().fold(0.0, |sum, v| sum + v.dot(*v));
+
+    for v in velocities.iter_mut() {
+        *v = *v - mean;
+    }
+
+    let energy_after = velocities.iter().fold(0.0, |sum, v| sum + v.dot(*v));
+    if energy_after > 0.0 {
+        let s = (energy_before / energy_after).sqrt();
+        for v in velocities.iter_mut() {
+            *v = *v * s;
+        }
+    }
+}
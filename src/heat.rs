@@ -0,0 +1,113 @@
+use std::any::Any;
+
+use particle::Particle;
+use attribute::{Attribute, Context, Response};
+use common::scale;
+use config::*;
+
+/// Internal energy of each particle, in the same units as its kinetic energy. In
+/// two dimensions the mean kinetic energy of a particle is kT, so the mean internal
+/// energy can be compared with it to see how the energy is partitioned.
+///
+/// Colliding particles exchange `conductance` of half the difference in their
+/// internal energies, 1 leaves them equal. Collisions are resolved with
+/// `restitution`, and when `inelastic_heating` is set the kinetic energy that
+/// an inelastic collision loses is shared between the two particles, whichever
+/// attribute made it inelastic. Energy lost at the walls is not.
+pub struct Heat {
+    pub conductance: f64,
+    pub restitution: f64,
+    pub inelastic_heating: bool,
+    energy: Vec<f64>,
+}
+
+impl Heat {
+    // for a bound attribute, every particle gets the config's internal energy. Expects
+    // a config that has been through read_config
+    pub fn configure(&mut self, config: &Config) {
+        if let Some(ref c) = config.heat {
+            self.conductance = c.conductance.unwrap_or(DEFAULT_CONDUCTANCE);
+            self.restitution = c.restitution.unwrap_or(DEFAULT_HEAT_RESTITUTION);
+            self.inelastic_heating = c.inelastic_heating.unwrap_or(DEFAULT_INELASTIC_HEATING);
+            self.set_all(c.internal_energy.unwrap_or(DEFAULT_INTERNAL_ENERGY));
+        }
+    }
+
+    pub fn data(&self) -> &Vec<f64> {
+        &self.energy
+    }
+
+    pub fn data_mut(&mut self) -> &mut Vec<f64> {
+        &mut self.energy
+    }
+
+    pub fn set_all(&mut self, energy: f64) {
+        for e in &mut self.energy {
+            *e = energy;
+        }
+    }
+
+    pub fn total(&self) -> f64 {
+        self.energy.iter().sum()
+    }
+
+    // 0 with no particles
+    pub fn mean(&self) -> f64 {
+        if self.energy.is_empty() { 0.0 } else { self.total() / self.energy.len() as f64 }
+    }
+}
+
+impl Attribute for Heat {
+    fn new() -> Heat {
+        Heat {
+            conductance: DEFAULT_CONDUCTANCE,
+            restitution: DEFAULT_HEAT_RESTITUTION,
+            inelastic_heating: DEFAULT_INELASTIC_HEATING,
+            energy: vec![]
+        }
+    }
+
+    fn name() -> &'static str { "heat" }
+    fn as_any(&self) -> &Any { self }
+    fn as_any_mut(&mut self) -> &mut Any { self }
+    fn scalars(&self) -> Vec<f64> { self.energy.clone() }
+    fn collision_listener(&self) -> bool { true }
+
+    fn collision_update(&mut self, i: usize, j: usize, _: &Particle, _: &Particle, context: &mut Context) {
+        let q = 0.5 * self.conductance * (self.energy[i] - self.energy[j]);
+        self.energy[i] -= q;
+        self.energy[j] += q;
+
+        if self.inelastic_heating {
+            let lost = context.impact.map_or(0.0, |impact| impact.kinetic_energy_lost);
+            self.energy[i] += 0.5 * lost;
+            self.energy[j] += 0.5 * lost;
+        }
+    }
+
+    // elastic collisions are left to the other attributes
+    fn collision_response(&mut self, _: usize, _: usize, _: &mut Context) -> Option<Response> {
+        if self.restitution < 1.0 { Some(Response::Bounce(self.restitution)) } else { None }
+    }
+
+    fn initialise(&mut self, number_of_particles: usize) {
+        self.energy = vec![0.0; number_of_particles];
+    }
+
+    fn set(&mut self, f: f64, i: f64) {
+        let l = self.energy.len() as f64;
+        self.energy[scale(i, [0.0, 1.0], [0.0, l - 1.0]) as usize] = f;
+    }
+
+    fn reorder(&mut self, order: &[usize]) {
+        self.energy = order.iter().map(|&k| self.energy[k]).collect();
+    }
+
+    fn push(&mut self) {
+        self.energy.push(0.0);
+    }
+
+    fn swap_remove(&mut self, i: usize) {
+        self.energy.swap_remove(i);
+    }
+}
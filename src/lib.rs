@@ -4,13 +4,30 @@ extern crate rustc_serialize;
 extern crate glium;
 extern crate scoped_pool;
 
+pub mod defaults;
 pub mod config;
+pub mod settings;
 pub mod vector;
 pub mod particle;
+pub mod particles;
+pub mod flow;
 pub mod collision;
+pub mod query;
 pub mod spatial_hash;
+pub mod cell_list;
 pub mod quadtree;
+pub mod loose_quadtree;
+pub mod bvh;
+pub mod naive;
+pub mod sweep_and_prune;
+pub mod neighbour_list;
+pub mod partition;
 pub mod simulator;
+pub mod generator;
 pub mod attribute;
+pub mod epidemic;
+pub mod heat;
+pub mod chemistry;
+pub mod rules;
 pub mod common;
 pub mod drawing;
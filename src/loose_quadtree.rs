@@ -0,0 +1,249 @@
+use std::cmp;
+use std::f64;
+
+use collision::*;
+use vector::Vector;
+use common::{balanced_ranges, start_pool};
+use defaults::DEFAULT_NUMBER_OF_THREADS;
+use scoped_pool::Pool;
+
+// deepest level an object is pushed down to, the root is level 0
+const MAXIMUM_DEPTH: usize = 12;
+
+struct Node {
+    position: Vector,
+    width: f64,
+    height: f64,
+    level: usize,
+    objects: Vec<usize>,
+    // 0 for a missing child, the root is never anyone's child
+    children: [usize; 4],
+    // bounds of every object at or below this node
+    min: Vector,
+    max: Vector,
+}
+
+impl Node {
+    fn new(position: Vector, width: f64, height: f64, level: usize) -> Node {
+        Node {
+            position: position,
+            width: width,
+            height: height,
+            level: level,
+            objects: vec![],
+            children: [0; 4],
+            min: Vector::new(f64::INFINITY, f64::INFINITY),
+            max: Vector::new(f64::NEG_INFINITY, f64::NEG_INFINITY)
+        }
+    }
+
+    fn overlaps(&self, min: Vector, max: Vector) -> bool {
+        self.min.x <= max.x && self.max.x >= min.x && self.min.y <= max.y && self.max.y >= min.y
+    }
+}
+
+// quadtree where every object is stored once, in the deepest node whose children
+// would still be at least twice as wide as the object. A node's loose bounds are twice
+// its size so an object only has to have its centre inside, and checks prune with the
+// bounds of what is actually stored below each node, so objects outside the box are
+// still found. Radii may differ between objects, see insert_with_radius.
+// Each check starts from an empty list, a pair is only ever found once.
+pub struct LooseQuadtree {
+    pub objects: Vec<(usize, Vector, f64)>,
+    radius: f64,
+    nodes: Vec<Node>,
+    collisions: Vec<Collision>,
+    pool: Option<Pool>,
+    number_of_threads: usize,
+}
+
+impl LooseQuadtree {
+    // radius is used for objects added with insert
+    pub fn new(width: f64, height: f64, radius: f64) -> LooseQuadtree {
+        LooseQuadtree {
+            objects: vec![],
+            radius: radius,
+            nodes: vec![Node::new(Vector::new(width/2.0, height/2.0), width, height, 0)],
+            collisions: Vec::with_capacity(10000),
+            pool: None,
+            number_of_threads: DEFAULT_NUMBER_OF_THREADS
+        }
+    }
+
+    // child of node n on the side of p, created if needed
+    fn child(&mut self, n: usize, p: Vector) -> usize {
+        let (position, width, height, level) = {
+            let node = &self.nodes[n];
+            (node.position, node.width, node.height, node.level)
+        };
+
+        let quadrant = (if p.x < position.x { 0 } else { 1 }) + (if p.y < position.y { 0 } else { 2 });
+        if self.nodes[n].children[quadrant] == 0 {
+            let dx = if quadrant % 2 == 0 { -width/4.0 } else { width/4.0 };
+            let dy = if quadrant < 2 { -height/4.0 } else { height/4.0 };
+
+            self.nodes.push( Node::new(position + Vector::new(dx, dy), width/2.0, height/2.0, level + 1) );
+            self.nodes[n].children[quadrant] = self.nodes.len() - 1;
+        }
+        self.nodes[n].children[quadrant]
+    }
+
+    // checks object `slot` against every object stored after it, stack is reused between calls
+    fn check_object(&self, slot: usize, stack: &mut Vec<usize>, collisions: &mut Vec<Collision>, mut comparisons: Option<&mut Vec<(usize, usize)>>) {
+        let (index1, p_position, p_radius) = self.objects[slot];
+        let min = p_position - Vector::new(p_radius, p_radius);
+        let max = p_position + Vector::new(p_radius, p_radius);
+
+        stack.clear();
+        stack.push(0);
+        while let Some(n) = stack.pop() {
+            let node = &self.nodes[n];
+            if !node.overlaps(min, max) {
+                continue;
+            }
+
+            for &other in &node.objects {
+                if other <= slot {
+                    continue;
+                }
+                let (index2, q_position, q_radius) = self.objects[other];
+
+                let normal = (q_position - p_position).normalise();
+                let penetration = p_radius + q_radius - p_position.distance( q_position );
+
+                if let Some(ref mut comparisons) = comparisons { comparisons.push((index1, index2)); }
+
+                // if circles are overlapping
+                if penetration > 0.0 {
+                    // add collision
+                    collisions.push( Collision::new(index1, index2, penetration, normal) );
+                }
+            }
+
+            for &c in &node.children {
+                if c != 0 { stack.push(c); }
+            }
+        }
+    }
+}
+
+impl SpatialPartition for LooseQuadtree {
+    fn insert(&mut self, index: usize, v: Vector) {
+        let radius = self.radius;
+        self.insert_with_radius(index, v, radius);
+    }
+
+    fn insert_with_radius(&mut self, index: usize, v: Vector, radius: f64) {
+        let slot = self.objects.len();
+        self.objects.push((index, v, radius));
+
+        let min = v - Vector::new(radius, radius);
+        let max = v + Vector::new(radius, radius);
+
+        let mut n = 0;
+        loop {
+            {
+                let node = &mut self.nodes[n];
+                node.min = Vector::new(node.min.x.min(min.x), node.min.y.min(min.y));
+                node.max = Vector::new(node.max.x.max(max.x), node.max.y.max(max.y));
+            }
+
+            // fits in a child's loose bounds if it is no bigger than half the child
+            let fits = {
+                let node = &self.nodes[n];
+                node.level < MAXIMUM_DEPTH && radius <= node.width.min(node.height) / 4.0
+            };
+            if !fits {
+                self.nodes[n].objects.push(slot);
+                break;
+            }
+            n = self.child(n, v);
+        }
+    }
+
+    // the nodes are rebuilt every frame, only the root is kept
+    fn clear(&mut self) {
+        self.collisions.clear();
+        self.objects.clear();
+        self.nodes.truncate(1);
+
+        let root = &mut self.nodes[0];
+        root.objects.clear();
+        root.children = [0; 4];
+        root.min = Vector::new(f64::INFINITY, f64::INFINITY);
+        root.max = Vector::new(f64::NEG_INFINITY, f64::NEG_INFINITY);
+    }
+
+    fn collision_check(&mut self) -> &Vec<Collision> {
+        let mut collisions = Vec::new();
+        let mut stack = Vec::new();
+        for slot in 0..self.objects.len() {
+            self.check_object(slot, &mut stack, &mut collisions, None);
+        }
+
+        self.collisions = collisions;
+        self.collisions.sort();
+
+        &self.collisions
+    }
+
+    // every object is a separate query, they are split into ranges and appended in order
+    fn collision_check_parallel(&mut self) -> &Vec<Collision> {
+        let costs = vec![1; self.objects.len()];
+        let ranges = balanced_ranges(&costs, self.number_of_threads * TASKS_PER_THREAD);
+        let mut results = vec![Vec::new(); ranges.len()];
+
+        start_pool(&mut self.pool, self.number_of_threads);
+        if let Some(ref p) = self.pool {
+            let loose_quadtree = &*self;
+            p.scoped(|scoped| {
+                for (result, &(start, end)) in results.iter_mut().zip( ranges.iter() ) {
+                    scoped.execute(move || {
+                        let mut stack = Vec::new();
+                        for slot in start..end {
+                            loose_quadtree.check_object(slot, &mut stack, result, None);
+                        }
+                    });
+                }
+            });
+        }
+
+        self.collisions.clear();
+        for mut result in results {
+            self.collisions.append( &mut result );
+        }
+
+        self.collisions.sort();
+
+        &self.collisions
+    }
+
+    fn collision_check_with_comparisons(&mut self) -> (&Vec<Collision>, Vec<(usize, usize)>) {
+        let mut collisions = Vec::new();
+        let mut comparisons = Vec::new();
+        let mut stack = Vec::new();
+        for slot in 0..self.objects.len() {
+            self.check_object(slot, &mut stack, &mut collisions, Some(&mut comparisons));
+        }
+
+        self.collisions = collisions;
+        self.collisions.sort();
+
+        (&self.collisions, comparisons)
+    }
+
+    fn set_number_of_threads(&mut self, number_of_threads: usize) {
+        let number_of_threads = cmp::max(number_of_threads, 1);
+        // the new pool is started by the next parallel check
+        if let Some(ref pool) = self.pool { pool.shutdown(); }
+        self.pool = None;
+        self.number_of_threads = number_of_threads;
+    }
+}
+
+// the pool's threads would otherwise outlive it
+impl Drop for LooseQuadtree {
+    fn drop(&mut self) {
+        if let Some(ref pool) = self.pool { pool.shutdown(); }
+    }
+}
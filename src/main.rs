@@ -5,6 +5,7 @@ extern crate boltzmann;
 extern crate rand;
 
 use std::cmp;
+use std::process;
 
 use glium::glutin;
 use glium::DisplayBuild;
@@ -12,17 +13,22 @@ use glium::Program;
 use glium::backend::glutin_backend::GlutinFacade;
 
 use boltzmann::simulator::Simulator;
+use boltzmann::particles::Curve;
+use boltzmann::flow::{Flow, Boundary};
 use boltzmann::collision::SpatialPartition;
 use boltzmann::vector::*;
 use boltzmann::attribute::*;
+use boltzmann::epidemic::{Epidemic, EpidemicParameters};
+use boltzmann::heat::Heat;
+use boltzmann::chemistry::Chemistry;
+use boltzmann::rules::RuleAttribute;
 use boltzmann::common::*;
 use boltzmann::drawing::*;
 
-#[allow(unused_imports)]
-use boltzmann::spatial_hash::SpatialHash;
-#[allow(unused_imports)]
-use boltzmann::quadtree::Quadtree;
 use boltzmann::config::*;
+use boltzmann::settings::read_config;
+use boltzmann::partition;
+use boltzmann::generator::InitialConditions;
 
 #[derive(Copy, Clone)]
 struct Vertex {
@@ -117,19 +123,14 @@ fn compile_shaders(display: &GlutinFacade, vertex_shader: &str, fragment_shader:
     None
 }
 
-fn p_x() -> f64 { scale(rand::random::<f64>(), [0.0, 1.0], [0.0, 1.0]) }
-fn p_y() -> f64 { scale(rand::random::<f64>(), [0.0, 1.0], [0.0, 1.0]) }
-fn v_x() -> f64 { scale(rand::random::<f64>(), [0.0, 1.0], [0.2, 0.8]) }
-fn v_y() -> f64 { scale(rand::random::<f64>(), [0.0, 1.0], [0.2, 0.8]) }
-
-
-fn particle_data<T: SpatialPartition>(simulation: &Simulator<T>) -> Vec<(Vector, (f32, f32, f32))> {
-    let a = simulation.attribute(0).get_data();
-    let (min, max) = simulation.attribute(0).data_bounds();
+// coloured by the displayed attribute
+fn particle_data<T: SpatialPartition>(simulation: &Simulator<T>, displayed: &str) -> Vec<(Vector, (f32, f32, f32))> {
+    let attribute = simulation.attribute_by_name(displayed).unwrap();
+    let a = attribute.scalars();
+    let (min, max) = attribute.data_bounds();
 
     let mut ps = Vec::new();
-    for (i, p) in simulation.particles.iter().enumerate() {
-        let position = p.get_position();
+    for (i, position) in simulation.positions().into_iter().enumerate() {
         let (red, green, blue) = grey_to_jet(a[i], min, max);
         ps.push( (position, (red, green, blue)) );  
     }        
@@ -156,15 +157,21 @@ fn density_data( data: Vec<f64>, max: f64 ) -> Vec<(f32, f32, f32)> {
     new
 }
 
-fn histogram_data<T: SpatialPartition>(simulation: &Simulator<T>) -> Vec<(Vector, f64)> {
-    let ds: Vec<(Vector, f64)> = simulation.positions().into_iter().zip( simulation.attribute(0).get_data().clone().into_iter() ).collect();
+fn histogram_data<T: SpatialPartition>(simulation: &Simulator<T>, displayed: &str) -> Vec<(Vector, f64)> {
+    let ds: Vec<(Vector, f64)> = simulation.positions().into_iter().zip( simulation.attribute_by_name(displayed).unwrap().scalars().into_iter() ).collect();
     ds
 }
 
 fn main() {
-    let config = read_config("simulation_config.toml");
+    let config = match read_config("simulation_config.toml") {
+        Ok(config) => config,
+        Err(errors) => {
+            for e in errors { eprintln!("simulation_config.toml: {}", e); }
+            process::exit(1);
+        }
+    };
     
-    // define simulation constants
+    // define simulation constants, read_config has checked the required ones and filled in defaults
     let number_of_particles = config.number_of_particles.unwrap();
     let number_of_data_points = config.number_of_data_points.unwrap();
     let dt = config.dt.unwrap();
@@ -175,6 +182,16 @@ fn main() {
     let height = config.height.unwrap();
     let density_number_of_rows = config.density_number_of_rows.unwrap();
     let density_number_of_columns = config.density_number_of_columns.unwrap();
+    let number_of_threads = config.number_of_threads.unwrap();
+    let print_statistics = config.print_statistics.unwrap();
+
+    let spatial_partition = match partition::from_config(&config, width as f64, height as f64, radius) {
+        Ok(spatial_partition) => spatial_partition,
+        Err(e) => {
+            eprintln!("simulation_config.toml: {}", e);
+            process::exit(1);
+        }
+    };
     
     let simulator_display = create_window(width, height, 664, 50, "boltzmann");
     let density_display = create_window(width, height, 152, 50, "density");
@@ -185,12 +202,64 @@ fn main() {
     let plotter_program = compile_shaders(&plotter_display, "shader/plotter_vertex.glsl", "shader/plotter_fragment.glsl", None).unwrap();      
             
             
-    let quad = Quadtree::new(width as f64, height as f64, radius);
-    let hash = SpatialHash::new(width as f64, height as f64, 32, 32, radius).unwrap();
-    let mut simulator = Simulator::new(hash, (&p_x, &p_y), (&v_x, &v_y), number_of_particles, radius, gravity, restitution, width as f64, height as f64, dt);
+    // a run is reproduced by putting the printed seed in the config
+    let seed = config.seed.unwrap_or_else(|| rand::random());
+    println!("seed = {}", seed);
+
+    let initial_conditions = InitialConditions::from_config(&config, width as f64, height as f64);
+    let mut simulator = match Simulator::from_initial_conditions(spatial_partition, &initial_conditions, number_of_particles, seed, radius, gravity, restitution, width as f64, height as f64, dt) {
+        Some(simulator) => simulator,
+        None => {
+            eprintln!("couldn't place {} particles of radius {} with the initial conditions in simulation_config.toml", number_of_particles, radius);
+            process::exit(1);
+        }
+    };
     
-    simulator.bind_attribute::<virus_attr>();
-    simulator.set_attribute(0, 1.0, 0.0);
+    simulator.set_number_of_threads(number_of_threads);
+    if let Some(ref reordering) = config.reordering {
+        simulator.set_reordering(Curve::from_name(reordering.curve.as_ref().unwrap()).unwrap(), reordering.period.unwrap());
+    }
+
+    let flow = Flow::from_config(&config);
+    for wall in flow.outflows {
+        simulator.set_boundary(wall, Boundary::Absorbing);
+    }
+    if let Some(inflow) = flow.inflow {
+        simulator.add_inflow(inflow);
+    }
+    for obstacle in flow.obstacles {
+        simulator.add_obstacle(obstacle);
+    }
+    
+    // the first attribute bound is displayed, [epidemic], [heat], [chemistry] and then
+    // each of [[attributes]] when configured, or else the virus
+    if let Some(ref epidemic) = config.epidemic {
+        simulator.bind_attribute::<Epidemic>().unwrap();
+        let (e, rng) = simulator.attribute_and_rng_mut::<Epidemic>().unwrap();
+        e.parameters = EpidemicParameters::from_config(&config);
+        e.seed_outbreak(rng, epidemic.infected.unwrap(), epidemic.vaccinated.unwrap());
+    }
+    if config.heat.is_some() {
+        simulator.bind_attribute::<Heat>().unwrap();
+        simulator.attribute_mut::<Heat>().unwrap().configure(&config);
+    }
+    if config.chemistry.is_some() {
+        simulator.bind_attribute::<Chemistry>().unwrap();
+        let (c, rng) = simulator.attribute_and_rng_mut::<Chemistry>().unwrap();
+        c.configure(rng, &config);
+    }
+    for attribute in config.attributes.iter().flat_map(|attributes| attributes.iter()) {
+        let name = attribute.name.as_ref().unwrap();
+        let id = simulator.bind_named_attribute::<RuleAttribute>(name)
+            .unwrap_or_else(|| panic!("simulation_config.toml: the attribute name \"{}\" is taken", name));
+        let (a, rng) = simulator.attribute_by_id_and_rng_mut::<RuleAttribute>(id).unwrap();
+        a.configure(rng, attribute);
+    }
+    if simulator.attributes().is_empty() {
+        let virus = simulator.bind_named_attribute::<virus_attr>("virus").unwrap();
+        simulator.set_attribute(virus, 1.0, 0.0);
+    }
+    let displayed = simulator.attributes().name(simulator.attributes().ids()[0]).unwrap().to_string();
 
     simulator.update();
     
@@ -198,15 +267,39 @@ fn main() {
     
     
     // let histogra_2d = histogram_2d(simulator.positions(), width as f64, height as f64, density_number_of_rows, density_number_of_columns);
-    let histogram_2d = histogram_2d_temp(histogram_data(&simulator), width as f64, height as f64, density_number_of_rows, density_number_of_columns);
-    let mut particles = Particles::new((simulator_display, simulator_program), particle_data(&simulator), radius, width as f64, height as f64);
+    let histogram_2d = histogram_2d_temp(histogram_data(&simulator, &displayed), width as f64, height as f64, density_number_of_rows, density_number_of_columns);
+    let mut particles = Particles::new((simulator_display, simulator_program), particle_data(&simulator, &displayed), radius, width as f64, height as f64);
     let mut plotter = Plotter::new((plotter_display, plotter_program), data, 2.0, 5.0)
                       .y_range((0.0, max));
     let mut density = boltzmann::drawing::Density::new((density_display, density_program), density_data(histogram_2d.1, histogram_2d.0 ), density_number_of_rows, density_number_of_columns);
     
 
     // run simulation 
+    let mut frame = 0;
     loop {
+        // drag averaged over the last 100 frames
+        frame += 1;
+        if frame % 100 == 0 && !simulator.obstacles.is_empty() {
+            if print_statistics {
+                println!("drag = {}, particles = {}", simulator.obstacles[0].force().x, simulator.number_of_particles());
+            }
+            simulator.obstacles[0].reset();
+        }
+        if frame % 100 == 0 && print_statistics {
+            if let Some(c) = simulator.attribute::<Epidemic>().and_then(|e| e.history().last()) {
+                println!("t = {:.3}, S = {}, E = {}, I = {}, R = {}, V = {}", c.time, c.susceptible, c.exposed, c.infected, c.recovered, c.vaccinated);
+            }
+            if let Some(heat) = simulator.attribute::<Heat>() {
+                let n = simulator.number_of_particles() as f64;
+                println!("kinetic = {}, internal = {}", simulator.total_energy() / n, heat.mean());
+            }
+            // 7 steps a frame
+            if let Some(chemistry) = simulator.attribute::<Chemistry>() {
+                let rates: Vec<f64> = (0..chemistry.reactions.len()).map(|k| chemistry.rate(k, 700)).collect();
+                println!("species = {:?}, counts = {:?}, rates = {:?}", chemistry.species, chemistry.counts(), rates);
+            }
+        }
+
         simulator.update();
         simulator.update();
         simulator.update();
@@ -216,13 +309,13 @@ fn main() {
         simulator.update();
 
         particles.draw();
-        particles.update(particle_data(&simulator));
+        particles.update(particle_data(&simulator, &displayed));
         plotter.plot();
         let (max, data) = plotter_data(&simulator, number_of_data_points);
         plotter.update( data );
         plotter = plotter.y_range((0.0, max));
         density.draw();
-        let histogram_2d = histogram_2d_temp(histogram_data(&simulator), width as f64, height as f64, density_number_of_rows, density_number_of_columns);
+        let histogram_2d = histogram_2d_temp(histogram_data(&simulator, &displayed), width as f64, height as f64, density_number_of_rows, density_number_of_columns);
         // let histogram_2d = histogram_2d(simulator.positions(), width as f64, height as f64, density_number_of_rows, density_number_of_columns);
         density.update( density_data(histogram_2d.1, histogram_2d.0 ) )
     }
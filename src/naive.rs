@@ -0,0 +1,82 @@
+use collision::*;
+use vector::Vector;
+
+// brute force partition, every object is checked against every other object
+// positions are kept as separate x and y arrays so each object's row of checks is
+// one vectorised pass over them
+pub struct Naive {
+    pub indices: Vec<usize>,
+    pub xs: Vec<f64>,
+    pub ys: Vec<f64>,
+    radius: f64,
+    penetrations: Vec<f64>,
+    collisions: Vec<Collision>,
+}
+
+impl Naive {
+    pub fn new(radius: f64) -> Naive {
+        Naive {
+            indices: vec![],
+            xs: vec![],
+            ys: vec![],
+            radius: radius,
+            penetrations: vec![],
+            collisions: Vec::with_capacity(10000)
+        }
+    }
+
+    fn check(&mut self, mut comparisons: Option<&mut Vec<(usize, usize)>>) {
+        for i in 0..self.indices.len() {
+            let p_position = Vector::new(self.xs[i], self.ys[i]);
+            penetrations(p_position.x, p_position.y, &self.xs[i+1..], &self.ys[i+1..], 2.0*self.radius, &mut self.penetrations);
+
+            for (k, &penetration) in self.penetrations.iter().enumerate() {
+                let j = i + 1 + k;
+
+                if let Some(ref mut comparisons) = comparisons { comparisons.push((i, j)); }
+
+                // if circles are overlapping
+                if penetration > 0.0 {
+                    let normal = (Vector::new(self.xs[j], self.ys[j]) - p_position).normalise();
+                    // add collision
+                    self.collisions.push( Collision::new(self.indices[i], self.indices[j], penetration, normal) );
+                }
+            }
+        }
+
+        self.collisions.sort();
+    }
+}
+
+impl SpatialPartition for Naive {
+    fn insert(&mut self, index: usize, v: Vector) {
+        self.indices.push(index);
+        self.xs.push(v.x);
+        self.ys.push(v.y);
+    }
+
+    fn clear(&mut self) {
+        self.collisions.clear();
+        self.indices.clear();
+        self.xs.clear();
+        self.ys.clear();
+    }
+
+    fn collision_check(&mut self) -> &Vec<Collision> {
+        self.check(None);
+
+        &self.collisions
+    }
+
+    // nothing to split up, falls back to the serial check
+    fn collision_check_parallel(&mut self) -> &Vec<Collision> {
+        self.collision_check()
+    }
+
+    fn collision_check_with_comparisons(&mut self) -> (&Vec<Collision>, Vec<(usize, usize)>) {
+        let mut comparisons = Vec::new();
+        self.check(Some(&mut comparisons));
+
+        (&self.collisions, comparisons)
+    }
+}
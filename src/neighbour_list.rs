@@ -0,0 +1,147 @@
+use collision::*;
+use vector::Vector;
+
+// Verlet neighbour list, wraps another partition and caches every pair closer than
+// 2*radius + skin. Each check only tests the cached pairs, the wrapped partition is
+// only rebuilt once some particle has moved more than half the skin since the last
+// build (or particles were added or left out), before then no new pair can overlap.
+// The wrapped partition must be built with radius + skin/2 so it finds those pairs.
+pub struct NeighbourList<T: SpatialPartition> {
+    pub spatial_partition: T,
+    pub skin: f64,
+    pub rebuilds: usize,
+    radius: f64,
+    positions: Vec<Vector>,
+    present: Vec<bool>,
+    reference_positions: Vec<Vector>,
+    reference_present: Vec<bool>,
+    pairs: Vec<(usize, usize)>,
+    collisions: Vec<Collision>,
+}
+
+impl<T: SpatialPartition> NeighbourList<T> {
+    pub fn new(spatial_partition: T, radius: f64, skin: f64) -> NeighbourList<T> {
+        NeighbourList {
+            spatial_partition: spatial_partition,
+            skin: skin,
+            rebuilds: 0,
+            radius: radius,
+            positions: vec![],
+            present: vec![],
+            reference_positions: vec![],
+            reference_present: vec![],
+            pairs: vec![],
+            collisions: Vec::with_capacity(10000)
+        }
+    }
+
+    fn needs_rebuild(&self) -> bool {
+        if self.present != self.reference_present {
+            return true;
+        }
+
+        let limit = self.skin / 2.0;
+        for i in 0..self.positions.len() {
+            if self.present[i] && self.positions[i].distance(self.reference_positions[i]) > limit {
+                return true;
+            }
+        }
+        false
+    }
+
+    fn rebuild(&mut self, parallel: bool) -> Vec<(usize, usize)> {
+        self.spatial_partition.clear();
+        for i in 0..self.positions.len() {
+            if self.present[i] {
+                self.spatial_partition.insert(i, self.positions[i]);
+            }
+        }
+
+        let mut comparisons = Vec::new();
+        self.pairs = {
+            let collisions = if parallel {
+                self.spatial_partition.collision_check_parallel()
+            }
+            else {
+                let (collisions, c) = self.spatial_partition.collision_check_with_comparisons();
+                comparisons = c;
+                collisions
+            };
+            collisions.iter().map(|c| (c.p1, c.p2)).collect()
+        };
+
+        self.reference_positions = self.positions.clone();
+        self.reference_present = self.present.clone();
+        self.rebuilds += 1;
+
+        comparisons
+    }
+
+    // narrow phase over the cached pairs, in the order the wrapped partition found them
+    fn check_pairs(&mut self) {
+        for &(i, j) in &self.pairs {
+            let p_position = self.positions[i];
+            let q_position = self.positions[j];
+
+            let normal = (q_position - p_position).normalise();
+            let penetration = 2.0*self.radius - p_position.distance( q_position );
+
+            // if circles are overlapping
+            if penetration > 0.0 {
+                // add collision
+                self.collisions.push( Collision::new(i, j, penetration, normal) );
+            }
+        }
+    }
+}
+
+impl<T: SpatialPartition> SpatialPartition for NeighbourList<T> {
+    fn insert(&mut self, index: usize, v: Vector) {
+        if index >= self.positions.len() {
+            self.positions.resize(index + 1, Vector::zero());
+            self.present.resize(index + 1, false);
+        }
+
+        self.positions[index] = v;
+        self.present[index] = true;
+    }
+
+    // the cached pairs are kept for the next frame
+    fn clear(&mut self) {
+        self.collisions.clear();
+        for p in &mut self.present {
+            *p = false;
+        }
+    }
+
+    fn collision_check(&mut self) -> &Vec<Collision> {
+        if self.needs_rebuild() {
+            self.rebuild(false);
+        }
+        self.check_pairs();
+
+        &self.collisions
+    }
+
+    fn collision_check_parallel(&mut self) -> &Vec<Collision> {
+        if self.needs_rebuild() {
+            self.rebuild(true);
+        }
+        self.check_pairs();
+
+        &self.collisions
+    }
+
+    // comparisons include the wrapped partition's on a rebuild
+    fn collision_check_with_comparisons(&mut self) -> (&Vec<Collision>, Vec<(usize, usize)>) {
+        let mut comparisons = if self.needs_rebuild() { self.rebuild(false) } else { Vec::new() };
+        comparisons.extend( self.pairs.iter().cloned() );
+        self.check_pairs();
+
+        (&self.collisions, comparisons)
+    }
+
+    fn set_number_of_threads(&mut self, number_of_threads: usize) {
+        self.spatial_partition.set_number_of_threads(number_of_threads);
+    }
+}
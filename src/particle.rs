@@ -17,6 +17,14 @@ impl Particle {
         }
     }
 
+    pub fn from_positions(position: Vector, previous_position: Vector, dt: f64) -> Particle {
+        Particle {
+            position: position,
+            previous_position: previous_position,
+            dt: dt
+        }
+    }
+
     pub fn verlet(&mut self, a: Vector) {
         let temp = self.position;
         self.position = 2.0*self.position - self.previous_position + a * self.dt * self.dt;
@@ -0,0 +1,331 @@
+use std::cmp;
+
+use vector::Vector;
+use particle::Particle;
+
+// quantisation of each axis for the curve keys, 2^16 cells a side
+const CURVE_BITS: u32 = 16;
+
+// sides of the box, the bottom is y = 0 and the left x = 0
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Wall {
+    Left,
+    Right,
+    Bottom,
+    Top
+}
+
+impl Wall {
+    pub fn from_name(name: &str) -> Option<Wall> {
+        match name {
+            "left" => Some(Wall::Left),
+            "right" => Some(Wall::Right),
+            "bottom" => Some(Wall::Bottom),
+            "top" => Some(Wall::Top),
+            _ => None
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Boundary {
+    // particles bounce off with the simulator's restitution
+    Reflecting,
+    // particles whose centre crosses the wall are removed
+    Absorbing
+}
+
+/// Space filling curves particles can be sorted along so that particles close in
+/// the box are close in memory. Hilbert keeps neighbours together a little better,
+/// Morton is cheaper to compute.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Curve {
+    Morton,
+    Hilbert
+}
+
+impl Curve {
+    pub fn from_name(name: &str) -> Option<Curve> {
+        match name {
+            "morton" => Some(Curve::Morton),
+            "hilbert" => Some(Curve::Hilbert),
+            _ => None
+        }
+    }
+
+    // position of cell (x, y) along the curve
+    pub fn key(&self, x: u32, y: u32) -> u64 {
+        match *self {
+            Curve::Morton => spread(x) | (spread(y) << 1),
+            Curve::Hilbert => hilbert(x, y)
+        }
+    }
+}
+
+// bits of x moved to the even bits
+fn spread(x: u32) -> u64 {
+    let mut x = x as u64 & 0xffff_ffff;
+    x = (x | (x << 16)) & 0x0000_ffff_0000_ffff;
+    x = (x | (x << 8)) & 0x00ff_00ff_00ff_00ff;
+    x = (x | (x << 4)) & 0x0f0f_0f0f_0f0f_0f0f;
+    x = (x | (x << 2)) & 0x3333_3333_3333_3333;
+    x = (x | (x << 1)) & 0x5555_5555_5555_5555;
+    x
+}
+
+// distance along the hilbert curve filling a 2^CURVE_BITS square
+fn hilbert(x: u32, y: u32) -> u64 {
+    let (mut x, mut y) = (x as u64, y as u64);
+    let mut d = 0;
+    let mut s = 1 << (CURVE_BITS - 1);
+    while s > 0 {
+        let rx = if x & s > 0 { 1 } else { 0 };
+        let ry = if y & s > 0 { 1 } else { 0 };
+        d += s * s * ((3 * rx) ^ ry);
+
+        // rotate the quadrant so the curve joins up
+        if ry == 0 {
+            if rx == 1 {
+                x = s - 1 - (x & (s - 1));
+                y = s - 1 - (y & (s - 1));
+            }
+            let t = x;
+            x = y;
+            y = t;
+        }
+        s /= 2;
+    }
+    d
+}
+
+/// Every particle in the simulation, stored as one array per quantity so loops
+/// over a single quantity run through contiguous memory and vectorise. Velocities
+/// are implied by the previous positions, as with `Particle`.
+///
+/// `id` is handed out when a particle is pushed and never reused, it follows the
+/// particle when the store is reordered or other particles are removed. A `fixed`
+/// particle doesn't move, collisions treat it as infinitely heavy.
+#[derive(Clone, PartialEq)]
+pub struct ParticleStore {
+    pub x: Vec<f64>,
+    pub y: Vec<f64>,
+    pub previous_x: Vec<f64>,
+    pub previous_y: Vec<f64>,
+    pub mass: Vec<f64>,
+    pub radius: Vec<f64>,
+    pub id: Vec<usize>,
+    pub fixed: Vec<bool>,
+    // where each id handed out so far is stored, None once it is removed
+    slots: Vec<Option<usize>>,
+    dt: f64,
+}
+
+/// A contiguous run of particles, what one integration task works on.
+pub struct ParticleChunk<'a> {
+    pub x: &'a mut [f64],
+    pub y: &'a mut [f64],
+    pub previous_x: &'a mut [f64],
+    pub previous_y: &'a mut [f64],
+    pub radius: &'a [f64],
+    pub fixed: &'a [bool],
+}
+
+impl ParticleStore {
+    pub fn new(dt: f64) -> ParticleStore {
+        ParticleStore {
+            x: vec![],
+            y: vec![],
+            previous_x: vec![],
+            previous_y: vec![],
+            mass: vec![],
+            radius: vec![],
+            id: vec![],
+            fixed: vec![],
+            slots: vec![],
+            dt: dt
+        }
+    }
+
+    // returns the new particle's id, it is stored at the end
+    pub fn push(&mut self, position: Vector, velocity: Vector, mass: f64, radius: f64) -> usize {
+        let previous_position = position - velocity * self.dt;
+        let id = self.slots.len();
+        self.slots.push(Some(self.len()));
+
+        self.x.push(position.x);
+        self.y.push(position.y);
+        self.previous_x.push(previous_position.x);
+        self.previous_y.push(previous_position.y);
+        self.mass.push(mass);
+        self.radius.push(radius);
+        self.id.push(id);
+        self.fixed.push(false);
+        id
+    }
+
+    // removes the particle at i, the last particle takes its place, returns the removed id
+    pub fn swap_remove(&mut self, i: usize) -> usize {
+        self.x.swap_remove(i);
+        self.y.swap_remove(i);
+        self.previous_x.swap_remove(i);
+        self.previous_y.swap_remove(i);
+        self.mass.swap_remove(i);
+        self.radius.swap_remove(i);
+        self.fixed.swap_remove(i);
+        let id = self.id.swap_remove(i);
+
+        self.slots[id] = None;
+        if i < self.id.len() {
+            self.slots[self.id[i]] = Some(i);
+        }
+        id
+    }
+
+    // where the particle with id is stored, None if it was removed or never existed
+    pub fn slot(&self, id: usize) -> Option<usize> {
+        self.slots.get(id).and_then(|&slot| slot)
+    }
+
+    pub fn len(&self) -> usize {
+        self.x.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.x.is_empty()
+    }
+
+    pub fn dt(&self) -> f64 {
+        self.dt
+    }
+
+    pub fn position(&self, i: usize) -> Vector {
+        Vector::new(self.x[i], self.y[i])
+    }
+
+    pub fn previous_position(&self, i: usize) -> Vector {
+        Vector::new(self.previous_x[i], self.previous_y[i])
+    }
+
+    pub fn velocity(&self, i: usize) -> Vector {
+        ( self.position(i) - self.previous_position(i) ) / self.dt
+    }
+
+    // moves a particle without changing its velocity
+    pub fn set_position(&mut self, i: usize, p: Vector) {
+        let v = self.velocity(i);
+        let previous_position = p - v * self.dt;
+        self.x[i] = p.x;
+        self.y[i] = p.y;
+        self.previous_x[i] = previous_position.x;
+        self.previous_y[i] = previous_position.y;
+    }
+
+    pub fn set_velocity(&mut self, i: usize, v: Vector) {
+        let previous_position = self.position(i) - v * self.dt;
+        self.previous_x[i] = previous_position.x;
+        self.previous_y[i] = previous_position.y;
+    }
+
+    // a copy of particle i for code that works on one particle at a time
+    pub fn particle(&self, i: usize) -> Particle {
+        Particle::from_positions(self.position(i), self.previous_position(i), self.dt)
+    }
+
+    pub fn positions(&self) -> Vec<Vector> {
+        (0..self.len()).map(|i| self.position(i)).collect()
+    }
+
+    // splits the store into runs of at most chunk_size particles
+    pub fn chunks<'a>(&'a mut self, chunk_size: usize) -> Vec<ParticleChunk<'a>> {
+        let chunk_size = cmp::max(chunk_size, 1);
+        self.x.chunks_mut(chunk_size)
+            .zip( self.y.chunks_mut(chunk_size) )
+            .zip( self.previous_x.chunks_mut(chunk_size) )
+            .zip( self.previous_y.chunks_mut(chunk_size) )
+            .zip( self.radius.chunks(chunk_size) )
+            .zip( self.fixed.chunks(chunk_size) )
+            .map(|(((((x, y), previous_x), previous_y), radius), fixed)| ParticleChunk {
+                x: x,
+                y: y,
+                previous_x: previous_x,
+                previous_y: previous_y,
+                radius: radius,
+                fixed: fixed
+            })
+            .collect()
+    }
+
+    // order that sorts the particles along curve, ties keep their current order
+    pub fn curve_order(&self, curve: Curve, width: f64, height: f64) -> Vec<usize> {
+        let cells = ((1u64 << CURVE_BITS) - 1) as f64;
+        let cell = |v: f64, size: f64| (v / size * cells).max(0.0).min(cells) as u32;
+
+        let mut keys: Vec<(u64, usize)> = (0..self.len())
+            .map(|i| (curve.key(cell(self.x[i], width), cell(self.y[i], height)), i))
+            .collect();
+        keys.sort();
+        keys.into_iter().map(|(_, i)| i).collect()
+    }
+
+    // particle order[k] moves to k
+    pub fn permute(&mut self, order: &[usize]) {
+        self.x = order.iter().map(|&i| self.x[i]).collect();
+        self.y = order.iter().map(|&i| self.y[i]).collect();
+        self.previous_x = order.iter().map(|&i| self.previous_x[i]).collect();
+        self.previous_y = order.iter().map(|&i| self.previous_y[i]).collect();
+        self.mass = order.iter().map(|&i| self.mass[i]).collect();
+        self.radius = order.iter().map(|&i| self.radius[i]).collect();
+        self.id = order.iter().map(|&i| self.id[i]).collect();
+        self.fixed = order.iter().map(|&i| self.fixed[i]).collect();
+        for (k, &id) in self.id.iter().enumerate() {
+            self.slots[id] = Some(k);
+        }
+    }
+}
+
+impl<'a> ParticleChunk<'a> {
+    // verlet step, each axis is its own loop over two arrays, fixed particles are
+    // put back afterwards
+    pub fn verlet(&mut self, a: Vector, dt: f64) {
+        let (ax, ay) = (a.x * dt * dt, a.y * dt * dt);
+        for (x, previous_x) in self.x.iter_mut().zip( self.previous_x.iter_mut() ) {
+            let temp = *x;
+            *x = 2.0 * temp - *previous_x + ax;
+            *previous_x = temp;
+        }
+        for (y, previous_y) in self.y.iter_mut().zip( self.previous_y.iter_mut() ) {
+            let temp = *y;
+            *y = 2.0 * temp - *previous_y + ay;
+            *previous_y = temp;
+        }
+        for (i, _) in self.fixed.iter().enumerate().filter(|&(_, &fixed)| fixed) {
+            self.x[i] = self.previous_x[i];
+            self.y[i] = self.previous_y[i];
+        }
+    }
+
+    // reflects particles off the reflecting walls of the box, boundaries are indexed by
+    // Wall, each axis is handled separately
+    pub fn boundary_check(&mut self, restitution: f64, width: f64, height: f64, boundaries: &[Boundary; 4]) {
+        let reflecting = |wall: Wall| boundaries[wall as usize] == Boundary::Reflecting;
+        reflect(self.x, self.previous_x, self.radius, restitution, width, reflecting(Wall::Left), reflecting(Wall::Right));
+        reflect(self.y, self.previous_y, self.radius, restitution, height, reflecting(Wall::Bottom), reflecting(Wall::Top));
+    }
+}
+
+// puts particles back inside [0, size] along one axis and sends them away from the wall,
+// for whichever of the low and high walls reflect
+fn reflect(xs: &mut [f64], previous_xs: &mut [f64], radius: &[f64], restitution: f64, size: f64, low: bool, high: bool) {
+    for ((x, previous_x), &r) in xs.iter_mut().zip( previous_xs.iter_mut() ).zip( radius.iter() ) {
+        let v = *x - *previous_x;
+        if low && *x - r < 0.0 {
+            *x = r;
+            *previous_x = *x - v.abs() * restitution;
+        }
+        let v = *x - *previous_x;
+        if high && *x + r > size {
+            *x = size - r;
+            *previous_x = *x + v.abs() * restitution;
+        }
+    }
+}
@@ -0,0 +1,112 @@
+use collision::*;
+use vector::Vector;
+use config::*;
+use naive::Naive;
+use spatial_hash::SpatialHash;
+use quadtree::Quadtree;
+use sweep_and_prune::SweepAndPrune;
+use cell_list::CellList;
+use loose_quadtree::LooseQuadtree;
+use bvh::Bvh;
+use neighbour_list::NeighbourList;
+
+// lets the simulator hold whichever partition was picked at runtime
+impl SpatialPartition for Box<SpatialPartition> {
+    fn insert(&mut self, index: usize, position: Vector) {
+        (**self).insert(index, position)
+    }
+
+    fn insert_with_radius(&mut self, index: usize, position: Vector, radius: f64) {
+        (**self).insert_with_radius(index, position, radius)
+    }
+
+    fn clear(&mut self) {
+        (**self).clear()
+    }
+
+    fn collision_check(&mut self) -> &Vec<Collision> {
+        (**self).collision_check()
+    }
+
+    fn collision_check_parallel(&mut self) -> &Vec<Collision> {
+        (**self).collision_check_parallel()
+    }
+
+    fn collision_check_with_comparisons(&mut self) -> (&Vec<Collision>, Vec<(usize, usize)>) {
+        (**self).collision_check_with_comparisons()
+    }
+
+    fn set_number_of_threads(&mut self, number_of_threads: usize) {
+        (**self).set_number_of_threads(number_of_threads)
+    }
+}
+
+// builds the partition named by `collisions` in the config, under a neighbour list if configured
+// an unknown name or a grid too fine for the radius is an error, a config that has been
+// through read_config has neither
+pub fn from_config(config: &Config, width: f64, height: f64, radius: f64) -> Result<Box<SpatialPartition>, ConfigError> {
+    match config.neighbour_list {
+        Some(ref c) => {
+            let skin = c.skin.unwrap_or(DEFAULT_NEIGHBOUR_LIST_SKIN);
+            let spatial_partition = build(config, width, height, radius + skin / 2.0)?;
+            Ok( Box::new( NeighbourList::new(spatial_partition, radius, skin) ) )
+        }
+        None => build(config, width, height, radius)
+    }
+}
+
+fn build(config: &Config, width: f64, height: f64, radius: f64) -> Result<Box<SpatialPartition>, ConfigError> {
+    let name = match config.collisions {
+        Some(ref name) => &**name,
+        None => DEFAULT_COLLISIONS
+    };
+
+    match name {
+        "naive" => Ok( Box::new( Naive::new(radius) ) ),
+        "spatial_hash" => {
+            let number_of_particles = config.number_of_particles.unwrap_or(0);
+            let (auto_number_of_columns, auto_number_of_rows) = SpatialHash::grid_for_density(width, height, radius, number_of_particles);
+            let (number_of_rows, number_of_columns, auto_tune) = match config.spatial_hash {
+                Some(ref c) => (c.number_of_rows.unwrap_or(auto_number_of_rows), c.number_of_columns.unwrap_or(auto_number_of_columns), c.auto_tune.unwrap_or(false)),
+                None => (auto_number_of_rows, auto_number_of_columns, false)
+            };
+
+            match SpatialHash::new(width, height, number_of_columns, number_of_rows, radius) {
+                Some(hash) => Ok( Box::new( hash.auto_tune(auto_tune) ) ),
+                None => Err( grid_error("spatial_hash", number_of_columns, number_of_rows, radius) )
+            }
+        },
+        "quadtree" => {
+            let (object_limit, maximum_objects_per_volume) = match config.quadtree {
+                Some(ref c) => (c.object_limit.unwrap_or(DEFAULT_QUADTREE_OBJECT_LIMIT), c.maximum_objects_per_volume.unwrap_or(DEFAULT_QUADTREE_MAXIMUM_OBJECTS_PER_VOLUME)),
+                None => (DEFAULT_QUADTREE_OBJECT_LIMIT, DEFAULT_QUADTREE_MAXIMUM_OBJECTS_PER_VOLUME)
+            };
+            let (merge_limit, maximum_depth) = match config.quadtree {
+                Some(ref c) => (c.merge_limit.unwrap_or(object_limit / 2), c.maximum_depth.unwrap_or(DEFAULT_QUADTREE_MAXIMUM_DEPTH)),
+                None => (object_limit / 2, DEFAULT_QUADTREE_MAXIMUM_DEPTH)
+            };
+
+            Ok( Box::new( Quadtree::with_thresholds(width, height, radius, object_limit, maximum_objects_per_volume).merge_limit(merge_limit).maximum_depth(maximum_depth) ) )
+        },
+        "sweep_and_prune" => Ok( Box::new( SweepAndPrune::new(width, height, radius) ) ),
+        "cell_list" => {
+            let (max_number_of_columns, max_number_of_rows) = CellList::maximum_grid(width, height, radius);
+            let (number_of_rows, number_of_columns) = match config.cell_list {
+                Some(ref c) => (c.number_of_rows.unwrap_or(max_number_of_rows), c.number_of_columns.unwrap_or(max_number_of_columns)),
+                None => (max_number_of_rows, max_number_of_columns)
+            };
+
+            match CellList::new(width, height, number_of_columns, number_of_rows, radius) {
+                Some(cell_list) => Ok( Box::new(cell_list) ),
+                None => Err( grid_error("cell_list", number_of_columns, number_of_rows, radius) )
+            }
+        },
+        "loose_quadtree" => Ok( Box::new( LooseQuadtree::new(width, height, radius) ) ),
+        "bvh" => Ok( Box::new( Bvh::new(radius) ) ),
+        _ => Err( ConfigError::new(None, format!("`collisions` must be one of {}, got \"{}\"", COLLISIONS.join(", "), name)) )
+    }
+}
+
+fn grid_error(section: &str, number_of_columns: usize, number_of_rows: usize, radius: f64) -> ConfigError {
+    ConfigError::new(None, format!("a {}x{} `{}` grid is too fine for radius {}", number_of_columns, number_of_rows, section, radius))
+}
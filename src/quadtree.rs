@@ -1,9 +1,17 @@
 use collision::*;
 use vector::Vector;
+use common::{balanced_ranges, start_pool};
+use query::*;
+use defaults::*;
 
-use scoped_pool::{Pool, Scope};
+use std::cmp;
 
+use scoped_pool::Pool;
 
+// the tree is kept between frames. insert moves an object that is already in it
+// straight away, so range, nearest and ray queries see where it is now without
+// waiting for a collision check. objects left out since the last clear are only
+// taken out, and nodes merged, at the next collision check
 pub struct Quadtree {
     pub empty: bool,
     pub level: usize,
@@ -13,13 +21,30 @@ pub struct Quadtree {
     pub height: f64,
     pub objects: Vec<(usize, Vector)>,
     pub children: Option<(Box<Quadtree>, Box<Quadtree>, Box<Quadtree>, Box<Quadtree>)>,
+    object_limit: usize,
+    merge_limit: usize,
+    maximum_depth: usize,
+    maximum_objects_per_volume: f64,
     collisions: Vec<Collision>,
-    pool: Option<Pool>
+    pool: Option<Pool>,
+    number_of_threads: usize,
+    // only used by the root, the objects inserted this frame and where each
+    // object currently is in the tree
+    present: Vec<bool>,
+    placed: Vec<Option<Vector>>,
 }
 
 #[allow(dead_code)]
 impl Quadtree {
     pub fn new(width: f64, height: f64, radius: f64) -> Quadtree {
+        Quadtree::with_thresholds(width, height, radius, DEFAULT_QUADTREE_OBJECT_LIMIT, DEFAULT_QUADTREE_MAXIMUM_OBJECTS_PER_VOLUME)
+    }
+
+    // object_limit: number of objects a node holds before it is divided
+    // maximum_objects_per_volume: a node is only divided if it can fit more circles than this
+    // the tree is kept between frames, objects that moved are taken out of the leaves
+    // they left and put in the ones they entered, see merge_limit and maximum_depth
+    pub fn with_thresholds(width: f64, height: f64, radius: f64, object_limit: usize, maximum_objects_per_volume: f64) -> Quadtree {
         Quadtree {
             empty: true,
             level: 0,
@@ -29,22 +54,50 @@ impl Quadtree {
             height: height,
             objects: vec![],
             children: None,
+            object_limit: object_limit,
+            merge_limit: object_limit / 2,
+            maximum_depth: DEFAULT_QUADTREE_MAXIMUM_DEPTH,
+            maximum_objects_per_volume: maximum_objects_per_volume,
             collisions: Vec::with_capacity(10000),
-            pool: Some(Pool::new(4))
+            pool: None,
+            number_of_threads: DEFAULT_NUMBER_OF_THREADS,
+            present: vec![],
+            placed: vec![]
         }
     }
-    fn child(level: usize, radius: f64, position: Vector, width: f64, height: f64) -> Quadtree {
+
+    // a divided node whose children hold this many objects or fewer between them
+    // is merged back into a leaf, half the object limit by default, keep it below
+    // object_limit so nodes don't flip between divided and merged every frame
+    pub fn merge_limit(mut self, merge_limit: usize) -> Self {
+        self.merge_limit = merge_limit;
+        self
+    }
+
+    // nodes at this level are never divided, the root is level 0
+    pub fn maximum_depth(mut self, maximum_depth: usize) -> Self {
+        self.maximum_depth = maximum_depth;
+        self
+    }
+    fn child(&self, position: Vector) -> Quadtree {
         Quadtree {
             empty: true,
-            level: level,
-            radius: radius,
+            level: self.level + 1,
+            radius: self.radius,
             position: position,
-            width: width,
-            height: height,
+            width: self.width/2.0,
+            height: self.height/2.0,
             objects: vec![],
             children: None,
+            object_limit: self.object_limit,
+            merge_limit: self.merge_limit,
+            maximum_depth: self.maximum_depth,
+            maximum_objects_per_volume: self.maximum_objects_per_volume,
             collisions: Vec::new(),
-            pool: None
+            pool: None,
+            number_of_threads: 1,
+            present: vec![],
+            placed: vec![]
         }
     }
 
@@ -52,7 +105,9 @@ impl Quadtree {
     pub fn reset(&mut self) {
         self.children = None;
         self.objects.clear();
-        self.empty == true;
+        self.empty = true;
+        self.present.clear();
+        self.placed.clear();
     }
 
 
@@ -68,22 +123,178 @@ impl Quadtree {
     // creates children nodes and inserts objects from this node to children
     pub fn divide(&mut self) {
         let p1 = Vector::new(self.position.x - self.width/4.0, self.position.y + self.height/4.0);
-        let c1 = Box::new( Quadtree::child(self.level+1, self.radius, p1, self.width/2.0, self.height/2.0) );
+        let c1 = Box::new( self.child(p1) );
 
         let p2 = Vector::new(self.position.x + self.width/4.0, self.position.y + self.height/4.0);
-        let c2 = Box::new( Quadtree::child(self.level+1, self.radius, p2, self.width/2.0, self.height/2.0) );
+        let c2 = Box::new( self.child(p2) );
 
         let p3 = Vector::new(self.position.x - self.width/4.0, self.position.y - self.height/4.0);
-        let c3 = Box::new( Quadtree::child(self.level+1, self.radius, p3, self.width/2.0, self.height/2.0) );
+        let c3 = Box::new( self.child(p3) );
 
         let p4 = Vector::new(self.position.x + self.width/4.0, self.position.y - self.height/4.0);
-        let c4 = Box::new( Quadtree::child(self.level+1, self.radius, p4, self.width/2.0, self.height/2.0) );
+        let c4 = Box::new( self.child(p4) );
 
         self.children = Some((c1, c2, c3, c4));
 
         let temp = self.objects.clone();
         self.objects.clear();
-        for (i, p) in temp { self.insert(i, p); }
+        for (i, p) in temp { self.place(i, p); }
+    }
+
+    // add object to quadtee at current level
+    // will get added to children if valid
+    fn place(&mut self, index: usize, p: Vector) {
+        self.empty = false;
+        if let Some((ref mut c1, ref mut c2, ref mut c3, ref mut c4)) = self.children {
+            if c1.within(p) { c1.place(index, p); }
+            if c2.within(p) { c2.place(index, p); }
+            if c3.within(p) { c3.place(index, p); }
+            if c4.within(p) { c4.place(index, p); }
+        }
+        else {
+            self.objects.push((index, p));
+
+            // number of circles that can possibly fit within with volume
+            let objects_per_volume = ( self.width*self.height ) / (4.0*self.radius*self.radius );
+
+            if self.objects.len() > self.object_limit && objects_per_volume > self.maximum_objects_per_volume && self.level < self.maximum_depth {
+                self.divide();
+            }
+        }
+    }
+
+    // takes an object placed at p out of every leaf it was put in
+    fn remove(&mut self, index: usize, p: Vector) {
+        if let Some((ref mut c1, ref mut c2, ref mut c3, ref mut c4)) = self.children {
+            if c1.within(p) { c1.remove(index, p); }
+            if c2.within(p) { c2.remove(index, p); }
+            if c3.within(p) { c3.remove(index, p); }
+            if c4.within(p) { c4.remove(index, p); }
+        }
+        else {
+            self.objects.retain(|&(i, _)| i != index);
+        }
+    }
+
+    // moves an object from p to q, only the leaves it left or entered change,
+    // in the leaves it stays in just the position is updated
+    fn relocate(&mut self, index: usize, p: Vector, q: Vector) {
+        if let Some((ref mut c1, ref mut c2, ref mut c3, ref mut c4)) = self.children {
+            for c in [c1, c2, c3, c4].iter_mut() {
+                match (c.within(p), c.within(q)) {
+                    (true, true) => c.relocate(index, p, q),
+                    (true, false) => c.remove(index, p),
+                    (false, true) => c.place(index, q),
+                    (false, false) => {}
+                }
+            }
+        }
+        else {
+            for o in &mut self.objects {
+                if o.0 == index { o.1 = q; }
+            }
+        }
+    }
+
+    // merges divided nodes holding merge_limit objects or fewer, returns the objects
+    // below this node, an object in more than one leaf is counted each time
+    fn merge(&mut self) -> usize {
+        let count = match self.children {
+            Some((ref mut c1, ref mut c2, ref mut c3, ref mut c4)) => c1.merge() + c2.merge() + c3.merge() + c4.merge(),
+            None => return self.objects.len()
+        };
+
+        if count <= self.merge_limit {
+            let mut nodes = Vec::new();
+            self.nodes(&mut nodes);
+
+            let mut objects: Vec<(usize, Vector)> = nodes.iter().flat_map(|n| n.objects.iter().cloned()).collect();
+            objects.sort_by_key(|&(i, _)| i);
+            objects.dedup_by_key(|o| o.0);
+
+            self.objects = objects;
+            self.children = None;
+        }
+
+        self.empty = count == 0;
+        count
+    }
+
+    // node bounds grown by the radius, every object stored below has its centre inside
+    fn bounds(&self) -> (Vector, Vector) {
+        let extent = Vector::new(self.width/2.0 + self.radius, self.height/2.0 + self.radius);
+        (self.position - extent, self.position + extent)
+    }
+
+    // objects in leaves overlapping the box, an object in more than one leaf is added each time
+    // the root is never skipped, it holds objects outside the box until it is divided
+    fn objects_in_box(&self, min: Vector, max: Vector, objects: &mut Vec<(usize, Vector)>) {
+        let (lo, hi) = self.bounds();
+        if self.level > 0 && (lo.x > max.x || hi.x < min.x || lo.y > max.y || hi.y < min.y) {
+            return;
+        }
+
+        if let Some((ref c1, ref c2, ref c3, ref c4)) = self.children {
+            c1.objects_in_box(min, max, objects);
+            c2.objects_in_box(min, max, objects);
+            c3.objects_in_box(min, max, objects);
+            c4.objects_in_box(min, max, objects);
+        }
+        objects.extend( self.objects.iter().filter(|&&(_, p)| p.x >= min.x && p.x <= max.x && p.y >= min.y && p.y <= max.y).cloned() );
+    }
+
+    // objects_in_box without the ones left out since the last clear, each once and sorted by index
+    fn present_objects_in_box(&self, min: Vector, max: Vector) -> Vec<(usize, Vector)> {
+        let mut objects = Vec::new();
+        self.objects_in_box(min, max, &mut objects);
+
+        objects.retain(|&(i, _)| self.present[i]);
+        objects.sort_by_key(|&(i, _)| i);
+        objects.dedup_by_key(|o| o.0);
+        objects
+    }
+
+    fn objects_in_circle(&self, centre: Vector, radius: f64) -> Vec<(usize, Vector)> {
+        let extent = Vector::new(radius, radius);
+        let mut objects = self.present_objects_in_box(centre - extent, centre + extent);
+        objects.retain(|&(_, p)| p.distance(centre) <= radius);
+        objects
+    }
+
+    // skips nodes the ray only enters after the best hit so far
+    fn ray_cast_node(&self, present: &Vec<bool>, origin: Vector, direction: Vector, max_distance: f64, best: &mut Option<RayHit>) {
+        if self.level > 0 {
+            let (lo, hi) = self.bounds();
+            let limit = best.map_or(max_distance, |h| h.distance);
+            match ray_box(origin, direction, lo, hi) {
+                Some((enter, _)) if enter <= limit => {},
+                _ => return
+            }
+        }
+
+        if let Some((ref c1, ref c2, ref c3, ref c4)) = self.children {
+            c1.ray_cast_node(present, origin, direction, max_distance, best);
+            c2.ray_cast_node(present, origin, direction, max_distance, best);
+            c3.ray_cast_node(present, origin, direction, max_distance, best);
+            c4.ray_cast_node(present, origin, direction, max_distance, best);
+        }
+        for &(index, position) in &self.objects {
+            if present[index] {
+                *best = closest(*best, hit(index, position, self.radius, origin, direction, max_distance));
+            }
+        }
+    }
+
+    // takes out the objects that weren't inserted again since the last clear
+    fn update(&mut self) {
+        for index in 0..self.placed.len() {
+            if let (false, Some(p)) = (self.present[index], self.placed[index]) {
+                self.remove(index, p);
+                self.placed[index] = None;
+            }
+        }
+
+        self.merge();
     }
 
     pub fn print(&self) {
@@ -99,6 +310,39 @@ impl Quadtree {
         println!(" ]");
     }
     
+    // every node holding objects, in depth first order
+    fn nodes<'a>(&'a self, nodes: &mut Vec<&'a Quadtree>) {
+        if !self.objects.is_empty() {
+            nodes.push(self);
+        }
+        if let Some((ref c1, ref c2, ref c3, ref c4)) = self.children {
+            c1.nodes(nodes);
+            c2.nodes(nodes);
+            c3.nodes(nodes);
+            c4.nodes(nodes);
+        }
+    }
+
+    // checks the objects of this node only
+    fn check_objects(&self, collisions: &mut Vec<Collision>) {
+        for i in 0..self.objects.len() {
+            let (index1, p_position) = self.objects[i];
+
+            for j in (i+1)..self.objects.len() {
+                let (index2, q_position) =  self.objects[j];
+
+                let normal = (q_position - p_position).normalise();
+                let penetration = 2.0*self.radius - p_position.distance( q_position );
+
+                // if circles are overlapping
+                if penetration > 0.0 {
+                    // add collision
+                    collisions.push( Collision::new(index1, index2, penetration, normal) );
+                }
+            }
+        }
+    }
+
     fn walk_tree(&self) -> Vec<Collision> {
         let mut collisions = Vec::new();
     
@@ -134,51 +378,32 @@ impl Quadtree {
 }
 impl SpatialPartition for Quadtree {
 
-    // add object to quadtee at current level
-    // will get added to children if valid
+    // an object already in the tree is moved, objects left out are only removed
+    // and nodes merged at the next collision check
     fn insert(&mut self, index: usize, p: Vector) {
-        self.empty = false;
-        if let Some((ref mut c1, ref mut c2, ref mut c3, ref mut c4)) = self.children {
-            if c1.within(p) { c1.insert(index, p); }
-            if c2.within(p) { c2.insert(index, p); }
-            if c3.within(p) { c3.insert(index, p); }
-            if c4.within(p) { c4.insert(index, p); }
+        if index >= self.placed.len() {
+            self.present.resize(index + 1, false);
+            self.placed.resize(index + 1, None);
         }
-        else {
-            self.objects.push((index, p));
-
-            // number of circles that can possibly fit within with volume
-            let objects_per_volume = ( self.width*self.height ) / (4.0*self.radius*self.radius );
-
-            // the volume must be able to contain more than 16
-            let maximum_objects_per_volume = 16.0;
-
-            // number of objects within the node
-            let object_limit = 16;
 
-            if self.objects.len() > object_limit && objects_per_volume > maximum_objects_per_volume {
-                // println!("divide");
-                self.divide();
-            }
+        match self.placed[index] {
+            Some(old) => if old != p { self.relocate(index, old, p) },
+            None => self.place(index, p)
         }
+        self.placed[index] = Some(p);
+        self.present[index] = true;
     }
 
-    // delete objects from the tree leaves non empty children alive
+    // keeps the tree for the next frame, objects not inserted again are removed at the next check
     fn clear(&mut self) {
-        if self.empty == true { self.children = None; }
-        if let Some((ref mut c1, ref mut c2, ref mut c3, ref mut c4)) = self.children {
-            c1.clear();
-            c2.clear();
-            c3.clear();
-            c4.clear();
+        for p in &mut self.present {
+            *p = false;
         }
-
-        self.objects.clear();
-        self.empty = true;
         self.collisions.clear();
     }
     
     fn collision_check(&mut self) -> &Vec<Collision> {
+        self.update();
     
         if let Some((ref c1, ref c2, ref c3, ref c4)) = self.children {
             self.collisions.append( &mut c1.walk_tree() );
@@ -212,50 +437,36 @@ impl SpatialPartition for Quadtree {
         &self.collisions
     }
     
+    // the nodes holding objects are split into runs with about the same number of
+    // pairs to check, each run is a task and the results are appended in order
     fn collision_check_parallel(&mut self) -> &Vec<Collision> {
-    
-        if let Some((ref c1, ref c2, ref c3, ref c4)) = self.children {
-    
-            let mut cs1 = Vec::new(); 
-            let mut cs2 = Vec::new(); 
-            let mut cs3 = Vec::new(); 
-            let mut cs4 = Vec::new(); 
-            
-            if let Some(ref p) = self.pool {
-                
-                
-                p.scoped(|scoped| {
-                    scoped.execute(|| { cs1 = c1.walk_tree() });
-                    scoped.execute(|| { cs2 = c2.walk_tree() });
-                    scoped.execute(|| { cs3 = c3.walk_tree() });
-                    scoped.execute(|| { cs4 = c4.walk_tree() });
-                    // Self::collect_data(&scoped, &mut cs1, &c1, &mut cs2, &c2, &mut cs3, &c3, &mut cs4, &c4);
-                });
-                // println!("{}", cs1.len());
-                self.collisions.append( &mut cs1 );
-                self.collisions.append( &mut cs2 );
-                self.collisions.append( &mut cs3 );
-                self.collisions.append( &mut cs4 );
-            }  
-        }
-        
-        for i in 0..self.objects.len() {
-            let (index1, p_position) = self.objects[i];
-            // let p_position = particles[index1].get_position();
-        
-            for j in (i+1)..self.objects.len() {
-                let (index2, q_position) =  self.objects[j];
-                // let q_position = particles[index2].get_position();
-        
-                let normal = (q_position - p_position).normalise();
-                let penetration = 2.0*self.radius - p_position.distance( q_position );
-        
-                // if circles are overlapping
-                if penetration > 0.0 {
-                    // add collision
-                    self.collisions.push( Collision::new(index1, index2, penetration, normal) );
+        self.update();
+
+        let mut results = Vec::new();
+
+        start_pool(&mut self.pool, self.number_of_threads);
+        if let Some(ref p) = self.pool {
+            let mut nodes = Vec::new();
+            self.nodes(&mut nodes);
+
+            let costs = nodes.iter().map(|n| n.objects.len() * n.objects.len()).collect();
+            let ranges = balanced_ranges(&costs, self.number_of_threads * TASKS_PER_THREAD);
+            results = vec![Vec::new(); ranges.len()];
+            let nodes = &nodes;
+
+            p.scoped(|scoped| {
+                for (result, &(start, end)) in results.iter_mut().zip( ranges.iter() ) {
+                    scoped.execute(move || {
+                        for node in &nodes[start..end] {
+                            node.check_objects(result);
+                        }
+                    });
                 }
-            }
+            });
+        }
+
+        for mut result in results {
+            self.collisions.append( &mut result );
         }
         
         self.collisions.sort();
@@ -265,49 +476,67 @@ impl SpatialPartition for Quadtree {
     }
     
     fn collision_check_with_comparisons(&mut self) -> (&Vec<Collision>, Vec<(usize, usize)>) {
+        self.update();
+
         let mut comparisons = Vec::new();
-    
-        if let Some((ref mut c1, ref mut c2, ref mut c3, ref mut c4)) = self.children {
-            let (collisions1, mut comparisons1) = c1.collision_check_with_comparisons();
-            let (collisions2, mut comparisons2) = c2.collision_check_with_comparisons();
-            let (collisions3, mut comparisons3) = c3.collision_check_with_comparisons();
-            let (collisions4, mut comparisons4) = c4.collision_check_with_comparisons();
-                        
-            self.collisions.append(&mut collisions1.clone());
-            self.collisions.append(&mut collisions2.clone());
-            self.collisions.append(&mut collisions3.clone());
-            self.collisions.append(&mut collisions4.clone());
-
-            comparisons.append(&mut comparisons1);
-            comparisons.append(&mut comparisons2);
-            comparisons.append(&mut comparisons3);
-            comparisons.append(&mut comparisons4);
-        }
-        
-        for i in 0..self.objects.len() {
-            let (index1, p_position) = self.objects[i];
-            // let p_position = particles[index1].get_position();
-        
-            for j in (i+1)..self.objects.len() {
-                let (index2, q_position) =  self.objects[j];
-                // let q_position = particles[index2].get_position();
-        
-                let normal = (q_position - p_position).normalise();
-                let penetration = 2.0*self.radius - p_position.distance( q_position );
-        
-                comparisons.push((i, j));
-        
-                // if circles are overlapping
-                if penetration > 0.0 {
-                    // add collision
-                    self.collisions.push( Collision::new(index1, index2, penetration, normal) );
+        let mut collisions = Vec::new();
+        {
+            let mut nodes = Vec::new();
+            self.nodes(&mut nodes);
+
+            for node in nodes {
+                for i in 0..node.objects.len() {
+                    for j in (i+1)..node.objects.len() {
+                        comparisons.push((i, j));
+                    }
                 }
+                node.check_objects(&mut collisions);
             }
         }
+        self.collisions.append( &mut collisions );
         
         self.collisions.sort();
         self.collisions.dedup();
     
         (&self.collisions, comparisons)
     }
+
+    fn set_number_of_threads(&mut self, number_of_threads: usize) {
+        let number_of_threads = cmp::max(number_of_threads, 1);
+        // the new pool is started by the next parallel check
+        if let Some(ref pool) = self.pool { pool.shutdown(); }
+        self.pool = None;
+        self.number_of_threads = number_of_threads;
+    }
+}
+
+impl SpatialQuery for Quadtree {
+    fn len(&self) -> usize {
+        self.present.iter().filter(|&&p| p).count()
+    }
+
+    fn query_circle(&self, centre: Vector, radius: f64) -> Vec<usize> {
+        self.objects_in_circle(centre, radius).iter().map(|&(i, _)| i).collect()
+    }
+
+    fn query_rectangle(&self, min: Vector, max: Vector) -> Vec<usize> {
+        self.present_objects_in_box(min, max).iter().map(|&(i, _)| i).collect()
+    }
+
+    fn nearest(&self, position: Vector, k: usize) -> Vec<usize> {
+        nearest_by_circles(position, k, self.len(), 2.0*self.radius, |radius| self.objects_in_circle(position, radius))
+    }
+
+    fn ray_cast(&self, origin: Vector, direction: Vector, max_distance: f64) -> Option<RayHit> {
+        let mut best = None;
+        self.ray_cast_node(&self.present, origin, direction.normalise(), max_distance, &mut best);
+        best
+    }
+}
+
+// the pool's threads would otherwise outlive it
+impl Drop for Quadtree {
+    fn drop(&mut self) {
+        if let Some(ref pool) = self.pool { pool.shutdown(); }
+    }
 }
@@ -0,0 +1,114 @@
+use std::f64;
+
+use collision::SpatialPartition;
+use vector::Vector;
+
+// first object a ray hits, the normal points out of the object at the hit
+#[derive(Copy, Clone)]
+pub struct RayHit {
+    pub index: usize,
+    pub distance: f64,
+    pub point: Vector,
+    pub normal: Vector
+}
+
+/// Queries over whatever was inserted since the last `clear()`, objects are the
+/// circles of the partition's radius. Results are sorted by index, except
+/// `nearest` which is sorted by distance.
+pub trait SpatialQuery: SpatialPartition {
+    // number of objects inserted since the last clear
+    fn len(&self) -> usize;
+
+    // objects whose centre is within radius of centre
+    fn query_circle(&self, centre: Vector, radius: f64) -> Vec<usize>;
+
+    // objects whose centre is inside the rectangle from min to max
+    fn query_rectangle(&self, min: Vector, max: Vector) -> Vec<usize>;
+
+    // the k objects with centres closest to position, nearest first
+    fn nearest(&self, position: Vector, k: usize) -> Vec<usize>;
+
+    // first object hit along direction from origin, up to max_distance away
+    fn ray_cast(&self, origin: Vector, direction: Vector, max_distance: f64) -> Option<RayHit>;
+}
+
+// k nearest from circle queries of doubling radius until k objects are inside,
+// every object closer than the k-th one is inside that circle too
+pub fn nearest_by_circles<F>(position: Vector, k: usize, len: usize, initial_radius: f64, query: F) -> Vec<usize>
+    where F: Fn(f64) -> Vec<(usize, Vector)>
+{
+    let k = k.min(len);
+    if k == 0 {
+        return vec![];
+    }
+
+    let mut radius = initial_radius.max(f64::MIN_POSITIVE);
+    let mut found = query(radius);
+    // objects too far outside the box for the partition to store are never found
+    while found.len() < k && radius < 1e15 {
+        radius *= 2.0;
+        found = query(radius);
+    }
+
+    // a NaN position, of an object or the query, has no place in the order
+    let mut found: Vec<(f64, usize)> = found.into_iter().map(|(i, p)| (p.distance(position), i)).filter(|&(d, _)| !d.is_nan()).collect();
+    found.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    found.into_iter().take(k).map(|(_, i)| i).collect()
+}
+
+// distance along a normalised ray to where it enters a circle, None if it
+// misses or the circle is behind the origin, 0 if the origin is inside
+pub fn ray_circle(origin: Vector, direction: Vector, centre: Vector, radius: f64) -> Option<f64> {
+    let offset = origin - centre;
+    let b = offset.dot(direction);
+    let c = offset.dot(offset) - radius*radius;
+
+    if c <= 0.0 {
+        return Some(0.0);
+    }
+    if b > 0.0 {
+        return None;
+    }
+
+    let discriminant = b*b - c;
+    if discriminant < 0.0 { None } else { Some(-b - discriminant.sqrt()) }
+}
+
+// distances along a ray where it enters and leaves a box, None if it misses
+pub fn ray_box(origin: Vector, direction: Vector, min: Vector, max: Vector) -> Option<(f64, f64)> {
+    let mut enter = f64::NEG_INFINITY;
+    let mut leave = f64::INFINITY;
+
+    for &(o, d, lo, hi) in &[(origin.x, direction.x, min.x, max.x), (origin.y, direction.y, min.y, max.y)] {
+        if d == 0.0 {
+            if o < lo || o > hi { return None; }
+        }
+        else {
+            let (t1, t2) = ((lo - o) / d, (hi - o) / d);
+            enter = enter.max(t1.min(t2));
+            leave = leave.min(t1.max(t2));
+        }
+    }
+
+    if enter > leave || leave < 0.0 { None } else { Some((enter.max(0.0), leave)) }
+}
+
+// the closer of two hits
+pub fn closest(best: Option<RayHit>, hit: Option<RayHit>) -> Option<RayHit> {
+    match (best, hit) {
+        (Some(b), Some(h)) => if h.distance < b.distance || (h.distance == b.distance && h.index < b.index) { Some(h) } else { Some(b) },
+        (None, h) => h,
+        (b, None) => b
+    }
+}
+
+// hit on the circle of radius around an object, direction must be normalised
+pub fn hit(index: usize, position: Vector, radius: f64, origin: Vector, direction: Vector, max_distance: f64) -> Option<RayHit> {
+    match ray_circle(origin, direction, position, radius) {
+        Some(t) if t <= max_distance => {
+            let point = origin + direction * t;
+            Some(RayHit { index: index, distance: t, point: point, normal: (point - position).normalise() })
+        },
+        _ => None
+    }
+}
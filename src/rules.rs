@@ -0,0 +1,515 @@
+use std::any::Any;
+
+use rand::Rng;
+
+use particle::Particle;
+use attribute::{Attribute, Context};
+use common::scale;
+use config::{Config, AttributeConfig};
+
+/// When a rule is applied, to every collision or to every particle once a step.
+/// A collision rule is applied both ways round, `a` being each of the pair in turn.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Event {
+    Collision,
+    Step
+}
+
+/// What a rule's expressions can refer to. `a` is the particle's value, or one of
+/// the pair's in a collision and `b` the other's. `impact` is the speed the pair
+/// collided at, `speed`, `x` and `y` are the particle's in a step rule.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Variable {
+    A,
+    B,
+    Time,
+    Dt,
+    Impact,
+    Speed,
+    X,
+    Y
+}
+
+impl Variable {
+    pub fn from_name(name: &str) -> Option<Variable> {
+        match name {
+            "a" => Some(Variable::A),
+            "b" => Some(Variable::B),
+            "time" => Some(Variable::Time),
+            "dt" => Some(Variable::Dt),
+            "impact" => Some(Variable::Impact),
+            "speed" => Some(Variable::Speed),
+            "x" => Some(Variable::X),
+            "y" => Some(Variable::Y),
+            _ => None
+        }
+    }
+
+    fn defined_on(&self, event: Event) -> bool {
+        match *self {
+            Variable::B | Variable::Impact => event == Event::Collision,
+            Variable::Speed | Variable::X | Variable::Y => event == Event::Step,
+            _ => true
+        }
+    }
+}
+
+/// Arithmetic on floats, comparisons and logic give 1 for true and 0 for false and
+/// anything but 0 is true.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Expr {
+    Number(f64),
+    Variable(Variable),
+    Negate(Box<Expr>),
+    Not(Box<Expr>),
+    Binary(&'static str, Box<Expr>, Box<Expr>)
+}
+
+// values of the variables for one evaluation, indexed by Variable
+pub type Values = [f64; 8];
+
+impl Expr {
+    pub fn eval(&self, values: &Values) -> f64 {
+        let truth = |b: bool| if b { 1.0 } else { 0.0 };
+        match *self {
+            Expr::Number(n) => n,
+            Expr::Variable(v) => values[v as usize],
+            Expr::Negate(ref e) => -e.eval(values),
+            Expr::Not(ref e) => truth(e.eval(values) == 0.0),
+            Expr::Binary(op, ref l, ref r) => {
+                let (l, r) = (l.eval(values), r.eval(values));
+                match op {
+                    "+" => l + r,
+                    "-" => l - r,
+                    "*" => l * r,
+                    "/" => l / r,
+                    "==" => truth(l == r),
+                    "!=" => truth(l != r),
+                    "<" => truth(l < r),
+                    "<=" => truth(l <= r),
+                    ">" => truth(l > r),
+                    ">=" => truth(l >= r),
+                    "and" => truth(l != 0.0 && r != 0.0),
+                    "or" => truth(l != 0.0 || r != 0.0),
+                    _ => unreachable!()
+                }
+            }
+        }
+    }
+
+    fn variables(&self, out: &mut Vec<Variable>) {
+        match *self {
+            Expr::Number(_) => {}
+            Expr::Variable(v) => out.push(v),
+            Expr::Negate(ref e) | Expr::Not(ref e) => e.variables(out),
+            Expr::Binary(_, ref l, ref r) => { l.variables(out); r.variables(out); }
+        }
+    }
+}
+
+/// A rule of the form
+///
+/// `on collision if a == 1 and b == 0 then b = 0.5 with probability 0.3`
+///
+/// `on` is `collision` or `step`, the condition and probability can be left out
+/// and several assignments are separated by commas. Every assignment is worked
+/// out from the values before the rule, so `then a = b, b = a` swaps them. In a
+/// collision both ways round are too, so the rule above infects whichever of the
+/// pair is healthy. When both set the same particle, the way with it as `a` wins.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Rule {
+    pub event: Event,
+    pub condition: Option<Expr>,
+    pub assignments: Vec<(Variable, Expr)>,
+    pub probability: Option<Expr>,
+}
+
+impl Rule {
+    pub fn parse(text: &str) -> Result<Rule, String> {
+        let mut parser = Parser { tokens: tokenise(text)?, next: 0 };
+
+        parser.keyword("on")?;
+        let event = match parser.take() {
+            Some(Token::Word(ref w)) if w == "collision" => Event::Collision,
+            Some(Token::Word(ref w)) if w == "step" => Event::Step,
+            t => return Err( format!("expected `collision` or `step` after `on`, found {}", describe(t.as_ref())) )
+        };
+
+        let condition = if parser.is_keyword("if") {
+            parser.next += 1;
+            Some( parser.or()? )
+        } else { None };
+
+        parser.keyword("then")?;
+        let mut assignments = vec![];
+        loop {
+            let target = match parser.take() {
+                Some(Token::Word(ref w)) if w == "a" => Variable::A,
+                Some(Token::Word(ref w)) if w == "b" => Variable::B,
+                t => return Err( format!("expected `a` or `b` to assign to, found {}", describe(t.as_ref())) )
+            };
+            parser.symbol("=")?;
+            assignments.push( (target, parser.or()?) );
+
+            if !parser.is_symbol(",") {
+                break;
+            }
+            parser.next += 1;
+        }
+
+        let probability = if parser.is_keyword("with") {
+            parser.next += 1;
+            parser.keyword("probability")?;
+            Some( parser.or()? )
+        } else { None };
+
+        if let Some(t) = parser.take() {
+            return Err( format!("unexpected {} at the end of the rule", describe(Some(&t))) );
+        }
+
+        let rule = Rule { event: event, condition: condition, assignments: assignments, probability: probability };
+        rule.check()?;
+        Ok(rule)
+    }
+
+    // every variable has a value for the event
+    fn check(&self) -> Result<(), String> {
+        let mut variables: Vec<Variable> = self.assignments.iter().map(|&(v, _)| v).collect();
+        for e in self.condition.iter().chain( self.assignments.iter().map(|&(_, ref e)| e) ).chain( self.probability.iter() ) {
+            e.variables(&mut variables);
+        }
+
+        let event = if self.event == Event::Collision { "collision" } else { "step" };
+        match variables.iter().find(|v| !v.defined_on(self.event)) {
+            Some(v) => Err( format!("`{}` can't be used in a {} rule", format!("{:?}", v).to_lowercase(), event) ),
+            None => Ok(())
+        }
+    }
+
+    // applies the rule to the values, the assignments are returned if it fires
+    fn apply<R: Rng>(&self, rng: &mut R, values: &Values) -> Option<Vec<(Variable, f64)>> {
+        if let Some(ref condition) = self.condition {
+            if condition.eval(values) == 0.0 {
+                return None;
+            }
+        }
+        if let Some(ref probability) = self.probability {
+            if rng.gen::<f64>() >= probability.eval(values) {
+                return None;
+            }
+        }
+        Some( self.assignments.iter().map(|&(v, ref e)| (v, e.eval(values))).collect() )
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Number(f64),
+    Word(String),
+    Symbol(&'static str)
+}
+
+const SYMBOLS: &'static [&'static str] = &["==", "!=", "<=", ">=", "<", ">", "=", "+", "-", "*", "/", "(", ")", ","];
+
+fn tokenise(text: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = vec![];
+    let mut rest = text.trim_start();
+    while !rest.is_empty() {
+        let c = rest.chars().next().unwrap();
+        let length;
+        if c.is_digit(10) || c == '.' {
+            length = rest.find(|c: char| !(c.is_digit(10) || c == '.')).unwrap_or(rest.len());
+            match rest[..length].parse() {
+                Ok(n) => tokens.push( Token::Number(n) ),
+                Err(_) => return Err( format!("`{}` isn't a number", &rest[..length]) )
+            }
+        }
+        else if c.is_alphabetic() || c == '_' {
+            length = rest.find(|c: char| !(c.is_alphanumeric() || c == '_')).unwrap_or(rest.len());
+            tokens.push( Token::Word(rest[..length].to_string()) );
+        }
+        else {
+            match SYMBOLS.iter().find(|s| rest.starts_with(*s)) {
+                Some(s) => {
+                    length = s.len();
+                    tokens.push( Token::Symbol(s) );
+                }
+                None => return Err( format!("unexpected `{}`", c) )
+            }
+        }
+        rest = rest[length..].trim_start();
+    }
+    Ok(tokens)
+}
+
+fn describe(token: Option<&Token>) -> String {
+    match token {
+        Some(&Token::Number(n)) => format!("`{}`", n),
+        Some(&Token::Word(ref w)) => format!("`{}`", w),
+        Some(&Token::Symbol(s)) => format!("`{}`", s),
+        None => "the end of the rule".to_string()
+    }
+}
+
+// recursive descent, loosest binding first: or, and, not, comparisons, + and -, * and /
+struct Parser {
+    tokens: Vec<Token>,
+    next: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.next)
+    }
+
+    fn take(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.next).cloned();
+        self.next += 1;
+        token
+    }
+
+    fn is_keyword(&self, keyword: &str) -> bool {
+        match self.peek() {
+            Some(&Token::Word(ref w)) => w == keyword,
+            _ => false
+        }
+    }
+
+    fn is_symbol(&self, symbol: &str) -> bool {
+        match self.peek() {
+            Some(&Token::Symbol(s)) => s == symbol,
+            _ => false
+        }
+    }
+
+    fn keyword(&mut self, keyword: &str) -> Result<(), String> {
+        if self.is_keyword(keyword) {
+            self.next += 1;
+            Ok(())
+        }
+        else {
+            Err( format!("expected `{}`, found {}", keyword, describe(self.peek())) )
+        }
+    }
+
+    fn symbol(&mut self, symbol: &str) -> Result<(), String> {
+        if self.is_symbol(symbol) {
+            self.next += 1;
+            Ok(())
+        }
+        else {
+            Err( format!("expected `{}`, found {}", symbol, describe(self.peek())) )
+        }
+    }
+
+    fn or(&mut self) -> Result<Expr, String> {
+        let mut e = self.and()?;
+        while self.is_keyword("or") {
+            self.next += 1;
+            e = Expr::Binary("or", Box::new(e), Box::new( self.and()? ));
+        }
+        Ok(e)
+    }
+
+    fn and(&mut self) -> Result<Expr, String> {
+        let mut e = self.not()?;
+        while self.is_keyword("and") {
+            self.next += 1;
+            e = Expr::Binary("and", Box::new(e), Box::new( self.not()? ));
+        }
+        Ok(e)
+    }
+
+    fn not(&mut self) -> Result<Expr, String> {
+        if self.is_keyword("not") {
+            self.next += 1;
+            return Ok( Expr::Not(Box::new( self.not()? )) );
+        }
+        self.comparison()
+    }
+
+    fn comparison(&mut self) -> Result<Expr, String> {
+        let e = self.sum()?;
+        for &op in &["==", "!=", "<=", ">=", "<", ">"] {
+            if self.is_symbol(op) {
+                self.next += 1;
+                return Ok( Expr::Binary(op, Box::new(e), Box::new( self.sum()? )) );
+            }
+        }
+        Ok(e)
+    }
+
+    fn sum(&mut self) -> Result<Expr, String> {
+        let mut e = self.product()?;
+        loop {
+            let op = if self.is_symbol("+") { "+" } else if self.is_symbol("-") { "-" } else { return Ok(e) };
+            self.next += 1;
+            e = Expr::Binary(op, Box::new(e), Box::new( self.product()? ));
+        }
+    }
+
+    fn product(&mut self) -> Result<Expr, String> {
+        let mut e = self.unary()?;
+        loop {
+            let op = if self.is_symbol("*") { "*" } else if self.is_symbol("/") { "/" } else { return Ok(e) };
+            self.next += 1;
+            e = Expr::Binary(op, Box::new(e), Box::new( self.unary()? ));
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        if self.is_symbol("-") {
+            self.next += 1;
+            return Ok( Expr::Negate(Box::new( self.unary()? )) );
+        }
+
+        match self.take() {
+            Some(Token::Number(n)) => Ok( Expr::Number(n) ),
+            Some(Token::Word(ref w)) => match Variable::from_name(w) {
+                Some(v) => Ok( Expr::Variable(v) ),
+                None => Err( format!("unknown variable `{}`", w) )
+            },
+            Some(Token::Symbol("(")) => {
+                let e = self.or()?;
+                self.symbol(")")?;
+                Ok(e)
+            }
+            t => Err( format!("expected a number, variable or `(`, found {}", describe(t.as_ref())) )
+        }
+    }
+}
+
+/// An attribute whose behaviour is a list of rules, so that one can be written in
+/// the config. Every particle starts at `initial`, `mix` spreads other values.
+/// Rules for the same event are applied in order, each seeing what the ones before
+/// it did.
+pub struct RuleAttribute {
+    pub rules: Vec<Rule>,
+    pub initial: f64,
+    pub bounds: Option<(f64, f64)>,
+    values: Vec<f64>,
+}
+
+impl RuleAttribute {
+    // for a bound attribute, expects a config that has been through read_config
+    pub fn configure<R: Rng>(&mut self, rng: &mut R, config: &AttributeConfig) {
+        self.rules = config.rules.iter().flat_map(|rules| rules.iter()).map(|r| Rule::parse(r).unwrap()).collect();
+        self.bounds = config.bounds.as_ref().map(|b| (b[0], b[1]));
+
+        let values = config.values.clone().unwrap();
+        self.initial = values[0];
+        let fractions = config.fractions.clone().unwrap_or(vec![1.0; values.len()]);
+        self.mix(rng, &values, &fractions);
+    }
+
+    // every rule in [[attributes]] parses
+    pub fn validate_config(config: &Config) -> Vec<(&'static str, usize, String)> {
+        let mut errors = vec![];
+        for (n, attribute) in config.attributes.iter().flat_map(|attributes| attributes.iter()).enumerate() {
+            let name = attribute.name.clone().unwrap_or(String::new());
+            for rule in attribute.rules.iter().flat_map(|rules| rules.iter()) {
+                if let Err(message) = Rule::parse(rule) {
+                    errors.push( ("attributes.rules", n, format!("in rule \"{}\" of attribute \"{}\": {}", rule, name, message)) );
+                }
+            }
+        }
+        errors
+    }
+
+    pub fn data(&self) -> &Vec<f64> {
+        &self.values
+    }
+
+    pub fn data_mut(&mut self) -> &mut Vec<f64> {
+        &mut self.values
+    }
+
+    // gives each particle one of values at random, in proportion to fractions
+    pub fn mix<R: Rng>(&mut self, rng: &mut R, values: &[f64], fractions: &[f64]) {
+        let total: f64 = fractions.iter().sum();
+        for value in &mut self.values {
+            let mut r = rng.gen::<f64>() * total;
+            *value = values[values.len() - 1];
+            for (&v, &f) in values.iter().zip( fractions.iter() ) {
+                if r < f {
+                    *value = v;
+                    break;
+                }
+                r -= f;
+            }
+        }
+    }
+}
+
+impl Attribute for RuleAttribute {
+    fn new() -> RuleAttribute {
+        RuleAttribute {
+            rules: vec![],
+            initial: 0.0,
+            bounds: None,
+            values: vec![]
+        }
+    }
+
+    fn name() -> &'static str { "rules" }
+    fn as_any(&self) -> &Any { self }
+    fn as_any_mut(&mut self) -> &mut Any { self }
+    fn scalars(&self) -> Vec<f64> { self.values.clone() }
+    fn collision_listener(&self) -> bool { true }
+
+    fn data_bounds(&self) -> (f64, f64) {
+        match self.bounds {
+            Some(bounds) => bounds,
+            None => (0.0, self.values.iter().cloned().fold(0./0., f64::max))
+        }
+    }
+
+    fn collision_update(&mut self, i: usize, j: usize, _: &Particle, _: &Particle, context: &mut Context) {
+        let impact = context.impact.map_or(0.0, |impact| impact.impact_speed);
+        for rule in self.rules.iter().filter(|r| r.event == Event::Collision) {
+            let mut assignments = vec![];
+            for &(a, b) in &[(i, j), (j, i)] {
+                let values = [self.values[a], self.values[b], context.time, context.dt, impact, 0.0, 0.0, 0.0];
+                for (v, value) in rule.apply(context.rng, &values).unwrap_or(vec![]) {
+                    assignments.push( if v == Variable::A { (1, a, value) } else { (0, b, value) } );
+                }
+            }
+            // assignments to a last, so they win
+            assignments.sort_by_key(|&(order, _, _)| order);
+            for (_, k, value) in assignments {
+                self.values[k] = value;
+            }
+        }
+    }
+
+    fn update(&mut self, i: usize, p: &Particle, context: &mut Context) {
+        let (position, speed) = (p.get_position(), p.get_velocity().magnitude());
+        for rule in self.rules.iter().filter(|r| r.event == Event::Step) {
+            let values = [self.values[i], 0.0, context.time, context.dt, 0.0, speed, position.x, position.y];
+            for (_, value) in rule.apply(context.rng, &values).unwrap_or(vec![]) {
+                self.values[i] = value;
+            }
+        }
+    }
+
+    fn initialise(&mut self, number_of_particles: usize) {
+        self.values = vec![self.initial; number_of_particles];
+    }
+
+    fn set(&mut self, f: f64, i: f64) {
+        let l = self.values.len() as f64;
+        self.values[scale(i, [0.0, 1.0], [0.0, l - 1.0]) as usize] = f;
+    }
+
+    fn reorder(&mut self, order: &[usize]) {
+        self.values = order.iter().map(|&k| self.values[k]).collect();
+    }
+
+    fn push(&mut self) {
+        self.values.push(self.initial);
+    }
+
+    fn swap_remove(&mut self, i: usize) {
+        self.values.swap_remove(i);
+    }
+}
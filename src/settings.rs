@@ -0,0 +1,18 @@
+//! Reading the config with the checks of every module that owns a section of it.
+//! config.rs decodes it and checks what it can on its own, it doesn't depend on the
+//! partitions or attributes so they can use it.
+
+use config::*;
+use spatial_hash::SpatialHash;
+use cell_list::CellList;
+use rules::RuleAttribute;
+
+pub const VALIDATORS: &'static [Validator] = &[SpatialHash::validate_config, CellList::validate_config, RuleAttribute::validate_config];
+
+pub fn read_config(config_path: &str) -> Result<Config, Vec<ConfigError>> {
+    read_config_with(config_path, VALIDATORS)
+}
+
+pub fn parse_config(toml_str: &str) -> Result<Config, Vec<ConfigError>> {
+    parse_config_with(toml_str, VALIDATORS)
+}
@@ -1,229 +1,479 @@
 
+use std::cmp;
+
 use vector::*;
-use particle::Particle;
+use particles::{ParticleStore, ParticleChunk, Curve};
+use flow::{Wall, Boundary, Inflow, Obstacle};
 use collision::*;
-use attribute::Attribute;
+use attribute::{Attribute, AttributeId, AttributeRegistry, Impact, Response, Body};
+use generator::InitialConditions;
+
+use rand::{ChaChaRng, SeedableRng};
+use scoped_pool::Pool;
 
-pub type Probability = Fn() -> f64;
+// every random number in a run comes from the simulator's rng so a seed reproduces it
+pub type SimulatorRng = ChaChaRng;
+
+pub fn seeded_rng(seed: u64) -> SimulatorRng {
+    ChaChaRng::from_seed(&[seed as u32, (seed >> 32) as u32])
+}
 
 pub struct Simulator<T: SpatialPartition> {
     pub spatial_partition: T,
-    pub particles: Vec<Particle>,
+    pub particles: ParticleStore,
     pub radius: f64,
     pub gravity: f64,
     pub restitution: f64,
     pub width: f64,
     pub height: f64,
     pub dt: f64,
-    attributes: Vec<Box<Attribute>>,
-    normal_attributes: Vec<usize>,
-    collision_attributes: Vec<usize>,
-    attribute_id_count: usize,
+    pub seed: u64,
+    pub rng: SimulatorRng,
+    pub obstacles: Vec<Obstacle>,
+    // particles added by inflows and removed by absorbing walls so far
+    pub injected: usize,
+    pub absorbed: usize,
+    pool: Option<Pool>,
+    number_of_threads: usize,
+    attributes: AttributeRegistry,
+    reordering: Option<(Curve, usize)>,
+    steps: usize,
+    // particles were added or removed since the partition was last filled
+    partition_stale: bool,
+    // indexed by Wall
+    boundaries: [Boundary; 4],
+    inflows: Vec<Inflow>,
 }
 
 #[allow(dead_code)]
 impl<T: SpatialPartition> Simulator<T> {
+    // one particle for each position, see generator::InitialConditions for producing them
     pub fn new(spatial_partition: T, 
-        initial_posiiton: (&Probability, &Probability), initial_velocity: (&Probability, &Probability), 
-        number_of_particles: usize, radius: f64, gravity: f64, restitution: f64, 
+        positions: Vec<Vector>, velocities: Vec<Vector>, seed: u64,
+        radius: f64, gravity: f64, restitution: f64, 
         width: f64, height: f64, dt: f64) -> Simulator<T> {
         
         let mut s = Simulator {
             spatial_partition: spatial_partition,
-            particles: vec![],
+            particles: ParticleStore::new(dt),
             radius: radius,
             gravity: gravity,
             restitution: restitution,
             width: width,
             height: height,
             dt: dt,
-            attributes: vec![],
-            normal_attributes: vec![],
-            collision_attributes: vec![],
-            attribute_id_count: 0
+            seed: seed,
+            rng: seeded_rng(seed),
+            obstacles: vec![],
+            injected: 0,
+            absorbed: 0,
+            pool: None,
+            number_of_threads: 1,
+            attributes: AttributeRegistry::new(),
+            reordering: None,
+            steps: 0,
+            partition_stale: false,
+            boundaries: [Boundary::Reflecting; 4],
+            inflows: vec![]
         };
-        s.initial_conditions(initial_posiiton, initial_velocity, number_of_particles, width, height, dt);
+        s.initial_conditions(positions, velocities);
         s
     }
 
-    fn initial_conditions(&mut self, initial_posiiton: (&Probability, &Probability), initial_velocity: (&Probability, &Probability), 
-        number_of_particles: usize, width: f64, height: f64, dt: f64) {
+    // generates the particles with the simulator's own rng, None if they can't be placed
+    pub fn from_initial_conditions(spatial_partition: T, 
+        initial_conditions: &InitialConditions, number_of_particles: usize, seed: u64,
+        radius: f64, gravity: f64, restitution: f64, 
+        width: f64, height: f64, dt: f64) -> Option<Simulator<T>> {
 
-        for i in 0..number_of_particles {
+        let mut s = Simulator::new(spatial_partition, vec![], vec![], seed, radius, gravity, restitution, width, height, dt);
 
-            // random positions and velocities
-            let p_x = (initial_posiiton.0() * (width - 2.0*self.radius ) ) - self.radius;
-            let p_y = (initial_posiiton.1() * (height - 2.0*self.radius ) ) - self.radius;
-            let v_x = (initial_velocity.0() * 500.0) - 250.0;
-            let v_y = (initial_velocity.1() * 500.0) - 250.0;
+        let positions = match initial_conditions.generate_positions(&mut s.rng, number_of_particles, radius) {
+            Some(positions) => positions,
+            None => return None
+        };
+        let velocities = initial_conditions.generate_velocities(&mut s.rng, number_of_particles);
 
-            let position = Vector::new( p_x, height as f64 - p_y );
-            let velocity = Vector::new( v_x, v_y );
+        s.initial_conditions(positions, velocities);
+        Some(s)
+    }
 
-            self.particles.push( Particle::new(position, velocity, dt) );
+    fn initial_conditions(&mut self, positions: Vec<Vector>, velocities: Vec<Vector>) {
+        for (i, (position, velocity)) in positions.into_iter().zip( velocities.into_iter() ).enumerate() {
+            self.particles.push(position, velocity, 1.0, self.radius);
 
             self.spatial_partition.insert(i, position);
         }
     }
     
-    pub fn bind_attribute<A: 'static + Attribute>(&mut self) -> usize {
-        let mut attribute = Box::new( A::new() );
-        attribute.initialise( vec![0.0; self.particles.len()] );
-        let id = self.attribute_id_count;
-        match attribute.collision_listener() {
-            true => self.collision_attributes.push(id),
-            false => self.normal_attributes.push(id)
-        }
-        self.attributes.push(attribute);
-        self.attribute_id_count += 1;
+    // threads used to integrate the particles and by the spatial partition, 1 runs the integration serially
+    pub fn set_number_of_threads(&mut self, number_of_threads: usize) {
+        let number_of_threads = cmp::max(number_of_threads, 1);
+        if let Some(ref pool) = self.pool { pool.shutdown(); }
+        self.pool = if number_of_threads > 1 { Some(Pool::new(number_of_threads)) } else { None };
+        self.number_of_threads = number_of_threads;
+        self.spatial_partition.set_number_of_threads(number_of_threads);
+    }
+
+    // sorts the particles along curve every period steps so that particles close in the
+    // box are close in memory, particles and attribute data move but keep their id
+    pub fn set_reordering(&mut self, curve: Curve, period: usize) {
+        self.reordering = if period > 0 { Some((curve, period)) } else { None };
+    }
+
+    // walls are reflecting until set otherwise
+    pub fn set_boundary(&mut self, wall: Wall, boundary: Boundary) {
+        self.boundaries[wall as usize] = boundary;
+    }
+
+    // the inflow's wall becomes absorbing
+    pub fn add_inflow(&mut self, inflow: Inflow) {
+        self.set_boundary(inflow.wall, Boundary::Absorbing);
+        self.inflows.push(inflow);
+    }
+
+    pub fn add_obstacle(&mut self, obstacle: Obstacle) {
+        self.obstacles.push(obstacle);
+    }
+
+    // binds an attribute under the name of its type, None if that name is taken
+    pub fn bind_attribute<A: 'static + Attribute>(&mut self) -> Option<AttributeId> {
+        self.bind_named_attribute::<A>(A::name())
+    }
+
+    // for more than one attribute of the same type, None if the name is taken
+    pub fn bind_named_attribute<A: 'static + Attribute>(&mut self, name: &str) -> Option<AttributeId> {
+        let number_of_particles = self.particles.len();
+        self.attributes.bind::<A>(name, number_of_particles)
+    }
+
+    pub fn remove_attribute(&mut self, id: AttributeId) -> bool {
+        self.attributes.remove(id)
+    }
+
+    // the first bound attribute of type A
+    pub fn attribute<A: 'static + Attribute>(&self) -> Option<&A> {
+        self.attributes.of_type::<A>()
+    }
+
+    pub fn attribute_mut<A: 'static + Attribute>(&mut self) -> Option<&mut A> {
+        self.attributes.of_type_mut::<A>()
+    }
+
+    // for setting an attribute up with random numbers that the seed reproduces
+    pub fn attribute_and_rng_mut<A: 'static + Attribute>(&mut self) -> Option<(&mut A, &mut SimulatorRng)> {
+        let rng = &mut self.rng;
+        self.attributes.of_type_mut::<A>().map(|a| (a, rng))
+    }
+
+    // as attribute_and_rng_mut for the attribute with id, None if it isn't an A
+    pub fn attribute_by_id_and_rng_mut<A: 'static + Attribute>(&mut self, id: AttributeId) -> Option<(&mut A, &mut SimulatorRng)> {
+        let rng = &mut self.rng;
+        self.attributes.get_mut(id).and_then(|a| a.as_any_mut().downcast_mut::<A>()).map(|a| (a, rng))
+    }
+
+    pub fn attribute_by_name(&self, name: &str) -> Option<&Attribute> {
+        self.attributes.by_name(name)
+    }
+
+    pub fn attribute_by_id(&self, id: AttributeId) -> Option<&Attribute> {
+        self.attributes.get(id)
+    }
+
+    pub fn attributes(&self) -> &AttributeRegistry {
+        &self.attributes
+    }
+
+    // false if there is no such attribute
+    pub fn set_attribute(&mut self, id: AttributeId, f: f64, i: f64) -> bool {
+        match self.attributes.get_mut(id) {
+            Some(a) => { a.set(f, i); true }
+            None => false
+        }
+    }
+
+    // adds a particle in every attribute's default state and returns its id, it takes part from the next update
+    pub fn insert_particle(&mut self, p: Vector, v: Vector) -> usize {
+        let radius = self.radius;
+        let id = self.particles.push(p, v, 1.0, radius);
+        self.attributes.push();
+        self.partition_stale = true;
         id
     }
-    
-    pub fn attribute(&self, id: usize) -> &Box<Attribute> {
-        &self.attributes[id]
+
+    // removes the particle with id, false if there is no such particle. The last particle
+    // moves into its place, so indices change but ids don't, see particle_index
+    pub fn remove_particle(&mut self, id: usize) -> bool {
+        match self.particles.slot(id) {
+            Some(i) => {
+                self.remove_index(i);
+                true
+            }
+            None => false
+        }
     }
-    
-    pub fn set_attribute(&mut self, id: usize, f: f64, i: f64) {
-        self.attributes[id].set(f, i);
+
+    fn remove_index(&mut self, i: usize) {
+        self.particles.swap_remove(i);
+        self.attributes.swap_remove(i);
+        self.partition_stale = true;
     }
 
-    pub fn insert_particle(&mut self, p: Vector, v: Vector) {
-        self.particles.push( Particle::new(p, v, self.dt) );
+    // index into particles and attribute data of the particle with id
+    pub fn particle_index(&self, id: usize) -> Option<usize> {
+        self.particles.slot(id)
+    }
+
+    pub fn number_of_particles(&self) -> usize {
+        self.particles.len()
     }
 
     pub fn velocities(&self) -> Vec<f64> {
-        let mut vs = Vec::new();
-        for p in &self.particles {
-            let v = p.get_velocity();
-            vs.push(v.magnitude());
-        }
-        vs
+        (0..self.particles.len()).map(|i| self.particles.velocity(i).magnitude()).collect()
     }
     
     pub fn positions(&self) -> Vec<Vector> {
-        let mut ps = Vec::new();
-        for p in &self.particles {
-            ps.push( p.get_position() );
-        }
-        ps
+        self.particles.positions()
     }
 
     // total engery in system
     pub fn total_energy(&self) -> f64 {
         let mut energy = 0.0;
-        for p in &self.particles {
-            let v = p.get_velocity();
-            energy += 0.5 * v.dot(v);
+        for i in 0..self.particles.len() {
+            let v = self.particles.velocity(i);
+            energy += 0.5 * self.particles.mass[i] * v.dot(v);
         }
         energy
     }
 
-    fn naive_collision_check(&self) -> Vec<Collision> {
-        let mut collisions = Vec::new();
-
-        for i in 0..self.particles.len() {
-            let p = self.particles[i];
-            let p_position = p.get_position();
+    // solves collisions by applying impulse and adjusting particle locations, the
+    // lighter particle of a pair moves more and a fixed one not at all
+    // collisions come back sorted, so they are resolved and seen by the attributes
+    // in the same order whatever the number of threads
+    fn solve_collisions(&mut self) {
+        let collisions = self.spatial_partition.collision_check_parallel();
+        // second particles of merged pairs, removed once every collision is resolved
+        let mut merged = vec![false; self.particles.len()];
 
-            for j in (i+1)..self.particles.len() {
-                let q = self.particles[j];
-                let q_position = q.get_position();
+        for c in collisions {
+            if merged[c.p1] || merged[c.p2] {
+                continue;
+            }
+            let particles = &mut self.particles;
 
-                let normal = (q_position - p_position).normalise();
-                let penetration = 2.0*self.radius - p_position.distance( q_position );
+            // the attributes see the particles as they were before the collision
+            let (p, q) = (particles.particle(c.p1), particles.particle(c.p2));
+                    
+            let (p_position, q_position) = (particles.position(c.p1), particles.position(c.p2));
+            let (p_velocity, q_velocity) = (particles.velocity(c.p1), particles.velocity(c.p2));
+            let inverse_mass = |i: usize| if particles.fixed[i] { 0.0 } else { 1.0 / particles.mass[i] };
+            let (w1, w2) = (inverse_mass(c.p1), inverse_mass(c.p2));
+            let normal = c.normal;
+            let penetration = c.penetration;
+            let relative_velocity = q_velocity - p_velocity;
+            let mut impact = Impact {
+                normal: normal,
+                penetration: penetration,
+                impact_speed: -relative_velocity.dot(normal),
+                impulse: 0.0,
+                kinetic_energy_lost: 0.0,
+                reduced_mass: if w1 + w2 > 0.0 { 1.0 / (w1 + w2) } else { 0.0 }
+            };
 
-                // if circles are overlapping
-                if penetration > 0.0 {
+            let restitution = match self.attributes.collision_response(c.p1, c.p2, impact, self.steps, self.dt, &mut self.rng) {
+                Some(Response::Pass) => continue,
+                Some(Response::Bounce(restitution)) => restitution,
+                Some(Response::Merge) => {
+                    // one particle at the centre of mass carrying both momenta
+                    let (m1, m2) = (particles.mass[c.p1], particles.mass[c.p2]);
+                    let m = m1 + m2;
+                    particles.mass[c.p1] = m;
+                    particles.set_position( c.p1, (m1 * p_position + m2 * q_position) / m );
+                    particles.set_velocity( c.p1, (m1 * p_velocity + m2 * q_velocity) / m );
+                    merged[c.p2] = true;
 
-                    // add collision
-                    collisions.push( Collision::new(i, j, penetration, normal) );
+                    impact.impulse = impact.reduced_mass * impact.impact_speed;
+                    impact.kinetic_energy_lost = 0.5 * m1 * m2 / m * relative_velocity.dot(relative_velocity);
+                    self.attributes.collision_update(c.p1, c.p2, &p, &q, impact, self.steps, self.dt, &mut self.rng);
+                    continue;
                 }
+                None => 1.0
+            };
+
+            // two fixed particles stay overlapped
+            if w1 + w2 > 0.0 {
+                // adjust particle positions
+                let scale = 0.8;
+                let slop = 0.0001;
+                let correction = f64::max( penetration - slop, 0.0 ) * scale * normal;
+                particles.set_position( c.p1, p_position - correction * (w1 / (w1 + w2)) );
+                particles.set_position( c.p2, q_position + correction * (w2 / (w1 + w2)) );
+            }
+
+            // applying impulse
+            if relative_velocity.dot(normal) < 0.0 && w1 + w2 > 0.0 {
+                let j = -(1.0 + restitution) * relative_velocity.dot( normal ) / (w1 + w2);
+                particles.set_velocity( c.p1, p_velocity - j * w1 * normal );
+                particles.set_velocity( c.p2, q_velocity + j * w2 * normal );
+                impact.impulse = j;
+                impact.kinetic_energy_lost = j * impact.impact_speed - 0.5 * j * j * (w1 + w2);
             }
+
+            // update attributes 
+            self.attributes.collision_update(c.p1, c.p2, &p, &q, impact, self.steps, self.dt, &mut self.rng);
         }
 
-        collisions
+        // from the back, so the particles still to be removed don't move
+        for i in (0..merged.len()).rev() {
+            if merged[i] {
+                self.remove_index(i);
+            }
+        }
     }
 
+    // lets the attributes change each particle's velocity, mass and whether it is fixed
+    fn act(&mut self) {
+        if self.attributes.is_empty() {
+            return;
+        }
 
-    // solves collisions by applying impulse and adjusting particle locations
-    fn solve_collisions(&mut self) {
-        let collisions = self.spatial_partition.collision_check_parallel();
+        for i in 0..self.particles.len() {
+            let before = Body { velocity: self.particles.velocity(i), mass: self.particles.mass[i], fixed: self.particles.fixed[i] };
+            let mut body = before;
+            self.attributes.act(i, &mut body, self.steps, self.dt, &mut self.rng);
 
-        for c in collisions {
-            
-            // update attributes 
-            for a in &self.collision_attributes { 
-                self.attributes[*a].collision_update(c.p1, c.p2, &self.particles[c.p1], &self.particles[c.p2]);
+            // only what changed, setting the velocity moves the previous position by round-off
+            if body.velocity != before.velocity {
+                self.particles.set_velocity(i, body.velocity);
             }
-                    
-            let p = self.particles[c.p1];
-            let q = self.particles[c.p2];
-            let normal = c.normal;
-            let penetration = c.penetration;
+            self.particles.mass[i] = body.mass;
+            self.particles.fixed[i] = body.fixed;
+        }
+    }
 
-            // adjust particle positions
-            let scale = 0.8;
-            let slop = 0.0001;
-            let correction = (f64::max( penetration - slop, 0.0 ) / 2.0) * scale * normal;
-            self.particles[c.p1].set_position( p.get_position() - correction );
-            self.particles[c.p2].set_position( q.get_position() + correction );
+    // verlet step and boundary check, each particle is independent so chunks of
+    // particles are handed to the pool when there is one
+    fn integrate(&mut self) {
+        let gravity = Vector::new(0.0, self.gravity);
+        let (dt, restitution, width, height) = (self.dt, self.restitution, self.width, self.height);
+        let boundaries = self.boundaries;
 
-            // applying impulse
-            let relative_velocity = q.get_velocity() - p.get_velocity();
-            if relative_velocity.dot(normal) < 0.0 {
-                let j = -( relative_velocity ).dot( normal );
-                self.particles[c.p1].set_velocity( p.get_velocity() - j * normal );
-                self.particles[c.p2].set_velocity( q.get_velocity() + j * normal );
+        let step = |mut chunk: ParticleChunk| {
+            chunk.verlet(gravity, dt);
+            chunk.boundary_check(restitution, width, height, &boundaries);
+        };
+
+        let particles = &mut self.particles;
+        let number_of_particles = particles.len();
+
+        match self.pool {
+            Some(ref pool) => {
+                let number_of_tasks = self.number_of_threads * TASKS_PER_THREAD;
+                let chunk_size = (number_of_particles + number_of_tasks - 1) / number_of_tasks;
+                let step = &step;
+
+                pool.scoped(|scoped| {
+                    for chunk in particles.chunks(chunk_size) {
+                        scoped.execute(move || step(chunk));
+                    }
+                });
+            }
+            None => {
+                for chunk in particles.chunks(number_of_particles) {
+                    step(chunk);
+                }
             }
         }
     }
-    
-    fn boundary_check(p: &mut Particle, radius: f64, restitution: f64, width: f64, height: f64) {
 
-        // for p in &mut self.particles {
-        let position = p.get_position();
-        let velocity = p.get_velocity();
-        if position.x - radius < 0.0 {
-            p.set_position( Vector::new( radius, position.y ) );
-            p.set_velocity( Vector::new( velocity.x.abs()*restitution, velocity.y ) );
-        }
-        let position = p.get_position();
-        let velocity = p.get_velocity();
-        if position.x + radius > width as f64 {
-            p.set_position( Vector::new( width as f64 - radius, position.y ) );
-            p.set_velocity( Vector::new( - velocity.x.abs()*restitution, velocity.y ) );
+    // removes particles that crossed an absorbing wall, from the back so the
+    // particles still to be looked at don't move
+    fn absorb(&mut self) {
+        let (left, right, bottom, top) = (
+            self.boundaries[Wall::Left as usize] == Boundary::Absorbing,
+            self.boundaries[Wall::Right as usize] == Boundary::Absorbing,
+            self.boundaries[Wall::Bottom as usize] == Boundary::Absorbing,
+            self.boundaries[Wall::Top as usize] == Boundary::Absorbing
+        );
+
+        for i in (0..self.particles.len()).rev() {
+            let (x, y) = (self.particles.x[i], self.particles.y[i]);
+            if (left && x < 0.0) || (right && x > self.width) || (bottom && y < 0.0) || (top && y > self.height) {
+                self.remove_index(i);
+                self.absorbed += 1;
+            }
         }
-        let position = p.get_position();
-        let velocity = p.get_velocity();
-        if position.y - radius < 0.0 {
-            p.set_position( Vector::new( position.x, radius ) );
-            p.set_velocity( Vector::new( velocity.x, velocity.y.abs()*restitution ) );
+    }
+
+    fn inject(&mut self) {
+        let mut new_particles = vec![];
+        for inflow in &self.inflows {
+            new_particles.append( &mut inflow.inject(&mut self.rng, &self.particles, self.width, self.height) );
         }
-        let position = p.get_position();
-        let velocity = p.get_velocity();
-        if position.y + radius > height as f64 {
-            p.set_position( Vector::new( position.x, height as f64 - radius ) );
-            p.set_velocity( Vector::new( velocity.x, - velocity.y.abs()*restitution ) );
+
+        self.injected += new_particles.len();
+        for (p, v) in new_particles {
+            self.insert_particle(p, v);
         }
     }
 
+    // sorts the particles along the curve, see set_reordering
+    fn reorder(&mut self) {
+        if let Some((curve, period)) = self.reordering {
+            if self.steps % period == 0 {
+                let order = self.particles.curve_order(curve, self.width, self.height);
+                self.particles.permute(&order);
+                self.attributes.reorder(&order);
+            }
+        }
+    }
 
-    // call from main loop
+    // call from main loop. A step resolves the collisions, asking the attributes how
+    // each one is resolved and then telling them about it, lets the attributes act on
+    // the particles, moves them, and then updates the attributes with where they are
     pub fn update(&mut self) {
+        // the partition still holds the old indices
+        if self.partition_stale {
+            self.spatial_partition.clear();
+            for i in 0..self.particles.len() {
+                self.spatial_partition.insert(i, self.particles.position(i));
+            }
+            self.partition_stale = false;
+        }
+
         self.solve_collisions();
 
         self.spatial_partition.clear();
 
+        self.act();
+
         // apply gravity
-        for (i, mut p) in &mut self.particles.iter_mut().enumerate() {
-            p.verlet( Vector::new(0.0, self.gravity) );
-            Self::boundary_check(p, self.radius, self.restitution, self.width, self.height);
-            self.spatial_partition.insert(i, p.get_position());
+        self.integrate();
+
+        for obstacle in &mut self.obstacles {
+            obstacle.collide(&mut self.particles, self.dt);
+        }
+        self.absorb();
+        self.inject();
+
+        self.steps += 1;
+        self.reorder();
+
+        for i in 0..self.particles.len() {
+            self.spatial_partition.insert(i, self.particles.position(i));
             
-            for a in &self.normal_attributes {
-                self.attributes[*a].update(i, &p);
-            }
+            let p = self.particles.particle(i);
+            self.attributes.update(i, &p, self.steps, self.dt, &mut self.rng);
         }
+        self.attributes.step(self.steps, self.dt, &mut self.rng);
+        self.partition_stale = false;
     }
 
 }
+
+// the pool's threads would otherwise outlive it
+impl<T: SpatialPartition> Drop for Simulator<T> {
+    fn drop(&mut self) {
+        if let Some(ref pool) = self.pool { pool.shutdown(); }
+    }
+}
@@ -1,24 +1,58 @@
 use std::cmp;
+use std::f64;
+use std::time::{Duration, Instant};
 
 use collision::*;
 use vector::Vector;
-use scoped_pool::{Pool, Scope};
+use common::{balanced_ranges, start_pool};
+use query::*;
+use defaults::DEFAULT_NUMBER_OF_THREADS;
+use config::{Config, partition_geometry};
+use scoped_pool::Pool;
+
+// objects expected in a cell when the grid is sized from the density
+pub const OBJECTS_PER_CELL: f64 = 4.0;
+
+// collision checks timed on a grid before it is compared with the previous one
+const TUNING_SAMPLES: usize = 10;
+// how much finer or coarser the next grid tried is
+const TUNING_STEP: f64 = 1.25;
+// checks to wait before trying other grids again once neither direction was faster
+const TUNING_PERIOD: usize = 1000;
+
+// runtime re-tuning, the grid is resized from the density when the number of objects
+// changes a lot, and otherwise walks finer or coarser while the checks get faster
+struct Tuning {
+    number_of_objects: usize,
+    sized_for: usize,
+    samples: usize,
+    elapsed: f64,
+    previous: Option<((usize, usize), f64)>,
+    step: f64,
+    failures: usize,
+    wait: usize,
+}
 
 pub struct SpatialHash {
     pub cells: Vec<Vec<(usize, Vector)>>,
     pub number_of_columns: usize,
     pub number_of_rows: usize,
+    width: f64,
+    height: f64,
     cell_width: f64,
     cell_height: f64,
     radius: f64,
     collisions: Vec<Collision>,
     pool: Option<Pool>,
+    number_of_threads: usize,
+    tuning: Option<Tuning>,
+    pending: Option<(usize, usize)>,
+    number_of_objects: usize,
 }
 
 impl SpatialHash {
     pub fn new(width: f64, height: f64, number_of_columns: usize, number_of_rows: usize, radius: f64) -> Option<SpatialHash> {
-        let max_number_of_columns = (width / radius.ceil()) as usize;
-        let max_number_of_rows = (height / radius.ceil()) as usize;
+        let (max_number_of_columns, max_number_of_rows) = SpatialHash::maximum_grid(width, height, radius);
         
         if number_of_rows > max_number_of_rows || number_of_columns > max_number_of_columns {
             None
@@ -28,14 +62,162 @@ impl SpatialHash {
                 cells: vec![vec![]; (number_of_columns+2)*(number_of_rows+2)],
                 number_of_columns: number_of_columns,
                 number_of_rows: number_of_rows,
+                width: width,
+                height: height,
                 cell_width: width / number_of_columns as f64,
                 cell_height: height / number_of_rows as f64,
                 radius: radius,
                 collisions: Vec::with_capacity(10000),
-                pool: Some(Pool::new(4))
+                pool: None,
+                number_of_threads: DEFAULT_NUMBER_OF_THREADS,
+                tuning: None,
+                pending: None,
+                number_of_objects: 0
+            })
+        }
+    }
+
+    // grid sized for the expected number of objects, never None
+    pub fn auto(width: f64, height: f64, radius: f64, number_of_objects: usize) -> SpatialHash {
+        let (number_of_columns, number_of_rows) = SpatialHash::grid_for_density(width, height, radius, number_of_objects);
+        SpatialHash::new(width, height, number_of_columns, number_of_rows, radius).unwrap()
+    }
+
+    // finest grid SpatialHash::new accepts, a single cell is always allowed, (columns, rows)
+    pub fn maximum_grid(width: f64, height: f64, radius: f64) -> (usize, usize) {
+        let max_number_of_columns = cmp::max((width / radius.ceil()) as usize, 1);
+        let max_number_of_rows = cmp::max((height / radius.ceil()) as usize, 1);
+
+        (max_number_of_columns, max_number_of_rows)
+    }
+
+    // the [spatial_hash] rows and columns are within maximum_grid, those left out always are
+    pub fn validate_config(config: &Config) -> Vec<(&'static str, usize, String)> {
+        let mut errors = vec![];
+        if let (Some(c), Some((width, height, radius))) = (config.spatial_hash.as_ref(), partition_geometry(config)) {
+            let (max_number_of_columns, max_number_of_rows) = SpatialHash::maximum_grid(width, height, radius);
+
+            if let Some(rows) = c.number_of_rows {
+                if rows == 0 || rows > max_number_of_rows {
+                    errors.push( ("spatial_hash.number_of_rows", 0,
+                        format!("`spatial_hash.number_of_rows` must be between 1 and {} for radius {} and height {}, got {}", max_number_of_rows, radius, height, rows)) );
+                }
+            }
+            if let Some(columns) = c.number_of_columns {
+                if columns == 0 || columns > max_number_of_columns {
+                    errors.push( ("spatial_hash.number_of_columns", 0,
+                        format!("`spatial_hash.number_of_columns` must be between 1 and {} for radius {} and width {}, got {}", max_number_of_columns, radius, width, columns)) );
+                }
+            }
+        }
+        errors
+    }
+
+    // square cells holding about OBJECTS_PER_CELL objects, but at least a diameter wide
+    // so most objects are only in one cell, (columns, rows)
+    pub fn grid_for_density(width: f64, height: f64, radius: f64, number_of_objects: usize) -> (usize, usize) {
+        let side = if number_of_objects == 0 {
+            width.max(height)
+        }
+        else {
+            (OBJECTS_PER_CELL * width * height / number_of_objects as f64).sqrt()
+        };
+        let side = side.max(2.0*radius);
+
+        clamp_grid(((width / side).round() as usize, (height / side).round() as usize), SpatialHash::maximum_grid(width, height, radius))
+    }
+
+    // re-tunes the grid at runtime, the collisions found don't depend on the grid so
+    // this only changes how long the checks take
+    pub fn auto_tune(mut self, enabled: bool) -> Self {
+        self.tuning = if enabled {
+            Some(Tuning {
+                number_of_objects: 0,
+                sized_for: 0,
+                samples: 0,
+                elapsed: 0.0,
+                previous: None,
+                step: TUNING_STEP,
+                failures: 0,
+                wait: 0
             })
         }
+        else {
+            None
+        };
+        self
+    }
+
+    // only called from clear(), while the cells are empty
+    fn resize(&mut self, number_of_columns: usize, number_of_rows: usize) {
+        self.cells = vec![vec![]; (number_of_columns+2)*(number_of_rows+2)];
+        self.number_of_columns = number_of_columns;
+        self.number_of_rows = number_of_rows;
+        self.cell_width = self.width / number_of_columns as f64;
+        self.cell_height = self.height / number_of_rows as f64;
+    }
+
+    // after every TUNING_SAMPLES checks the mean time is compared with the grid before,
+    // a slower grid is undone and the other direction is tried
+    fn record(&mut self, elapsed: Duration) {
+        let grid = (self.number_of_columns, self.number_of_rows);
+        let maximum = SpatialHash::maximum_grid(self.width, self.height, self.radius);
+
+        let next = match self.tuning {
+            Some(ref mut t) => {
+                if t.wait > 0 {
+                    t.wait -= 1;
+                    return;
+                }
+
+                t.samples += 1;
+                t.elapsed += elapsed.as_secs() as f64 + elapsed.subsec_nanos() as f64 * 1e-9;
+                if t.samples < TUNING_SAMPLES {
+                    return;
+                }
+
+                let mean = t.elapsed / t.samples as f64;
+                t.samples = 0;
+                t.elapsed = 0.0;
+
+                match t.previous {
+                    Some((previous_grid, previous_mean)) if previous_mean <= mean => {
+                        t.previous = None;
+                        t.step = 1.0 / t.step;
+                        t.failures += 1;
+                        if t.failures >= 2 {
+                            t.failures = 0;
+                            t.wait = TUNING_PERIOD;
+                        }
+                        previous_grid
+                    },
+                    previous => {
+                        if previous.is_some() {
+                            t.failures = 0;
+                        }
+
+                        let next = clamp_grid(scale_grid(grid, t.step), maximum);
+                        if next == grid {
+                            // can't go any further this way
+                            t.previous = None;
+                            t.step = 1.0 / t.step;
+                            t.failures += 1;
+                        }
+                        else {
+                            t.previous = Some((grid, mean));
+                        }
+                        next
+                    }
+                }
+            },
+            None => return
+        };
+
+        if next != grid {
+            self.pending = Some(next);
+        }
     }
+
     fn in_cell(&self, v: Vector) -> (i32, i32) {
         let c = (v.x / self.cell_width) as i32;
         let r = (v.y / self.cell_height) as i32;
@@ -69,42 +251,82 @@ impl SpatialHash {
         b1 && b2 && b3 && b4
     }
     
-    fn check_collisions_in_quadrant(&self, row: i32, column: i32, rows: usize, columns: usize) -> Vec<Collision> {
+    // cell containing v, may be outside the padded grid
+    fn cell_of(&self, v: Vector) -> (i32, i32) {
+        ((v.y / self.cell_height).floor() as i32, (v.x / self.cell_width).floor() as i32)
+    }
+
+    // every object stored in a cell overlapping the box, each once and sorted by index
+    // every object is stored in the cell holding its centre, or in a border cell
+    fn objects_in_box(&self, min: Vector, max: Vector) -> Vec<(usize, Vector)> {
+        let (r0, c0) = self.cell_of(min);
+        let (r1, c1) = self.cell_of(max);
+        let (r0, c0) = (cmp::max(r0, -1), cmp::max(c0, -1));
+        let (r1, c1) = (cmp::min(r1, self.number_of_rows as i32), cmp::min(c1, self.number_of_columns as i32));
+
+        let mut objects = Vec::new();
+        for r in r0..(r1+1) {
+            for c in c0..(c1+1) {
+                objects.extend( self.cells[self.get_cell_index(r, c)].iter().cloned() );
+            }
+        }
+
+        objects.sort_by_key(|&(i, _)| i);
+        objects.dedup_by_key(|o| o.0);
+        objects
+    }
+
+    fn objects_in_circle(&self, centre: Vector, radius: f64) -> Vec<(usize, Vector)> {
+        let extent = Vector::new(radius, radius);
+        let mut objects = self.objects_in_box(centre - extent, centre + extent);
+        objects.retain(|&(_, p)| p.distance(centre) <= radius);
+        objects
+    }
+
+    // checks cells [start, end) of the padded grid
+    fn check_collisions_in_cells(&self, start: usize, end: usize) -> Vec<Collision> {
         let mut collisions = Vec::with_capacity(1000);
-        for r in 0..rows {
-            for c in 0..columns {
-                let c = &self.cells[self.get_cell_index(r as i32 + row, c as i32 + column)];
+        for c in &self.cells[start..end] {
+            for i in 0..c.len() {
+                let (index1, p_position) = c[i];
                 
-                for i in 0..c.len() {
-                    let (index1, p_position) = c[i];
+                for j in (i+1)..c.len() {
+                    let (index2, q_position) =  c[j];
                     
-                    for j in (i+1)..c.len() {
-                        let (index2, q_position) =  c[j];
-                        
-                        let normal = (q_position - p_position).normalise();
-                        let penetration = 2.0*self.radius - p_position.distance( q_position );
-                        
-                        // if circles are overlapping
-                        if penetration > 0.0 {
-                            // add collision
-                            collisions.push( Collision::new(index1, index2, penetration, normal) );
-                        }
+                    let normal = (q_position - p_position).normalise();
+                    let penetration = 2.0*self.radius - p_position.distance( q_position );
+                    
+                    // if circles are overlapping
+                    if penetration > 0.0 {
+                        // add collision
+                        collisions.push( Collision::new(index1, index2, penetration, normal) );
                     }
                 }
             }
         }
-        collisions.sort();
-        collisions.dedup();
         collisions
     }
-    
+}
 
+// a finer grid for a step above 1, (columns, rows)
+fn scale_grid(grid: (usize, usize), step: f64) -> (usize, usize) {
+    ((grid.0 as f64 * step).round() as usize, (grid.1 as f64 * step).round() as usize)
+}
+
+fn clamp_grid(grid: (usize, usize), maximum: (usize, usize)) -> (usize, usize) {
+    (cmp::max(cmp::min(grid.0, maximum.0), 1), cmp::max(cmp::min(grid.1, maximum.1), 1))
 }
 
 impl SpatialPartition for SpatialHash {
     fn insert(&mut self, index: usize, v: Vector) {
+        self.number_of_objects += 1;
+        if let Some(ref mut t) = self.tuning {
+            t.number_of_objects += 1;
+        }
+
+        // objects outside the box go in the border cells, or the padding around them
         let (row, column) = self.in_cell(v);
-        let (r, c) = (cmp::min(row, self.number_of_rows as i32 -1), cmp::min(column, self.number_of_columns as i32 -1));
+        let (r, c) = (cmp::max(cmp::min(row, self.number_of_rows as i32 -1), 0), cmp::max(cmp::min(column, self.number_of_columns as i32 -1), 0));
         
         if self.contained(r, c, v) {
             let cell_index = self.get_cell_index(r as i32, c as i32);
@@ -122,14 +344,44 @@ impl SpatialPartition for SpatialHash {
         }
     }
 
+    // grid changes from the tuning are applied here, while the cells are empty
     fn clear(&mut self) {
         self.collisions.clear();
+        self.number_of_objects = 0;
         for c in &mut self.cells {
             c.clear();
         }
+
+        let mut resized = None;
+        if let Some(ref mut t) = self.tuning {
+            let n = t.number_of_objects;
+            t.number_of_objects = 0;
+
+            if t.sized_for == 0 {
+                t.sized_for = n;
+            }
+            else if n > 0 && (n as f64 > 1.5 * t.sized_for as f64 || 1.5 * (n as f64) < t.sized_for as f64) {
+                t.sized_for = n;
+                t.samples = 0;
+                t.elapsed = 0.0;
+                t.previous = None;
+                t.failures = 0;
+                resized = Some(n);
+            }
+        }
+
+        if let Some(n) = resized {
+            self.pending = Some( SpatialHash::grid_for_density(self.width, self.height, self.radius, n) );
+        }
+
+        if let Some((number_of_columns, number_of_rows)) = self.pending.take() {
+            self.resize(number_of_columns, number_of_rows);
+        }
     }
 
     fn collision_check(&mut self) -> &Vec<Collision> {
+        let start = Instant::now();
+
         for c in &self.cells {
             for i in 0..c.len() {
                 let (index1, p_position) = c[i];
@@ -153,33 +405,39 @@ impl SpatialPartition for SpatialHash {
         self.collisions.sort();
         self.collisions.dedup();
 
+        self.record(start.elapsed());
+
         &self.collisions
     }
     
+    // the cells are split into ranges with about the same number of pairs to check,
+    // each range is a task and the results are appended in range order
     fn collision_check_parallel(&mut self) -> &Vec<Collision> {
-        
-        let mut c1 = Vec::new();
-        let mut c2 = Vec::new();
-        let mut c3 = Vec::new();
-        let mut c4 = Vec::new();
+        let start = Instant::now();
 
+        let costs = self.cells.iter().map(|c| c.len() * c.len()).collect();
+        let ranges = balanced_ranges(&costs, self.number_of_threads * TASKS_PER_THREAD);
+        let mut results = vec![Vec::new(); ranges.len()];
+
+        start_pool(&mut self.pool, self.number_of_threads);
         if let Some(ref p) = self.pool {
+            let spatial_hash = &*self;
             p.scoped(|scoped| {
-                scoped.execute(|| { c1 = self.check_collisions_in_quadrant(0, 0, self.number_of_rows/2, self.number_of_columns/2) });
-                scoped.execute(|| { c2 = self.check_collisions_in_quadrant(self.number_of_rows as i32/2, 0, self.number_of_rows/2, self.number_of_columns/2) });
-                scoped.execute(|| { c3 = self.check_collisions_in_quadrant(0, self.number_of_columns as i32/2, self.number_of_rows/2, self.number_of_columns/2) });
-                scoped.execute(|| { c4 = self.check_collisions_in_quadrant(self.number_of_rows as i32/2, self.number_of_columns as i32/2, self.number_of_rows/2, self.number_of_columns/2) });
+                for (result, &(start, end)) in results.iter_mut().zip( ranges.iter() ) {
+                    scoped.execute(move || { *result = spatial_hash.check_collisions_in_cells(start, end) });
+                }
             });
-            
-            self.collisions.append( &mut c1 );
-            self.collisions.append( &mut c2 );
-            self.collisions.append( &mut c3 );
-            self.collisions.append( &mut c4 );
+        }
+
+        for mut result in results {
+            self.collisions.append( &mut result );
         }
         
         self.collisions.sort();
         self.collisions.dedup();
 
+        self.record(start.elapsed());
+
         &self.collisions
     }
     
@@ -212,4 +470,92 @@ impl SpatialPartition for SpatialHash {
 
         (&self.collisions, comparisons)
     }
+
+    fn set_number_of_threads(&mut self, number_of_threads: usize) {
+        let number_of_threads = cmp::max(number_of_threads, 1);
+        // the new pool is started by the next parallel check
+        if let Some(ref pool) = self.pool { pool.shutdown(); }
+        self.pool = None;
+        self.number_of_threads = number_of_threads;
+    }
+}
+
+impl SpatialQuery for SpatialHash {
+    fn len(&self) -> usize {
+        self.number_of_objects
+    }
+
+    fn query_circle(&self, centre: Vector, radius: f64) -> Vec<usize> {
+        self.objects_in_circle(centre, radius).iter().map(|&(i, _)| i).collect()
+    }
+
+    fn query_rectangle(&self, min: Vector, max: Vector) -> Vec<usize> {
+        self.objects_in_box(min, max).iter()
+            .filter(|&&(_, p)| p.x >= min.x && p.x <= max.x && p.y >= min.y && p.y <= max.y)
+            .map(|&(i, _)| i).collect()
+    }
+
+    fn nearest(&self, position: Vector, k: usize) -> Vec<usize> {
+        let initial_radius = self.cell_width.min(self.cell_height);
+        nearest_by_circles(position, k, self.number_of_objects, initial_radius, |radius| self.objects_in_circle(position, radius))
+    }
+
+    // walks the cells along the ray, an object hit inside a cell is stored in that cell
+    // so the first cell with a hit before the ray leaves it holds the nearest hit
+    fn ray_cast(&self, origin: Vector, direction: Vector, max_distance: f64) -> Option<RayHit> {
+        let direction = direction.normalise();
+        let min = Vector::new(-self.cell_width, -self.cell_height);
+        let max = Vector::new((self.number_of_columns + 1) as f64 * self.cell_width, (self.number_of_rows + 1) as f64 * self.cell_height);
+
+        let (enter, leave) = match ray_box(origin, direction, min, max) {
+            Some(t) => t,
+            None => return None
+        };
+        let leave = leave.min(max_distance);
+
+        let start = origin + direction * enter;
+        let (mut r, mut c) = self.cell_of(start);
+        r = cmp::max(cmp::min(r, self.number_of_rows as i32), -1);
+        c = cmp::max(cmp::min(c, self.number_of_columns as i32), -1);
+
+        let step_c = if direction.x > 0.0 { 1 } else { -1 };
+        let step_r = if direction.y > 0.0 { 1 } else { -1 };
+        let boundary = |cell: i32, step: i32, size: f64| (if step > 0 { cell + 1 } else { cell }) as f64 * size;
+        let mut next_c = if direction.x == 0.0 { f64::INFINITY } else { (boundary(c, step_c, self.cell_width) - origin.x) / direction.x };
+        let mut next_r = if direction.y == 0.0 { f64::INFINITY } else { (boundary(r, step_r, self.cell_height) - origin.y) / direction.y };
+        let delta_c = (self.cell_width / direction.x).abs();
+        let delta_r = (self.cell_height / direction.y).abs();
+
+        let mut best = None;
+        loop {
+            for &(index, position) in &self.cells[self.get_cell_index(r, c)] {
+                best = closest(best, hit(index, position, self.radius, origin, direction, max_distance));
+            }
+
+            let exit = next_c.min(next_r);
+            if let Some(h) = best {
+                if h.distance <= exit { break; }
+            }
+            if exit > leave { break; }
+
+            if next_c < next_r {
+                c += step_c;
+                next_c += delta_c;
+            }
+            else {
+                r += step_r;
+                next_r += delta_r;
+            }
+            if r < -1 || c < -1 || r > self.number_of_rows as i32 || c > self.number_of_columns as i32 { break; }
+        }
+
+        best
+    }
+}
+
+// the pool's threads would otherwise outlive it
+impl Drop for SpatialHash {
+    fn drop(&mut self) {
+        if let Some(ref pool) = self.pool { pool.shutdown(); }
+    }
 }
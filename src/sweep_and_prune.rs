@@ -0,0 +1,213 @@
+use std::cmp;
+
+use collision::*;
+use vector::Vector;
+use common::{balanced_ranges, start_pool};
+use defaults::DEFAULT_NUMBER_OF_THREADS;
+use scoped_pool::Pool;
+
+// keeps the objects sorted along one axis, two objects can only collide if their
+// projections onto that axis are within a diameter of each other
+// the order from the previous frame is kept across clear() so re-sorting the
+// slightly moved objects with an insertion sort is close to linear
+// the sorted objects are kept as separate index, x and y arrays, the objects an
+// object can collide with are a run of them checked in one vectorised pass
+pub struct SweepAndPrune {
+    pub indices: Vec<usize>,
+    pub xs: Vec<f64>,
+    pub ys: Vec<f64>,
+    pub axis_x: bool,
+    radius: f64,
+    positions: Vec<Vector>,
+    present: Vec<bool>,
+    in_order: Vec<bool>,
+    order: Vec<usize>,
+    collisions: Vec<Collision>,
+    pool: Option<Pool>,
+    number_of_threads: usize,
+}
+
+impl SweepAndPrune {
+    // sorts along the longer side of the box, which spreads the projections out the most
+    pub fn new(width: f64, height: f64, radius: f64) -> SweepAndPrune {
+        SweepAndPrune {
+            indices: vec![],
+            xs: vec![],
+            ys: vec![],
+            axis_x: width >= height,
+            radius: radius,
+            positions: vec![],
+            present: vec![],
+            in_order: vec![],
+            order: vec![],
+            collisions: Vec::with_capacity(10000),
+            pool: None,
+            number_of_threads: DEFAULT_NUMBER_OF_THREADS
+        }
+    }
+
+    fn key(&self, v: Vector) -> f64 {
+        if self.axis_x { v.x } else { v.y }
+    }
+
+    // drops objects that weren't inserted this frame, then insertion sorts what is left
+    fn sort(&mut self) {
+        {
+            let present = &self.present;
+            let in_order = &mut self.in_order;
+            self.order.retain(|&i| {
+                if !present[i] { in_order[i] = false; }
+                present[i]
+            });
+        }
+
+        for k in 1..self.order.len() {
+            let index = self.order[k];
+            let key = self.key(self.positions[index]);
+            let mut j = k;
+            while j > 0 && self.key(self.positions[self.order[j-1]]) > key {
+                self.order[j] = self.order[j-1];
+                j -= 1;
+            }
+            self.order[j] = index;
+        }
+
+        self.indices.clear();
+        self.xs.clear();
+        self.ys.clear();
+        for &i in &self.order {
+            self.indices.push(i);
+            self.xs.push(self.positions[i].x);
+            self.ys.push(self.positions[i].y);
+        }
+    }
+
+    // one past the last sorted object within a diameter of object i along the axis
+    fn run_end(&self, i: usize) -> usize {
+        let keys = if self.axis_x { &self.xs } else { &self.ys };
+        let limit = keys[i] + 2.0*self.radius;
+        let mut end = i + 1;
+        while end < keys.len() && keys[end] <= limit {
+            end += 1;
+        }
+        end
+    }
+
+    // sweeps from every object in [start, end) along the sorted list
+    fn sweep(&self, start: usize, end: usize, mut comparisons: Option<&mut Vec<(usize, usize)>>) -> Vec<Collision> {
+        let mut collisions = Vec::new();
+        let mut penetrations_buffer = Vec::new();
+        for i in start..end {
+            let p_position = Vector::new(self.xs[i], self.ys[i]);
+            let run_end = self.run_end(i);
+            penetrations(p_position.x, p_position.y, &self.xs[i+1..run_end], &self.ys[i+1..run_end], 2.0*self.radius, &mut penetrations_buffer);
+
+            for (k, &penetration) in penetrations_buffer.iter().enumerate() {
+                let j = i + 1 + k;
+
+                if let Some(ref mut comparisons) = comparisons { comparisons.push((i, j)); }
+
+                // if circles are overlapping
+                if penetration > 0.0 {
+                    let normal = (Vector::new(self.xs[j], self.ys[j]) - p_position).normalise();
+                    // add collision
+                    collisions.push( Collision::new(self.indices[i], self.indices[j], penetration, normal) );
+                }
+            }
+        }
+        collisions
+    }
+}
+
+impl SpatialPartition for SweepAndPrune {
+    fn insert(&mut self, index: usize, v: Vector) {
+        if index >= self.positions.len() {
+            self.positions.resize(index + 1, Vector::zero());
+            self.present.resize(index + 1, false);
+            self.in_order.resize(index + 1, false);
+        }
+
+        self.positions[index] = v;
+        self.present[index] = true;
+
+        if !self.in_order[index] {
+            self.in_order[index] = true;
+            self.order.push(index);
+        }
+    }
+
+    // keeps the order for the next frame
+    fn clear(&mut self) {
+        self.collisions.clear();
+        self.indices.clear();
+        self.xs.clear();
+        self.ys.clear();
+        for &i in &self.order {
+            self.present[i] = false;
+        }
+    }
+
+    fn collision_check(&mut self) -> &Vec<Collision> {
+        self.sort();
+
+        let mut collisions = self.sweep(0, self.indices.len(), None);
+        self.collisions.append( &mut collisions );
+        self.collisions.sort();
+
+        &self.collisions
+    }
+
+    // the sorted list is split into runs of starting objects, each run is a task
+    fn collision_check_parallel(&mut self) -> &Vec<Collision> {
+        self.sort();
+
+        let costs = vec![1; self.indices.len()];
+        let ranges = balanced_ranges(&costs, self.number_of_threads * TASKS_PER_THREAD);
+        let mut results = vec![Vec::new(); ranges.len()];
+
+        start_pool(&mut self.pool, self.number_of_threads);
+        if let Some(ref p) = self.pool {
+            let sweep_and_prune = &*self;
+            p.scoped(|scoped| {
+                for (result, &(start, end)) in results.iter_mut().zip( ranges.iter() ) {
+                    scoped.execute(move || { *result = sweep_and_prune.sweep(start, end, None) });
+                }
+            });
+        }
+
+        for mut result in results {
+            self.collisions.append( &mut result );
+        }
+
+        self.collisions.sort();
+
+        &self.collisions
+    }
+
+    fn collision_check_with_comparisons(&mut self) -> (&Vec<Collision>, Vec<(usize, usize)>) {
+        self.sort();
+
+        let mut comparisons = Vec::new();
+        let mut collisions = self.sweep(0, self.indices.len(), Some(&mut comparisons));
+        self.collisions.append( &mut collisions );
+
+        self.collisions.sort();
+
+        (&self.collisions, comparisons)
+    }
+
+    fn set_number_of_threads(&mut self, number_of_threads: usize) {
+        let number_of_threads = cmp::max(number_of_threads, 1);
+        // the new pool is started by the next parallel check
+        if let Some(ref pool) = self.pool { pool.shutdown(); }
+        self.pool = None;
+        self.number_of_threads = number_of_threads;
+    }
+}
+
+// the pool's threads would otherwise outlive it
+impl Drop for SweepAndPrune {
+    fn drop(&mut self) {
+        if let Some(ref pool) = self.pool { pool.shutdown(); }
+    }
+}
@@ -7,7 +7,7 @@ use std::ops::Div;
 use std::ops::Neg;
 
 
-#[derive(Copy, Clone, PartialEq)]
+#[derive(Copy, Clone, PartialEq, Default)]
 pub struct Vector {
     pub x: f64,
     pub y: f64
@@ -0,0 +1,254 @@
+#[macro_use]
+extern crate boltzmann;
+extern crate rand;
+
+use boltzmann::simulator::Simulator;
+use boltzmann::naive::Naive;
+use boltzmann::vector::Vector;
+use boltzmann::attribute::*;
+use boltzmann::particle::Particle;
+use boltzmann::common::scale;
+
+use rand::Rng;
+
+// two particles heading into each other
+fn simulator() -> Simulator<Naive> {
+    let positions = vec![Vector::new(10.0, 10.0), Vector::new(12.5, 10.0)];
+    let velocities = vec![Vector::new(100.0, 0.0), Vector::new(-100.0, 0.0)];
+    Simulator::new(Naive::new(1.0), positions, velocities, 1, 1.0, 0.0, 1.0, 100.0, 100.0, 0.001)
+}
+
+#[test]
+fn attributes_found_by_type_and_name() {
+    let mut s = simulator();
+    let speed = s.bind_attribute::<speed_attr>().unwrap();
+    let tag = s.bind_named_attribute::<tag_attr>("tag").unwrap();
+
+    assert_eq!(s.attributes().id("speed_attr"), Some(speed));
+    assert_eq!(s.attributes().id("tag"), Some(tag));
+    assert!(s.attribute::<virus_attr>().is_none());
+    assert!(s.attribute_by_name("virus").is_none());
+
+    s.set_attribute(tag, 1.0, 0.0);
+    s.update();
+
+    assert_eq!(s.attribute::<tag_attr>().unwrap().data(), &s.attribute_by_name("tag").unwrap().scalars());
+    assert!(s.attribute::<speed_attr>().unwrap().data()[0] > 0.0);
+}
+
+#[test]
+fn attributes_of_the_same_type_under_different_names() {
+    let mut s = simulator();
+    let first = s.bind_named_attribute::<tag_attr>("first").unwrap();
+    let second = s.bind_named_attribute::<tag_attr>("second").unwrap();
+    assert!(s.bind_named_attribute::<virus_attr>("first").is_none());
+
+    s.set_attribute(first, 1.0, 0.0);
+    s.set_attribute(second, 1.0, 1.0);
+
+    let tags = s.attributes().all_of_type::<tag_attr>();
+    assert_eq!(tags.len(), 2);
+    assert_eq!((tags[0].0, tags[1].0), (first, second));
+    assert_eq!(tags[0].1.data(), &vec![1.0, 0.0]);
+    assert_eq!(tags[1].1.data(), &vec![0.0, 1.0]);
+}
+
+#[test]
+fn removed_attributes_leave_the_others_alone() {
+    let mut s = simulator();
+    let virus = s.bind_attribute::<virus_attr>().unwrap();
+    let tag = s.bind_attribute::<tag_attr>().unwrap();
+    s.set_attribute(tag, 1.0, 0.0);
+
+    assert!(s.remove_attribute(virus));
+    assert!(!s.remove_attribute(virus));
+    assert!(!s.set_attribute(virus, 1.0, 0.0));
+    assert!(s.attribute_by_id(virus).is_none());
+    assert_eq!(s.attributes().ids(), vec![tag]);
+
+    // the name can be bound again, with a new id
+    let again = s.bind_attribute::<virus_attr>().unwrap();
+    assert!(again != virus);
+
+    // the particles collide and swap tags
+    for _ in 0..20 {
+        s.update();
+    }
+    assert_eq!(s.attribute::<tag_attr>().unwrap().data(), &vec![0.0, 1.0]);
+}
+
+// a state that isn't a float, how many collisions a particle has been in and its last partner
+#[derive(Clone, Default, PartialEq, Debug)]
+pub struct Contacts {
+    count: usize,
+    last: Option<usize>,
+}
+
+impl State for Contacts {
+    fn to_scalar(&self) -> f64 { self.count as f64 }
+    fn from_scalar(f: f64) -> Contacts { Contacts { count: f as usize, last: None } }
+}
+
+impl_collision_attribute! { contacts_attr: Contacts, self, i, j, p1, p2,
+    {
+        self.t[i].count += 1;
+        self.t[i].last = Some(j);
+        self.t[j].count += 1;
+        self.t[j].last = Some(i);
+    }
+}
+
+#[test]
+fn attributes_hold_any_state() {
+    let mut s = simulator();
+    s.bind_attribute::<contacts_attr>().unwrap();
+    let virus = s.bind_attribute::<virus_attr>().unwrap();
+    s.set_attribute(virus, 1.0, 0.0);
+
+    for _ in 0..20 {
+        s.update();
+    }
+
+    let contacts = s.attribute::<contacts_attr>().unwrap();
+    assert!(contacts.data()[0].count > 0);
+    assert_eq!(contacts.data()[0].last, Some(1));
+    assert_eq!(contacts.scalars()[1], contacts.data()[1].count as f64);
+
+    // the first hit only exposes
+    let infection = s.attribute::<virus_attr>().unwrap().data();
+    assert_eq!(infection[0], Infection::Infected);
+    assert!(infection[1] != Infection::Healthy);
+}
+
+// when each particle was last hit, and how hard
+impl_collision_attribute! { hit_time_attr, self, i, j, p1, p2, context,
+    {
+        self.t[i] = context.time;
+        self.t[j] = context.time;
+    }
+}
+
+impl_collision_attribute! { impact_attr, self, i, j, p1, p2, context,
+    {
+        let impact = context.impact.unwrap();
+        self.t[i] = impact.impact_speed;
+        self.t[j] = impact.impulse;
+    }
+}
+
+// a particle is marked once it has gone a tenth of a second without being hit
+impl_attribute! { rested_attr, self, i, p, context,
+    {
+        let hit = context.attribute::<hit_time_attr>().map(|a| a.data()[i]).unwrap_or(0.0);
+        self.t[i] = if context.time - hit > 0.1 { 1.0 } else { 0.0 };
+    }
+}
+
+// draws from the simulator's rng, so the run is reproduced by the seed
+impl_attribute! { noise_attr, self, i, p, context,
+    {
+        self.t[i] = context.rng.gen::<f64>();
+    }
+}
+
+#[test]
+fn attributes_see_the_time_and_the_impact() {
+    let mut s = simulator();
+    s.bind_attribute::<hit_time_attr>().unwrap();
+    s.bind_attribute::<impact_attr>().unwrap();
+    s.bind_attribute::<rested_attr>().unwrap();
+
+    for _ in 0..20 {
+        s.update();
+    }
+    let hit = s.attribute::<hit_time_attr>().unwrap().data()[0];
+    assert!(hit > 0.0 && hit < 0.02);
+    assert_eq!(s.attribute::<hit_time_attr>().unwrap().data()[1], hit);
+
+    // closing at 200, both particles have unit mass
+    let impact = s.attribute::<impact_attr>().unwrap().data();
+    assert!((impact[0] - 200.0).abs() < 1e-6);
+    assert!((impact[1] - 200.0).abs() < 1e-6);
+
+    assert_eq!(s.attribute::<rested_attr>().unwrap().data(), &vec![0.0, 0.0]);
+    for _ in 0..200 {
+        s.update();
+    }
+    assert_eq!(s.attribute::<rested_attr>().unwrap().data(), &vec![1.0, 1.0]);
+}
+
+#[test]
+fn attributes_draw_from_the_seeded_rng() {
+    let run = || {
+        let mut s = simulator();
+        s.bind_attribute::<noise_attr>().unwrap();
+        for _ in 0..5 {
+            s.update();
+        }
+        s.attribute::<noise_attr>().unwrap().data().clone()
+    };
+    let noise = run();
+    assert!(noise[0] != noise[1]);
+    assert_eq!(noise, run());
+}
+
+// attributes that change the physics are written with impl_attribute_common
+impl_attribute_common! { sticky_attr, f64, {
+    fn collision_listener(&self) -> bool { false }
+    fn collision_response(&mut self, _: usize, _: usize, _: &mut Context) -> Option<Response> { Some(Response::Bounce(0.0)) }
+} }
+
+impl_attribute_common! { ghost_attr, f64, {
+    fn collision_listener(&self) -> bool { false }
+    fn collision_response(&mut self, _: usize, _: usize, _: &mut Context) -> Option<Response> { Some(Response::Pass) }
+} }
+
+// particles set to 1 are held in place and the rest lose a tenth of their speed every step
+impl_attribute_common! { quarantine_attr, f64, {
+    fn collision_listener(&self) -> bool { false }
+    fn act(&mut self, i: usize, body: &mut Body, _: &mut Context) {
+        if self.t[i] == 1.0 { body.fixed = true; } else { body.velocity = body.velocity * 0.9; }
+    }
+} }
+
+#[test]
+fn attributes_decide_how_collisions_are_resolved() {
+    let mut sticky = simulator();
+    sticky.bind_attribute::<sticky_attr>().unwrap();
+    let mut ghost = simulator();
+    ghost.bind_attribute::<ghost_attr>().unwrap();
+    // the first attribute bound decides
+    ghost.bind_attribute::<sticky_attr>().unwrap();
+
+    for _ in 0..20 {
+        sticky.update();
+        ghost.update();
+    }
+
+    let v = sticky.particles.velocity(0);
+    assert!(v.x.abs() < 1e-6 && sticky.particles.velocity(1).x.abs() < 1e-6);
+
+    assert!(ghost.particles.x[0] > ghost.particles.x[1]);
+    assert!((ghost.particles.velocity(0).x - 100.0).abs() < 1e-6);
+}
+
+#[test]
+fn attributes_act_on_the_particles() {
+    let mut s = simulator();
+    let quarantine = s.bind_attribute::<quarantine_attr>().unwrap();
+    s.set_attribute(quarantine, 1.0, 1.0);
+    let x = s.particles.x[1];
+
+    s.update();
+    assert!(s.particles.velocity(0).x < 100.0 && s.particles.velocity(0).x > 0.0);
+
+    for _ in 0..20 {
+        s.update();
+    }
+    assert!(s.particles.fixed[1]);
+    assert_eq!(s.particles.x[1], x);
+    assert_eq!(s.particles.velocity(1).x, 0.0);
+
+    // bounced off the fixed particle
+    assert!(s.particles.velocity(0).x < 0.0);
+}
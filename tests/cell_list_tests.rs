@@ -0,0 +1,86 @@
+extern crate boltzmann;
+extern crate rand;
+
+use boltzmann::cell_list::CellList;
+use boltzmann::collision::*;
+use boltzmann::vector::Vector;
+
+fn collision_check(radius: f64, particles: &Vec<Vector>) -> Vec<(usize, usize)> {
+    let mut collisions = Vec::new();
+
+    for i in 0..particles.len() {
+        for j in (i+1)..particles.len() {
+            if 2.0*radius - particles[i].distance( particles[j] ) > 0.0 {
+                collisions.push( (i, j) );
+            }
+        }
+    }
+
+    collisions
+}
+
+// the cell list doesn't sort, so compare as sets
+fn collision_pairs(collisions: &Vec<Collision>) -> Vec<(usize, usize)> {
+    let mut pairs: Vec<(usize, usize)> = collisions.iter().map(|c| (c.p1, c.p2)).collect();
+    pairs.sort();
+    pairs
+}
+
+#[test]
+fn cell_list_test_collisions_512x512() {
+    let mut cell_list = CellList::new(512.0, 512.0, 128, 128, 2.0).unwrap();
+
+    let mut particles = Vec::new();
+
+    for i in 0..10000 {
+        let x = rand::random::<f64>() * 512.0;
+        let y = rand::random::<f64>() * 512.0;
+        let p = Vector::new(x, y);
+        particles.push( p );
+
+        cell_list.insert( i, p );
+    }
+
+    let expected = collision_check(2.0, &particles);
+
+    assert_eq!(collision_pairs(cell_list.collision_check()), expected);
+}
+
+// particles just outside the box belong to the border cells
+#[test]
+fn cell_list_test_collisions_outside_box() {
+    let mut cell_list = CellList::new(100.0, 100.0, 50, 50, 1.0).unwrap();
+
+    let mut particles = Vec::new();
+
+    for i in 0..2000 {
+        let x = rand::random::<f64>() * 104.0 - 2.0;
+        let y = rand::random::<f64>() * 104.0 - 2.0;
+        let p = Vector::new(x, y);
+        particles.push( p );
+
+        cell_list.insert( i, p );
+    }
+
+    let expected = collision_check(1.0, &particles);
+    let serial = cell_list.collision_check().clone();
+
+    cell_list.clear();
+    for i in 0..particles.len() {
+        cell_list.insert( i, particles[i] );
+    }
+    cell_list.set_number_of_threads(3);
+
+    assert_eq!(collision_pairs(&serial), expected);
+
+    // same order too, not just the same pairs
+    let parallel: Vec<(usize, usize)> = cell_list.collision_check_parallel().iter().map(|c| (c.p1, c.p2)).collect();
+    let serial: Vec<(usize, usize)> = serial.iter().map(|c| (c.p1, c.p2)).collect();
+    assert_eq!(parallel, serial);
+}
+
+#[test]
+fn cell_list_too_fine() {
+    assert!(CellList::new(100.0, 100.0, 51, 50, 1.0).is_none());
+    assert!(CellList::new(100.0, 100.0, 0, 50, 1.0).is_none());
+}
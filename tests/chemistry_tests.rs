@@ -0,0 +1,133 @@
+extern crate boltzmann;
+
+use boltzmann::simulator::*;
+use boltzmann::spatial_hash::SpatialHash;
+use boltzmann::naive::Naive;
+use boltzmann::generator::*;
+use boltzmann::vector::Vector;
+use boltzmann::attribute::Attribute;
+use boltzmann::heat::Heat;
+use boltzmann::chemistry::*;
+
+fn gas(seed: u64, temperature: f64) -> Simulator<SpatialHash> {
+    let hash = SpatialHash::new(100.0, 100.0, 10, 10, 1.0).unwrap();
+    let initial_conditions = InitialConditions::new(100.0, 100.0).velocities(VelocityDistribution::Maxwellian(temperature));
+    let mut simulator = Simulator::from_initial_conditions(hash, &initial_conditions, 1000, seed, 1.0, 0.0, 1.0, 100.0, 100.0, 0.001).unwrap();
+    simulator.bind_attribute::<Chemistry>().unwrap();
+    simulator
+}
+
+// two particles heading into each other, a and b, that merge into c or become c and d
+fn pair(merge: bool) -> Simulator<Naive> {
+    let positions = vec![Vector::new(10.0, 10.0), Vector::new(12.5, 10.0)];
+    let velocities = vec![Vector::new(100.0, 0.0), Vector::new(-50.0, 0.0)];
+    let mut s = Simulator::new(Naive::new(1.0), positions, velocities, 1, 1.0, 0.0, 1.0, 100.0, 100.0, 0.001);
+    s.bind_attribute::<Chemistry>().unwrap();
+    {
+        let chemistry = s.attribute_mut::<Chemistry>().unwrap();
+        let (a, b, c, d) = (chemistry.add_species("a"), chemistry.add_species("b"), chemistry.add_species("c"), chemistry.add_species("d"));
+        chemistry.add_reaction( Reaction::new((a, b), if merge { Products::One(c) } else { Products::Two(c, d) }) );
+        chemistry.data_mut()[1].species = b;
+    }
+    s
+}
+
+#[test]
+fn merging_conserves_mass_and_momentum() {
+    let mut s = pair(true);
+    for _ in 0..20 {
+        s.update();
+    }
+
+    assert_eq!(s.number_of_particles(), 1);
+    // the drawing sizes its buffers from these, they must all shrink together
+    assert_eq!(s.positions().len(), 1);
+    assert_eq!(s.attribute::<Chemistry>().unwrap().scalars().len(), 1);
+    assert_eq!(s.particles.mass[0], 2.0);
+    assert!((s.particles.velocity(0).x - 25.0).abs() < 1e-6);
+
+    let chemistry = s.attribute::<Chemistry>().unwrap();
+    assert_eq!(chemistry.counts(), vec![0, 0, 1, 0]);
+    assert_eq!(chemistry.history().iter().map(|sample| sample.reactions[0]).sum::<usize>(), 1);
+
+    // or the product has unit mass and the same momentum
+    let mut s = pair(true);
+    s.attribute_mut::<Chemistry>().unwrap().product_mass = Some(1.0);
+    for _ in 0..20 {
+        s.update();
+    }
+    assert_eq!(s.particles.mass[0], 1.0);
+    assert!((s.particles.velocity(0).x - 50.0).abs() < 1e-6);
+}
+
+#[test]
+fn two_products_change_species_in_place() {
+    let mut s = pair(false);
+    for _ in 0..20 {
+        s.update();
+    }
+
+    assert_eq!(s.number_of_particles(), 2);
+    assert_eq!(s.attribute::<Chemistry>().unwrap().counts(), vec![0, 0, 1, 1]);
+    let species: Vec<usize> = s.attribute::<Chemistry>().unwrap().data().iter().map(|m| m.species).collect();
+    assert_eq!(species, vec![2, 3]);
+}
+
+// counts every collision, and the fraction energetic enough should be exp(-Ea/kT)
+fn reactions(activation_energy: f64) -> usize {
+    let mut s = gas(3, 2500.0);
+    {
+        let chemistry = s.attribute_mut::<Chemistry>().unwrap();
+        let a = chemistry.add_species("a");
+        chemistry.add_reaction( Reaction::new((a, a), Products::Two(a, a)).activation_energy(activation_energy) );
+    }
+    for _ in 0..300 {
+        s.update();
+    }
+    s.attribute::<Chemistry>().unwrap().history().iter().map(|sample| sample.reactions[0]).sum()
+}
+
+#[test]
+fn reactions_follow_the_arrhenius_law() {
+    let all = reactions(0.0) as f64;
+    let energetic = reactions(2500.0) as f64;
+
+    assert!(all > 1000.0);
+    assert!((energetic / all - (-1.0f64).exp()).abs() < 0.05);
+}
+
+#[test]
+fn species_are_mixed_in_proportion() {
+    let mut s = gas(4, 2500.0);
+    let (chemistry, rng) = s.attribute_and_rng_mut::<Chemistry>().unwrap();
+    chemistry.add_species("a");
+    chemistry.add_species("b");
+    chemistry.mix(rng, &[3.0, 1.0]);
+
+    let counts = chemistry.counts();
+    assert_eq!(counts[0] + counts[1], 1000);
+    assert!(counts[0] > 700 && counts[0] < 800);
+    assert_eq!(chemistry.data_bounds(), (0.0, 1.0));
+}
+
+#[test]
+fn reactions_happen_when_another_attribute_decides_the_bounce() {
+    let positions = vec![Vector::new(10.0, 10.0), Vector::new(12.5, 10.0)];
+    let velocities = vec![Vector::new(100.0, 0.0), Vector::new(-50.0, 0.0)];
+    let mut s = Simulator::new(Naive::new(1.0), positions, velocities, 1, 1.0, 0.0, 1.0, 100.0, 100.0, 0.001);
+    s.bind_attribute::<Heat>().unwrap();
+    s.attribute_mut::<Heat>().unwrap().restitution = 0.9;
+    s.bind_attribute::<Chemistry>().unwrap();
+    {
+        let chemistry = s.attribute_mut::<Chemistry>().unwrap();
+        let (a, b, c) = (chemistry.add_species("a"), chemistry.add_species("b"), chemistry.add_species("c"));
+        chemistry.add_reaction( Reaction::new((a, b), Products::One(c)) );
+        chemistry.data_mut()[1].species = b;
+    }
+    for _ in 0..20 {
+        s.update();
+    }
+
+    assert_eq!(s.number_of_particles(), 1);
+    assert_eq!(s.attribute::<Chemistry>().unwrap().counts(), vec![0, 0, 1]);
+}
@@ -0,0 +1,411 @@
+extern crate boltzmann;
+
+use boltzmann::config::*;
+use boltzmann::settings::parse_config;
+use boltzmann::partition;
+
+#[test]
+fn config_defaults() {
+    let config = parse_config("
+number_of_particles = 100
+dt = 0.001
+radius = 1.0
+width = 100
+height = 100
+").unwrap();
+
+    assert_eq!(config.number_of_data_points, Some(DEFAULT_NUMBER_OF_DATA_POINTS));
+    assert_eq!(config.gravity, Some(DEFAULT_GRAVITY));
+    assert_eq!(config.restitution, Some(DEFAULT_RESTITUTION));
+    assert_eq!(config.collisions, Some(DEFAULT_COLLISIONS.to_string()));
+    assert_eq!(config.print_statistics, Some(DEFAULT_PRINT_STATISTICS));
+}
+
+#[test]
+fn config_reports_every_error() {
+    let errors = parse_config("
+number_of_particles = 100
+dt = -0.001
+radius = 1.0
+width = 100
+restitution = 2.0
+collisions = \"octree\"
+").unwrap_err();
+
+
+    assert_eq!(errors.len(), 4);
+    assert!(errors.contains(&ConfigError::new(None, "missing required field `height`".to_string())));
+    assert!(errors.iter().any(|e| e.line == Some(3) && e.message.contains("`dt`")));
+    assert!(errors.iter().any(|e| e.line == Some(6) && e.message.contains("`restitution`")));
+    assert!(errors.iter().any(|e| e.line == Some(7) && e.message.contains("`collisions`")));
+}
+
+#[test]
+fn config_wrong_types_and_unknown_fields() {
+    let errors = parse_config("
+number_of_particles = 100
+dt = 1
+radius = \"big\"
+width = 100
+height = 100
+gravty = 9.8
+
+[spatial_hash]
+number_of_rows = 10
+number_of_colums = 10
+").unwrap_err();
+
+
+    assert_eq!(errors.len(), 4);
+    assert!(errors.iter().any(|e| e.line == Some(3) && e.message.contains("`dt`")));
+    assert!(errors.iter().any(|e| e.line == Some(4) && e.message.contains("`radius`")));
+    assert!(errors.iter().any(|e| e.line == Some(7) && e.message.contains("`gravty`")));
+    assert!(errors.iter().any(|e| e.line == Some(11) && e.message.contains("`spatial_hash.number_of_colums`")));
+}
+
+#[test]
+fn config_spatial_hash_too_fine_for_radius() {
+    let errors = parse_config("
+number_of_particles = 100
+dt = 0.001
+radius = 2.0
+width = 100
+height = 100
+
+[spatial_hash]
+number_of_rows = 10
+number_of_columns = 60
+").unwrap_err();
+
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].line, Some(10));
+}
+
+#[test]
+fn config_too_many_particles() {
+    let errors = parse_config("
+number_of_particles = 10000
+dt = 0.001
+radius = 1.0
+width = 100
+height = 100
+").unwrap_err();
+
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].line, Some(2));
+}
+
+#[test]
+fn config_reordering() {
+    let config = parse_config("
+number_of_particles = 100
+dt = 0.001
+radius = 1.0
+width = 100
+height = 100
+
+[reordering]
+period = 50
+").unwrap();
+
+    let reordering = config.reordering.unwrap();
+    assert_eq!(reordering.curve, Some(DEFAULT_REORDERING_CURVE.to_string()));
+    assert_eq!(reordering.period, Some(50));
+
+    let errors = parse_config("
+number_of_particles = 100
+dt = 0.001
+radius = 1.0
+width = 100
+height = 100
+
+[reordering]
+curve = \"peano\"
+period = 0
+").unwrap_err();
+
+    assert_eq!(errors.len(), 2);
+    assert!(errors.iter().any(|e| e.line == Some(9) && e.message.contains("`reordering.curve`")));
+    assert!(errors.iter().any(|e| e.line == Some(10) && e.message.contains("`reordering.period`")));
+}
+
+#[test]
+fn config_flow() {
+    let errors = parse_config("
+number_of_particles = 100
+dt = 0.001
+radius = 1.0
+width = 100
+height = 100
+
+[flow]
+inflow = \"front\"
+drift = [1.0]
+outflow = [\"right\", \"back\"]
+obstacles = [[50.0, 50.0, 10.0], [20.0, 20.0, -1.0]]
+").unwrap_err();
+
+
+    assert_eq!(errors.len(), 5);
+    assert!(errors.iter().any(|e| e.line == Some(9) && e.message.contains("`flow.inflow`")));
+    assert!(errors.iter().any(|e| e.line == Some(9) && e.message.contains("`flow.density` is required")));
+    assert!(errors.iter().any(|e| e.line == Some(10) && e.message.contains("`flow.drift`")));
+    assert!(errors.iter().any(|e| e.line == Some(11) && e.message.contains("\"back\"")));
+    assert!(errors.iter().any(|e| e.line == Some(12) && e.message.contains("radius must be positive")));
+}
+
+#[test]
+fn config_epidemic() {
+    let config = parse_config("
+number_of_particles = 100
+dt = 0.001
+radius = 1.0
+width = 100
+height = 100
+
+[epidemic]
+incubation_time = 0.5
+").unwrap();
+
+    let epidemic = config.epidemic.unwrap();
+    assert_eq!(epidemic.transmission, Some(DEFAULT_TRANSMISSION));
+    assert_eq!(epidemic.incubation_time, Some(0.5));
+    assert_eq!(epidemic.infected, Some(DEFAULT_INITIALLY_INFECTED));
+
+    let errors = parse_config("
+number_of_particles = 100
+dt = 0.001
+radius = 1.0
+width = 100
+height = 100
+
+[epidemic]
+transmission = 1.5
+recovery_time = 0.0
+vaccinated = -0.1
+").unwrap_err();
+
+    assert_eq!(errors.len(), 3);
+    assert!(errors.iter().any(|e| e.line == Some(9) && e.message.contains("`epidemic.transmission`")));
+    assert!(errors.iter().any(|e| e.line == Some(10) && e.message.contains("`epidemic.recovery_time`")));
+    assert!(errors.iter().any(|e| e.line == Some(11) && e.message.contains("`epidemic.vaccinated`")));
+}
+
+#[test]
+fn config_heat() {
+    let config = parse_config("
+number_of_particles = 100
+dt = 0.001
+radius = 1.0
+width = 100
+height = 100
+
+[heat]
+restitution = 0.9
+").unwrap();
+
+    let heat = config.heat.unwrap();
+    assert_eq!(heat.conductance, Some(DEFAULT_CONDUCTANCE));
+    assert_eq!(heat.restitution, Some(0.9));
+    assert_eq!(heat.inelastic_heating, Some(DEFAULT_INELASTIC_HEATING));
+
+    let errors = parse_config("
+number_of_particles = 100
+dt = 0.001
+radius = 1.0
+width = 100
+height = 100
+
+[heat]
+conductance = 2.0
+restitution = 1.1
+internal_energy = -1.0
+").unwrap_err();
+
+    assert_eq!(errors.len(), 3);
+    assert!(errors.iter().any(|e| e.line == Some(9) && e.message.contains("`heat.conductance`")));
+    assert!(errors.iter().any(|e| e.line == Some(10) && e.message.contains("`heat.restitution`")));
+    assert!(errors.iter().any(|e| e.line == Some(11) && e.message.contains("`heat.internal_energy`")));
+}
+
+#[test]
+fn config_chemistry() {
+    let config = parse_config("
+number_of_particles = 100
+dt = 0.001
+radius = 1.0
+width = 100
+height = 100
+
+[chemistry]
+species = [\"a\", \"b\", \"c\"]
+fractions = [0.5, 0.5, 0.0]
+
+[[chemistry.reactions]]
+reactants = [\"a\", \"b\"]
+products = [\"c\"]
+").unwrap();
+
+    let chemistry = config.chemistry.unwrap();
+    assert_eq!(chemistry.conserve_mass, Some(DEFAULT_CONSERVE_MASS));
+    let reactions = chemistry.reactions.unwrap();
+    assert_eq!(reactions.len(), 1);
+    assert_eq!(reactions[0].activation_energy, Some(DEFAULT_ACTIVATION_ENERGY));
+
+    let errors = parse_config("
+number_of_particles = 100
+dt = 0.001
+radius = 1.0
+width = 100
+height = 100
+
+[chemistry]
+species = [\"a\", \"b\"]
+fractions = [1.0]
+
+[[chemistry.reactions]]
+reactants = [\"a\", \"x\"]
+products = [\"a\", \"b\", \"a\"]
+activation_energy = -1.0
+").unwrap_err();
+
+
+    assert_eq!(errors.len(), 4);
+    assert!(errors.iter().any(|e| e.line == Some(10) && e.message.contains("`chemistry.fractions`")));
+    assert!(errors.iter().any(|e| e.line == Some(13) && e.message.contains("`reactants`")));
+    assert!(errors.iter().any(|e| e.line == Some(14) && e.message.contains("`products`")));
+    assert!(errors.iter().any(|e| e.line == Some(15) && e.message.contains("`activation_energy`")));
+}
+
+#[test]
+fn config_attributes() {
+    let config = parse_config("
+number_of_particles = 100
+dt = 0.001
+radius = 1.0
+width = 100
+height = 100
+
+[[attributes]]
+name = \"rumour\"
+rules = [\"on collision if a == 1 and b == 0 then b = 1 with probability 0.5\"]
+").unwrap();
+
+    let attributes = config.attributes.unwrap();
+    assert_eq!(attributes.len(), 1);
+    assert_eq!(attributes[0].values, Some(vec![DEFAULT_ATTRIBUTE_VALUE]));
+
+    let errors = parse_config("
+number_of_particles = 100
+dt = 0.001
+radius = 1.0
+width = 100
+height = 100
+
+[[attributes]]
+name = \"rumour\"
+values = [0.0, 1.0]
+fractions = [1.0]
+rules = [\"on step if b == 0 then a = 1\"]
+
+[[attributes]]
+name = \"rumour\"
+
+[[attributes]]
+name = \"heat\"
+").unwrap_err();
+
+    assert_eq!(errors.len(), 4);
+    assert!(errors.iter().any(|e| e.line == Some(11) && e.message.contains("`fractions`")));
+    assert!(errors.iter().any(|e| e.line == Some(12) && e.message.contains("`b` can't be used in a step rule")));
+    assert!(errors.iter().any(|e| e.line == Some(15) && e.message.contains("more than one attribute")));
+    assert!(errors.iter().any(|e| e.line == Some(18) && e.message.contains("can't be named \"heat\"")));
+}
+
+#[test]
+fn config_errors_in_later_entries() {
+    let errors = parse_config("
+number_of_particles = 100
+dt = 0.001
+radius = 1.0
+width = 100
+height = 100
+
+[chemistry]
+species = [\"a\", \"b\"]
+
+[[chemistry.reactions]]
+reactants = [\"a\", \"b\"]
+products = [\"b\"]
+
+[[chemistry.reactions]]
+reactants = [\"a\", \"b\"]
+products = [\"x\"]
+
+[[attributes]]
+name = \"rumour\"
+bounds = [0.0, 1.0]
+rules = [\"on step then a = 1\"]
+
+[[attributes]]
+name = \"gossip\"
+bounds = [1.0, 0.0]
+rules = [\"on step then a = b\"]
+").unwrap_err();
+
+    assert_eq!(errors.len(), 3);
+    assert!(errors.iter().any(|e| e.line == Some(17) && e.message.contains("`products`")));
+    assert!(errors.iter().any(|e| e.line == Some(26) && e.message.contains("`bounds` of attribute \"gossip\"")));
+    assert!(errors.iter().any(|e| e.line == Some(27) && e.message.contains("of attribute \"gossip\"")));
+}
+
+#[test]
+fn config_negative_integers_in_entries() {
+    let errors = parse_config("
+number_of_particles = 100
+dt = 0.001
+radius = 1.0
+width = 100
+height = 100
+
+[chemistry]
+species = [\"a\", \"b\"]
+
+[[chemistry.reactions]]
+reactants = [\"a\", \"b\"]
+products = [\"b\"]
+
+[[chemistry.reactions]]
+reactants = [\"a\", \"b\"]
+products = [\"a\"]
+activation_energy = -2
+").unwrap_err();
+
+    assert!(errors.iter().any(|e| e.line == Some(18) && e.message.contains("`chemistry.reactions.activation_energy` must not be negative, got -2")));
+}
+
+#[test]
+fn config_partition_errors() {
+    let mut config = parse_config("
+number_of_particles = 100
+dt = 0.001
+radius = 1.0
+width = 100
+height = 100
+collisions = \"cell_list\"
+").unwrap();
+    assert!(partition::from_config(&config, 100.0, 100.0, 1.0).is_ok());
+
+    // a config that hasn't been through read_config is reported rather than panicking
+    config.cell_list = Some( CellListConfig { number_of_rows: Some(1000), number_of_columns: None } );
+    match partition::from_config(&config, 100.0, 100.0, 1.0) {
+        Err(e) => assert!(e.message.contains("too fine")),
+        Ok(_) => panic!("a 1000 row cell list fits a radius of 1 in a 100x100 box")
+    }
+
+    config.collisions = Some("octree".to_string());
+    match partition::from_config(&config, 100.0, 100.0, 1.0) {
+        Err(e) => assert!(e.message.contains("`collisions` must be one of")),
+        Ok(_) => panic!("octree isn't a partition")
+    }
+}
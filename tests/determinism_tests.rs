@@ -0,0 +1,99 @@
+extern crate boltzmann;
+
+use boltzmann::simulator::Simulator;
+use boltzmann::collision::SpatialPartition;
+use boltzmann::spatial_hash::SpatialHash;
+use boltzmann::quadtree::Quadtree;
+use boltzmann::cell_list::CellList;
+use boltzmann::generator::*;
+use boltzmann::particles::ParticleStore;
+use boltzmann::attribute::*;
+
+// runs the same seeded simulation and returns the final particles and attribute data
+fn run<T: SpatialPartition>(spatial_partition: T, number_of_threads: usize) -> (ParticleStore, Vec<f64>) {
+    let initial_conditions = InitialConditions::new(200.0, 200.0).velocities(VelocityDistribution::Maxwellian(10000.0));
+    let mut simulator = Simulator::from_initial_conditions(spatial_partition, &initial_conditions, 4000, 7, 1.0, -100.0, 0.95, 200.0, 200.0, 0.0005).unwrap();
+
+    simulator.set_number_of_threads(number_of_threads);
+
+    let virus = simulator.bind_attribute::<virus_attr>().unwrap();
+    simulator.set_attribute(virus, 1.0, 0.0);
+
+    for _ in 0..100 {
+        simulator.update();
+    }
+
+    (simulator.particles.clone(), simulator.attribute::<virus_attr>().unwrap().scalars())
+}
+
+#[test]
+fn spatial_hash_thread_count_independent() {
+    let expected = run(SpatialHash::new(200.0, 200.0, 15, 15, 1.0).unwrap(), 1);
+
+    for &n in &[2, 4, 8] {
+        let result = run(SpatialHash::new(200.0, 200.0, 15, 15, 1.0).unwrap(), n);
+        assert!(result.0 == expected.0, "particles differ with {} threads", n);
+        assert!(result.1 == expected.1, "attributes differ with {} threads", n);
+    }
+}
+
+#[test]
+fn quadtree_thread_count_independent() {
+    let expected = run(Quadtree::new(200.0, 200.0, 1.0), 1);
+
+    for &n in &[2, 4, 8] {
+        let result = run(Quadtree::new(200.0, 200.0, 1.0), n);
+        assert!(result.0 == expected.0, "particles differ with {} threads", n);
+        assert!(result.1 == expected.1, "attributes differ with {} threads", n);
+    }
+}
+
+#[test]
+fn cell_list_thread_count_independent() {
+    let expected = run(CellList::new(200.0, 200.0, 100, 100, 1.0).unwrap(), 1);
+
+    for &n in &[2, 4, 8] {
+        let result = run(CellList::new(200.0, 200.0, 100, 100, 1.0).unwrap(), n);
+        assert!(result.0 == expected.0, "particles differ with {} threads", n);
+        assert!(result.1 == expected.1, "attributes differ with {} threads", n);
+    }
+}
+
+#[test]
+fn parallel_matches_serial() {
+    let initial_conditions = InitialConditions::new(200.0, 200.0);
+    let mut rng = boltzmann::simulator::seeded_rng(3);
+    let positions = initial_conditions.generate_positions(&mut rng, 4000, 1.0).unwrap();
+
+    let mut serial = SpatialHash::new(200.0, 200.0, 15, 15, 1.0).unwrap();
+    let mut parallel = SpatialHash::new(200.0, 200.0, 15, 15, 1.0).unwrap();
+    parallel.set_number_of_threads(8);
+
+    for (i, p) in positions.iter().enumerate() {
+        serial.insert(i, *p);
+        parallel.insert(i, *p);
+    }
+
+    let c1: Vec<(usize, usize)> = serial.collision_check().iter().map(|c| (c.p1, c.p2)).collect();
+    let c2: Vec<(usize, usize)> = parallel.collision_check_parallel().iter().map(|c| (c.p1, c.p2)).collect();
+
+    assert_eq!(c1, c2);
+}
+
+// attributes that leave the particles alone don't change how they move
+#[test]
+fn attributes_leave_the_motion_alone() {
+    let run = |bind: bool| {
+        let initial_conditions = InitialConditions::new(200.0, 200.0).velocities(VelocityDistribution::Maxwellian(10000.0));
+        let mut simulator = Simulator::from_initial_conditions(SpatialHash::new(200.0, 200.0, 15, 15, 1.0).unwrap(), &initial_conditions, 1000, 7, 1.0, -100.0, 0.95, 200.0, 200.0, 0.0005).unwrap();
+        if bind {
+            simulator.bind_attribute::<virus_attr>().unwrap();
+        }
+        for _ in 0..100 {
+            simulator.update();
+        }
+        simulator.particles.clone()
+    };
+
+    assert!(run(true) == run(false));
+}
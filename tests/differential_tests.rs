@@ -0,0 +1,323 @@
+extern crate boltzmann;
+extern crate rand;
+
+use boltzmann::collision::*;
+use boltzmann::vector::Vector;
+use boltzmann::particle::Particle;
+use boltzmann::naive::Naive;
+use boltzmann::spatial_hash::SpatialHash;
+use boltzmann::cell_list::CellList;
+use boltzmann::quadtree::Quadtree;
+use boltzmann::sweep_and_prune::SweepAndPrune;
+use boltzmann::neighbour_list::NeighbourList;
+use boltzmann::loose_quadtree::LooseQuadtree;
+use boltzmann::bvh::Bvh;
+
+use rand::{Rng, SeedableRng, XorShiftRng};
+
+// every partition is compared with naive_collision_check on randomly generated cases,
+// a failing case is shrunk to as few particles and frames as still fail before it is
+// printed, the seed it came from is printed too
+
+const CASES: u32 = 25;
+const THREADS: usize = 3;
+
+// the same particles over a few frames, for the partitions that keep state between frames
+#[derive(Clone)]
+struct Case {
+    width: f64,
+    height: f64,
+    radius: f64,
+    frames: Vec<Vec<Vector>>,
+}
+
+impl Case {
+    fn print(&self) {
+        println!("width = {}, height = {}, radius = {}", self.width, self.height, self.radius);
+        for (f, frame) in self.frames.iter().enumerate() {
+            println!("frame {}:", f);
+            for (i, p) in frame.iter().enumerate() {
+                println!("    {}: ({}, {})", i, p.x, p.y);
+            }
+        }
+    }
+
+    // the same case without particle i
+    fn without_particle(&self, i: usize) -> Case {
+        let mut case = self.clone();
+        for frame in &mut case.frames {
+            frame.remove(i);
+        }
+        case
+    }
+
+    fn without_frame(&self, f: usize) -> Case {
+        let mut case = self.clone();
+        case.frames.remove(f);
+        case
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+enum Check {
+    Serial,
+    Parallel,
+    WithComparisons
+}
+
+type Constructor = fn(&Case) -> Box<SpatialPartition>;
+
+fn number_of_particles(case: &Case) -> usize {
+    case.frames[0].len()
+}
+
+fn naive(case: &Case) -> Box<SpatialPartition> {
+    Box::new( Naive::new(case.radius) )
+}
+
+fn spatial_hash(case: &Case) -> Box<SpatialPartition> {
+    Box::new( SpatialHash::auto(case.width, case.height, case.radius, number_of_particles(case)) )
+}
+
+fn spatial_hash_single_cell(case: &Case) -> Box<SpatialPartition> {
+    Box::new( SpatialHash::new(case.width, case.height, 1, 1, case.radius).unwrap() )
+}
+
+fn spatial_hash_finest(case: &Case) -> Box<SpatialPartition> {
+    let (columns, rows) = SpatialHash::maximum_grid(case.width, case.height, case.radius);
+    Box::new( SpatialHash::new(case.width, case.height, columns, rows, case.radius).unwrap() )
+}
+
+fn spatial_hash_auto_tune(case: &Case) -> Box<SpatialPartition> {
+    Box::new( SpatialHash::auto(case.width, case.height, case.radius, 1).auto_tune(true) )
+}
+
+fn cell_list_single_cell(case: &Case) -> Box<SpatialPartition> {
+    Box::new( CellList::new(case.width, case.height, 1, 1, case.radius).unwrap() )
+}
+
+fn cell_list_finest(case: &Case) -> Box<SpatialPartition> {
+    let (columns, rows) = CellList::maximum_grid(case.width, case.height, case.radius);
+    Box::new( CellList::new(case.width, case.height, columns, rows, case.radius).unwrap() )
+}
+
+fn quadtree(case: &Case) -> Box<SpatialPartition> {
+    Box::new( Quadtree::new(case.width, case.height, case.radius) )
+}
+
+// divides as far as it can and merges whenever it can
+fn quadtree_eager(case: &Case) -> Box<SpatialPartition> {
+    Box::new( Quadtree::with_thresholds(case.width, case.height, case.radius, 1, 1.0).merge_limit(0).maximum_depth(6) )
+}
+
+fn sweep_and_prune(case: &Case) -> Box<SpatialPartition> {
+    Box::new( SweepAndPrune::new(case.width, case.height, case.radius) )
+}
+
+fn neighbour_list(case: &Case) -> Box<SpatialPartition> {
+    let skin = case.radius;
+    Box::new( NeighbourList::new(SweepAndPrune::new(case.width, case.height, case.radius + skin/2.0), case.radius, skin) )
+}
+
+fn loose_quadtree(case: &Case) -> Box<SpatialPartition> {
+    Box::new( LooseQuadtree::new(case.width, case.height, case.radius) )
+}
+
+fn bvh(case: &Case) -> Box<SpatialPartition> {
+    Box::new( Bvh::new(case.radius).margin(case.radius / 4.0) )
+}
+
+fn partitions() -> Vec<(&'static str, Constructor)> {
+    vec![
+        ("naive", naive as Constructor),
+        ("spatial_hash", spatial_hash),
+        ("spatial_hash_single_cell", spatial_hash_single_cell),
+        ("spatial_hash_finest", spatial_hash_finest),
+        ("spatial_hash_auto_tune", spatial_hash_auto_tune),
+        ("cell_list_single_cell", cell_list_single_cell),
+        ("cell_list_finest", cell_list_finest),
+        ("quadtree", quadtree),
+        ("quadtree_eager", quadtree_eager),
+        ("sweep_and_prune", sweep_and_prune),
+        ("neighbour_list", neighbour_list),
+        ("loose_quadtree", loose_quadtree),
+        ("bvh", bvh),
+    ]
+}
+
+// None if every frame matches, otherwise the first frame that doesn't and why
+fn run(case: &Case, constructor: Constructor, check: Check) -> Option<String> {
+    let mut spatial_partition = constructor(case);
+    spatial_partition.set_number_of_threads(THREADS);
+
+    for (f, frame) in case.frames.iter().enumerate() {
+        spatial_partition.clear();
+        for (i, &p) in frame.iter().enumerate() {
+            spatial_partition.insert(i, p);
+        }
+
+        let mut found = match check {
+            Check::Serial => spatial_partition.collision_check().clone(),
+            Check::Parallel => spatial_partition.collision_check_parallel().clone(),
+            Check::WithComparisons => spatial_partition.collision_check_with_comparisons().0.clone()
+        };
+        found.sort();
+
+        let particles = frame.iter().map(|&p| Particle::new(p, Vector::zero(), 1.0)).collect();
+        let expected = naive_collision_check(case.radius, &particles);
+
+        if found.len() != expected.len() {
+            return Some( format!("frame {}: found {} collisions, expected {}", f, found.len(), expected.len()) );
+        }
+        for (c, e) in found.iter().zip( expected.iter() ) {
+            if (c.p1, c.p2) != (e.p1, e.p2) {
+                return Some( format!("frame {}: found ({}, {}), expected ({}, {})", f, c.p1, c.p2, e.p1, e.p2) );
+            }
+            // particles on top of each other have no normal
+            let same_normal = c.normal == e.normal || (c.normal.x.is_nan() && e.normal.x.is_nan());
+            if c.penetration != e.penetration || !same_normal {
+                return Some( format!("frame {}: ({}, {}) has a different penetration or normal", f, c.p1, c.p2) );
+            }
+        }
+    }
+    None
+}
+
+// drops frames and particles one at a time for as long as the case still fails
+fn shrink(case: Case, constructor: Constructor, check: Check) -> Case {
+    let mut case = case;
+    loop {
+        let mut smaller = None;
+
+        for f in 0..case.frames.len() {
+            if case.frames.len() > 1 && run(&case.without_frame(f), constructor, check).is_some() {
+                smaller = Some(case.without_frame(f));
+                break;
+            }
+        }
+        if smaller.is_none() {
+            for i in 0..number_of_particles(&case) {
+                if run(&case.without_particle(i), constructor, check).is_some() {
+                    smaller = Some(case.without_particle(i));
+                    break;
+                }
+            }
+        }
+
+        match smaller {
+            Some(c) => case = c,
+            None => return case
+        }
+    }
+}
+
+// checks every partition in every mode on CASES cases from generate
+fn differential(name: &str, generate: fn(&mut XorShiftRng) -> Case) {
+    for seed in 1..(CASES + 1) {
+        let mut rng = XorShiftRng::from_seed([0x193a6754, 0xa8a7d469, 0x97830e05, seed]);
+        let case = generate(&mut rng);
+
+        for &(partition, constructor) in &partitions() {
+            for &check in &[Check::Serial, Check::Parallel, Check::WithComparisons] {
+                if let Some(_) = run(&case, constructor, check) {
+                    let shrunk = shrink(case.clone(), constructor, check);
+                    let reason = run(&shrunk, constructor, check).unwrap();
+
+                    println!("{} {:?} failed on {} case with seed {}, shrunk to:", partition, check, name, seed);
+                    shrunk.print();
+                    panic!("{} {:?}: {}", partition, check, reason);
+                }
+            }
+        }
+    }
+}
+
+// the particles of the first frame drift a little in each of the next ones
+fn frames(rng: &mut XorShiftRng, first: Vec<Vector>, radius: f64) -> Vec<Vec<Vector>> {
+    let mut frames = vec![first];
+    for _ in 0..2 {
+        let next = frames.last().unwrap().iter().map(|&p| {
+            p + Vector::new(rng.gen::<f64>() - 0.5, rng.gen::<f64>() - 0.5) * radius
+        }).collect();
+        frames.push(next);
+    }
+    frames
+}
+
+fn size(rng: &mut XorShiftRng) -> (f64, f64, f64) {
+    let radius = 0.25 + rng.gen::<f64>() * 2.0;
+    (radius * (4.0 + rng.gen::<f64>() * 60.0), radius * (4.0 + rng.gen::<f64>() * 60.0), radius)
+}
+
+fn random_case(rng: &mut XorShiftRng) -> Case {
+    let (width, height, radius) = size(rng);
+    let n = rng.gen_range(0, 200);
+    let first = (0..n).map(|_| Vector::new(rng.gen::<f64>() * width, rng.gen::<f64>() * height)).collect();
+
+    Case { width: width, height: height, radius: radius, frames: frames(rng, first, radius) }
+}
+
+// on the walls, in the corners and on the lines between cells of common grids
+fn edge_case(rng: &mut XorShiftRng) -> Case {
+    let (width, height, radius) = size(rng);
+    let n = rng.gen_range(0, 200);
+
+    let first = (0..n).map(|_| {
+        let divisions = *rng.choose(&[1.0, 2.0, 4.0, 8.0, 16.0]).unwrap();
+        let x = (rng.gen_range(0, divisions as usize + 1) as f64 / divisions) * width;
+        let y = (rng.gen_range(0, divisions as usize + 1) as f64 / divisions) * height;
+        match rng.gen_range(0, 3) {
+            0 => Vector::new(x, rng.gen::<f64>() * height),
+            1 => Vector::new(rng.gen::<f64>() * width, y),
+            _ => Vector::new(x, y)
+        }
+    }).collect();
+
+    Case { width: width, height: height, radius: radius, frames: frames(rng, first, radius) }
+}
+
+// up to a radius outside the box, where particles end up between boundary checks
+fn out_of_bounds_case(rng: &mut XorShiftRng) -> Case {
+    let (width, height, radius) = size(rng);
+    let n = rng.gen_range(0, 200);
+    let first = (0..n).map(|_| {
+        Vector::new(rng.gen::<f64>() * (width + 2.0*radius) - radius, rng.gen::<f64>() * (height + 2.0*radius) - radius)
+    }).collect();
+
+    Case { width: width, height: height, radius: radius, frames: frames(rng, first, radius) }
+}
+
+// the box barely fits a particle, many share a position or sit in a line
+fn degenerate_case(rng: &mut XorShiftRng) -> Case {
+    let radius = 0.25 + rng.gen::<f64>() * 2.0;
+    let (width, height) = *rng.choose(&[(2.0*radius, 2.0*radius), (2.0*radius, 100.0*radius), (100.0*radius, 2.0*radius), (7.0*radius, 3.0*radius)]).unwrap();
+    let n = rng.gen_range(0, 60);
+
+    let first = (0..n).map(|i| match i % 3 {
+        0 => Vector::new(width / 2.0, height / 2.0),
+        1 => Vector::new(rng.gen::<f64>() * width, height / 2.0),
+        _ => Vector::new(rng.gen::<f64>() * width, rng.gen::<f64>() * height)
+    }).collect();
+
+    Case { width: width, height: height, radius: radius, frames: frames(rng, first, radius) }
+}
+
+#[test]
+fn differential_random() {
+    differential("random", random_case);
+}
+
+#[test]
+fn differential_edge_of_domain() {
+    differential("edge of domain", edge_case);
+}
+
+#[test]
+fn differential_out_of_bounds() {
+    differential("out of bounds", out_of_bounds_case);
+}
+
+#[test]
+fn differential_degenerate() {
+    differential("degenerate", degenerate_case);
+}
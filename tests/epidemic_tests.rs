@@ -0,0 +1,102 @@
+extern crate boltzmann;
+
+use boltzmann::simulator::*;
+use boltzmann::spatial_hash::SpatialHash;
+use boltzmann::generator::*;
+use boltzmann::epidemic::*;
+
+fn outbreak(seed: u64, parameters: EpidemicParameters, vaccinated: f64) -> Simulator<SpatialHash> {
+    let hash = SpatialHash::new(100.0, 100.0, 10, 10, 1.0).unwrap();
+    let initial_conditions = InitialConditions::new(100.0, 100.0).velocities(VelocityDistribution::Maxwellian(10000.0));
+    let mut simulator = Simulator::from_initial_conditions(hash, &initial_conditions, 1000, seed, 1.0, 0.0, 1.0, 100.0, 100.0, 0.001).unwrap();
+
+    simulator.bind_attribute::<Epidemic>().unwrap();
+    {
+        let (epidemic, rng) = simulator.attribute_and_rng_mut::<Epidemic>().unwrap();
+        epidemic.parameters = parameters;
+        epidemic.seed_outbreak(rng, 10, vaccinated);
+    }
+    simulator
+}
+
+#[test]
+fn epidemic_moves_particles_through_the_compartments() {
+    let mut s = outbreak(1, EpidemicParameters::new(1.0, 0.02, 0.05), 0.2);
+    for _ in 0..300 {
+        s.update();
+    }
+
+    let epidemic = s.attribute::<Epidemic>().unwrap();
+    let history = epidemic.history();
+    assert_eq!(history.len(), 300);
+    assert!(history.iter().all(|c| c.total() == 1000.0 && c.vaccinated == 198.0));
+    assert!(history.windows(2).all(|w| w[1].susceptible <= w[0].susceptible && w[1].recovered >= w[0].recovered));
+    assert!(history.iter().any(|c| c.exposed > 0.0));
+    assert!(history[299].recovered > 10.0);
+    assert!(epidemic.contact_rate() > 0.0);
+}
+
+#[test]
+fn epidemic_without_transmission_stays_with_the_first_cases() {
+    let mut s = outbreak(2, EpidemicParameters::new(0.0, 0.0, 0.05), 0.0);
+    for _ in 0..300 {
+        s.update();
+    }
+
+    let last = *s.attribute::<Epidemic>().unwrap().history().last().unwrap();
+    assert_eq!(last.susceptible, 990.0);
+    assert_eq!(last.infected + last.recovered, 10.0);
+}
+
+#[test]
+fn epidemic_is_reproduced_by_the_seed() {
+    let run = || {
+        let mut s = outbreak(3, EpidemicParameters::new(0.5, 0.0, 0.05), 0.0);
+        for _ in 0..100 {
+            s.update();
+        }
+        s.attribute::<Epidemic>().unwrap().history().to_vec()
+    };
+    assert!(run() == run());
+}
+
+#[test]
+fn sir_ode_peaks_at_the_herd_immunity_threshold() {
+    let initial = Counts { time: 0.0, susceptible: 999.0, exposed: 0.0, infected: 1.0, recovered: 0.0, vaccinated: 0.0 };
+    let (beta, recovery_time) = (3.0, 1.0);
+    let solution = seir_ode(initial, beta, 0.0, recovery_time, 0.001, 20000);
+
+    assert!(solution.iter().all(|c| (c.total() - 1000.0).abs() < 1e-6 && c.exposed == 0.0));
+
+    // infections stop growing once s/n = 1/R0
+    let peak = solution.iter().fold(solution[0], |peak, &c| if c.infected > peak.infected { c } else { peak });
+    assert!((peak.susceptible - 1000.0 / (beta * recovery_time)).abs() < 1.0);
+
+    // final size, ln(s/s0) = -R0 (1 - s/n)
+    let s = solution.last().unwrap().susceptible;
+    assert!(((s / 999.0).ln() + beta * recovery_time * (1.0 - s / 1000.0)).abs() < 1e-3);
+}
+
+// in a dilute gas particles rarely meet the same one twice, so it is well mixed and
+// the SIR model with the transmission rate the agents measured follows them
+#[test]
+fn dilute_epidemic_follows_the_sir_ode() {
+    let hash = SpatialHash::new(200.0, 200.0, 20, 20, 1.0).unwrap();
+    let initial_conditions = InitialConditions::new(200.0, 200.0).velocities(VelocityDistribution::Maxwellian(10000.0));
+    let mut s = Simulator::from_initial_conditions(hash, &initial_conditions, 800, 3, 1.0, 0.0, 1.0, 200.0, 200.0, 0.004).unwrap();
+    s.bind_attribute::<Epidemic>().unwrap();
+    {
+        let (epidemic, rng) = s.attribute_and_rng_mut::<Epidemic>().unwrap();
+        epidemic.parameters = EpidemicParameters::new(0.15, 0.0, 1.0);
+        epidemic.seed_outbreak(rng, 20, 0.0);
+    }
+    for _ in 0..3000 {
+        s.update();
+    }
+
+    let epidemic = s.attribute::<Epidemic>().unwrap();
+    let history = epidemic.history();
+    let solution = seir_ode(history[0], epidemic.transmission_rate(), 0.0, 1.0, 0.004, history.len() - 1);
+    let (agents, ode) = (history.last().unwrap().recovered, solution.last().unwrap().recovered);
+    assert!((agents - ode).abs() < 0.2 * ode, "agents {}, ode {}", agents, ode);
+}
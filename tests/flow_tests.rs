@@ -0,0 +1,75 @@
+extern crate boltzmann;
+
+use boltzmann::simulator::Simulator;
+use boltzmann::spatial_hash::SpatialHash;
+use boltzmann::flow::*;
+use boltzmann::vector::Vector;
+
+// an empty channel fed from the left and drained on the right
+fn channel(drift: f64) -> Simulator<SpatialHash> {
+    let hash = SpatialHash::auto(100.0, 50.0, 0.5, 250);
+    let mut simulator = Simulator::new(hash, vec![], vec![], 5, 0.5, 0.0, 1.0, 100.0, 50.0, 0.001);
+    simulator.add_inflow( Inflow::new(Wall::Left, 0.05, 1000.0, Vector::new(drift, 0.0)).depth(5.0) );
+    simulator.set_boundary(Wall::Right, Boundary::Absorbing);
+    simulator
+}
+
+#[test]
+fn absorbing_walls_remove_particles_reflecting_ones_keep_them() {
+    let positions = vec![Vector::new(2.0, 25.0), Vector::new(98.0, 25.0)];
+    let velocities = vec![Vector::new(-1000.0, 0.0), Vector::new(1000.0, 0.0)];
+    let mut simulator = Simulator::new(SpatialHash::auto(100.0, 50.0, 0.5, 2), positions, velocities, 1, 0.5, 0.0, 1.0, 100.0, 50.0, 0.001);
+    simulator.set_boundary(Wall::Right, Boundary::Absorbing);
+
+    for _ in 0..10 {
+        simulator.update();
+    }
+
+    assert_eq!(simulator.number_of_particles(), 1);
+    assert_eq!(simulator.absorbed, 1);
+    assert_eq!(simulator.particles.id, vec![0]);
+    assert!(simulator.particles.velocity(0).x > 0.0);
+}
+
+#[test]
+fn channel_reaches_a_steady_number_of_particles() {
+    let mut simulator = channel(1000.0);
+
+    let mut counts = vec![];
+    for step in 0..600 {
+        simulator.update();
+        if step % 100 == 99 { counts.push(simulator.number_of_particles()); }
+    }
+
+    // every particle that came in either left or is still there
+    assert_eq!(simulator.injected, simulator.absorbed + simulator.number_of_particles());
+    assert!(simulator.absorbed > 0);
+
+    // filled by the time the first particles reach the far end, then roughly level
+    let (last, previous) = (counts[5] as f64, counts[4] as f64);
+    assert!(last > 100.0, "{:?}", counts);
+    assert!((last - previous).abs() < 0.2 * last, "{:?}", counts);
+}
+
+#[test]
+fn obstacle_in_a_flow_is_pushed_downstream() {
+    let mut simulator = channel(1000.0);
+    simulator.add_obstacle( Obstacle::new(Vector::new(60.0, 25.0), 8.0) );
+
+    for _ in 0..300 {
+        simulator.update();
+    }
+    simulator.obstacles[0].reset();
+    for _ in 0..300 {
+        simulator.update();
+    }
+
+    let force = simulator.obstacles[0].force();
+    assert!(force.x > 0.0);
+    assert!(force.y.abs() < force.x);
+
+    // no particle is left inside it
+    for i in 0..simulator.number_of_particles() {
+        assert!(simulator.particles.position(i).distance(Vector::new(60.0, 25.0)) >= 8.0 + 0.5 - 1e-9);
+    }
+}
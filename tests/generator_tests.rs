@@ -0,0 +1,78 @@
+extern crate boltzmann;
+extern crate rand;
+
+use boltzmann::generator::*;
+use boltzmann::vector::Vector;
+
+fn overlapping(positions: &Vec<Vector>, radius: f64) -> bool {
+    for i in 0..positions.len() {
+        for j in (i+1)..positions.len() {
+            if positions[i].distance(positions[j]) < 2.0*radius - 1e-9 {
+                return true;
+            }
+        }
+    }
+    false
+}
+
+fn inside(positions: &Vec<Vector>, min: Vector, max: Vector, radius: f64) -> bool {
+    positions.iter().all(|p| p.x - radius >= min.x && p.x + radius <= max.x && p.y - radius >= min.y && p.y + radius <= max.y)
+}
+
+#[test]
+fn generator_non_overlapping() {
+    let mut rng = rand::XorShiftRng::new_unseeded();
+    let initial_conditions = InitialConditions::new(100.0, 100.0).placement(Placement::NonOverlapping);
+
+    let positions = initial_conditions.generate_positions(&mut rng, 500, 1.0).unwrap();
+
+    assert_eq!(positions.len(), 500);
+    assert!(!overlapping(&positions, 1.0));
+    assert!(inside(&positions, Vector::zero(), Vector::new(100.0, 100.0), 1.0));
+}
+
+#[test]
+fn generator_lattices() {
+    let mut rng = rand::XorShiftRng::new_unseeded();
+
+    for &placement in &[Placement::SquareLattice, Placement::HexagonalLattice] {
+        let initial_conditions = InitialConditions::new(100.0, 100.0).placement(placement);
+        let positions = initial_conditions.generate_positions(&mut rng, 1000, 1.0).unwrap();
+
+        assert_eq!(positions.len(), 1000);
+        assert!(!overlapping(&positions, 1.0));
+        assert!(inside(&positions, Vector::zero(), Vector::new(100.0, 100.0), 1.0));
+    }
+
+    // a square lattice can't hold as many as the densest packing
+    let initial_conditions = InitialConditions::new(10.0, 10.0).placement(Placement::SquareLattice);
+    assert!(initial_conditions.generate_positions(&mut rng, 26, 1.0).is_none());
+}
+
+#[test]
+fn generator_region() {
+    let mut rng = rand::XorShiftRng::new_unseeded();
+    let initial_conditions = InitialConditions::new(100.0, 100.0).region(Vector::zero(), Vector::new(50.0, 100.0));
+
+    let positions = initial_conditions.generate_positions(&mut rng, 1000, 1.0).unwrap();
+
+    assert!(inside(&positions, Vector::zero(), Vector::new(50.0, 100.0), 1.0));
+}
+
+#[test]
+fn generator_velocities() {
+    let mut rng = rand::XorShiftRng::new_unseeded();
+
+    let initial_conditions = InitialConditions::new(100.0, 100.0).velocities(VelocityDistribution::Monoenergetic(10.0));
+    let velocities = initial_conditions.generate_velocities(&mut rng, 1000);
+    assert!(velocities.iter().all(|v| (v.magnitude() - 10.0).abs() < 1e-9));
+
+    // mean kinetic energy per particle is the temperature in two dimensions
+    let initial_conditions = InitialConditions::new(100.0, 100.0).velocities(VelocityDistribution::Maxwellian(100.0)).zero_momentum(true);
+    let velocities = initial_conditions.generate_velocities(&mut rng, 10000);
+    let momentum = velocities.iter().fold(Vector::zero(), |sum, v| sum + *v);
+    let energy = velocities.iter().fold(0.0, |sum, v| sum + 0.5 * v.dot(*v)) / 10000.0;
+
+    assert!(momentum.magnitude() < 1e-6);
+    assert!((energy - 100.0).abs() < 5.0);
+}
@@ -0,0 +1,66 @@
+extern crate boltzmann;
+
+use boltzmann::simulator::*;
+use boltzmann::spatial_hash::SpatialHash;
+use boltzmann::generator::*;
+use boltzmann::attribute::Attribute;
+use boltzmann::heat::Heat;
+
+fn gas(seed: u64) -> Simulator<SpatialHash> {
+    let hash = SpatialHash::new(100.0, 100.0, 10, 10, 1.0).unwrap();
+    let initial_conditions = InitialConditions::new(100.0, 100.0).velocities(VelocityDistribution::Maxwellian(2500.0));
+    let mut simulator = Simulator::from_initial_conditions(hash, &initial_conditions, 1000, seed, 1.0, 0.0, 1.0, 100.0, 100.0, 0.001).unwrap();
+    simulator.bind_attribute::<Heat>().unwrap();
+    simulator
+}
+
+#[test]
+fn heat_flows_from_hot_particles_to_cold_ones() {
+    let mut s = gas(1);
+    // every other particle is hot, they are placed at random
+    for (i, e) in s.attribute_mut::<Heat>().unwrap().data_mut().iter_mut().enumerate() {
+        *e = if i % 2 == 0 { 100.0 } else { 0.0 };
+    }
+    let spread = |heat: &Heat| {
+        let mean = heat.mean();
+        heat.data().iter().map(|e| (e - mean) * (e - mean)).sum::<f64>() / heat.data().len() as f64
+    };
+    let (total, before) = (s.attribute::<Heat>().unwrap().total(), spread(s.attribute::<Heat>().unwrap()));
+
+    for _ in 0..200 {
+        s.update();
+    }
+
+    let heat = s.attribute::<Heat>().unwrap();
+    assert!((heat.total() - total).abs() < 1e-6);
+    assert!(spread(heat) < 0.5 * before);
+}
+
+#[test]
+fn inelastic_collisions_heat_the_particles() {
+    let mut s = gas(2);
+    s.attribute_mut::<Heat>().unwrap().restitution = 0.5;
+    let energy = s.total_energy();
+
+    for _ in 0..200 {
+        s.update();
+    }
+
+    let internal = s.attribute::<Heat>().unwrap().total();
+    assert!(internal > 0.1 * energy);
+    assert!((s.total_energy() + internal - energy).abs() < 1e-6 * energy);
+    assert!(s.attribute::<Heat>().unwrap().scalars().iter().all(|&e| e >= 0.0));
+
+    // without inelastic heating the energy is just lost
+    let mut s = gas(2);
+    {
+        let heat = s.attribute_mut::<Heat>().unwrap();
+        heat.restitution = 0.5;
+        heat.inelastic_heating = false;
+    }
+    for _ in 0..200 {
+        s.update();
+    }
+    assert_eq!(s.attribute::<Heat>().unwrap().total(), 0.0);
+    assert!(s.total_energy() < 0.9 * energy);
+}
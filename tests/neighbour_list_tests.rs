@@ -0,0 +1,54 @@
+extern crate boltzmann;
+extern crate rand;
+
+use boltzmann::neighbour_list::NeighbourList;
+use boltzmann::spatial_hash::SpatialHash;
+use boltzmann::collision::*;
+use boltzmann::vector::Vector;
+
+fn collision_check(radius: f64, particles: &Vec<Vector>) -> Vec<(usize, usize)> {
+    let mut collisions = Vec::new();
+
+    for i in 0..particles.len() {
+        for j in (i+1)..particles.len() {
+            if 2.0*radius - particles[i].distance( particles[j] ) > 0.0 {
+                collisions.push( (i, j) );
+            }
+        }
+    }
+
+    collisions
+}
+
+fn collision_pairs(collisions: &Vec<Collision>) -> Vec<(usize, usize)> {
+    collisions.iter().map(|c| (c.p1, c.p2)).collect()
+}
+
+// particles drift a little each frame, the cached pairs must keep finding every collision
+#[test]
+fn neighbour_list_test_collisions_moving() {
+    let radius = 1.0;
+    let skin = 0.5;
+    let hash = SpatialHash::new(100.0, 100.0, 10, 10, radius + skin / 2.0).unwrap();
+    let mut neighbour_list = NeighbourList::new(hash, radius, skin);
+
+    let mut particles = Vec::new();
+    for _ in 0..2000 {
+        particles.push( Vector::new(rand::random::<f64>() * 100.0, rand::random::<f64>() * 100.0) );
+    }
+
+    let frames = 50;
+    for _ in 0..frames {
+        neighbour_list.clear();
+
+        for (i, p) in particles.iter_mut().enumerate() {
+            *p = *p + Vector::new(rand::random::<f64>() * 0.1 - 0.05, rand::random::<f64>() * 0.1 - 0.05);
+            neighbour_list.insert(i, *p);
+        }
+
+        let expected = collision_check(radius, &particles);
+        assert_eq!(collision_pairs(neighbour_list.collision_check_parallel()), expected);
+    }
+
+    assert!(neighbour_list.rebuilds < frames);
+}
@@ -0,0 +1,77 @@
+extern crate boltzmann;
+
+use boltzmann::simulator::Simulator;
+use boltzmann::particles::*;
+use boltzmann::naive::Naive;
+use boltzmann::vector::Vector;
+use boltzmann::attribute::*;
+
+// consecutive cells along a curve over a 2^4 square
+fn walk(curve: Curve) -> Vec<(u32, u32)> {
+    let mut cells: Vec<(u64, (u32, u32))> = (0..16).flat_map(|x| (0..16).map(move |y| (x, y)))
+        .map(|(x, y)| (curve.key(x, y), (x, y)))
+        .collect();
+    cells.sort();
+    cells.into_iter().map(|(_, c)| c).collect()
+}
+
+#[test]
+fn curves_visit_every_cell_once() {
+    for &curve in &[Curve::Morton, Curve::Hilbert] {
+        let mut keys: Vec<u64> = (0..16).flat_map(|x| (0..16).map(move |y| curve.key(x, y))).collect();
+        keys.sort();
+        keys.dedup();
+        assert_eq!(keys.len(), 256);
+    }
+
+    assert_eq!(Curve::Morton.key(3, 0), 5);
+    assert_eq!(Curve::Morton.key(0, 3), 10);
+}
+
+#[test]
+fn hilbert_steps_between_neighbouring_cells() {
+    // an aligned square is one stretch of the curve, so the walk covers it without jumps
+    let cells = walk(Curve::Hilbert);
+    for w in cells.windows(2) {
+        let (dx, dy) = ((w[0].0 as i64 - w[1].0 as i64).abs(), (w[0].1 as i64 - w[1].1 as i64).abs());
+        assert_eq!(dx + dy, 1, "{:?} to {:?}", w[0], w[1]);
+    }
+}
+
+#[test]
+fn permute_moves_every_column() {
+    let mut particles = ParticleStore::new(0.1);
+    for i in 0..4 {
+        particles.push(Vector::new(i as f64, 0.0), Vector::new(0.0, i as f64), i as f64 + 1.0, 1.0);
+    }
+
+    particles.permute(&[2, 0, 3, 1]);
+
+    assert_eq!(particles.id, vec![2, 0, 3, 1]);
+    assert_eq!(particles.x, vec![2.0, 0.0, 3.0, 1.0]);
+    assert_eq!(particles.mass, vec![3.0, 1.0, 4.0, 2.0]);
+    assert!((particles.velocity(0).y - 2.0).abs() < 1e-9);
+}
+
+#[test]
+fn reordering_sorts_particles_and_keeps_attributes_with_them() {
+    // a spread out line in reverse curve order, nothing moves or collides
+    let positions: Vec<Vector> = (0..50).map(|i| Vector::new(195.0 - 3.9 * i as f64, 5.0)).collect();
+    let velocities = vec![Vector::zero(); positions.len()];
+    let mut simulator = Simulator::new(Naive::new(1.0), positions.clone(), velocities, 1, 1.0, 0.0, 1.0, 200.0, 200.0, 0.001);
+
+    let tag = simulator.bind_attribute::<tag_attr>().unwrap();
+    simulator.set_attribute(tag, 1.0, 0.0);
+    simulator.set_reordering(Curve::Hilbert, 1);
+    simulator.update();
+
+    let order = simulator.particles.curve_order(Curve::Hilbert, 200.0, 200.0);
+    assert_eq!(order, (0..50).collect::<Vec<usize>>());
+
+    for i in 0..50 {
+        let id = simulator.particles.id[i];
+        assert!(simulator.particles.position(i) == positions[id]);
+        assert_eq!(simulator.attribute::<tag_attr>().unwrap().data()[i], if id == 0 { 1.0 } else { 0.0 });
+    }
+    assert!(simulator.particles.id != (0..50).collect::<Vec<usize>>());
+}
@@ -0,0 +1,103 @@
+extern crate boltzmann;
+extern crate rand;
+
+use boltzmann::loose_quadtree::LooseQuadtree;
+use boltzmann::bvh::Bvh;
+use boltzmann::collision::*;
+use boltzmann::vector::Vector;
+
+fn collision_check(particles: &Vec<(Vector, f64)>) -> Vec<(usize, usize)> {
+    let mut collisions = Vec::new();
+
+    for i in 0..particles.len() {
+        for j in (i+1)..particles.len() {
+            let (p, r1) = particles[i];
+            let (q, r2) = particles[j];
+            if r1 + r2 - p.distance(q) > 0.0 {
+                collisions.push( (i, j) );
+            }
+        }
+    }
+
+    collisions
+}
+
+fn collision_pairs(collisions: &Vec<Collision>) -> Vec<(usize, usize)> {
+    collisions.iter().map(|c| (c.p1, c.p2)).collect()
+}
+
+// radii from 0.5 to 10, a few particles just outside the box
+fn particles(n: usize) -> Vec<(Vector, f64)> {
+    (0..n).map(|_| {
+        let p = Vector::new(rand::random::<f64>() * 220.0 - 10.0, rand::random::<f64>() * 220.0 - 10.0);
+        let r = 0.5 * 20.0f64.powf(rand::random::<f64>());
+        (p, r)
+    }).collect()
+}
+
+#[test]
+fn loose_quadtree_polydisperse() {
+    let mut loose_quadtree = LooseQuadtree::new(200.0, 200.0, 1.0);
+
+    for _ in 0..3 {
+        let particles = particles(2000);
+
+        loose_quadtree.clear();
+        for (i, &(p, r)) in particles.iter().enumerate() {
+            loose_quadtree.insert_with_radius( i, p, r );
+        }
+
+        let expected = collision_check(&particles);
+        assert_eq!(collision_pairs(loose_quadtree.collision_check()), expected);
+
+        loose_quadtree.clear();
+        for (i, &(p, r)) in particles.iter().enumerate() {
+            loose_quadtree.insert_with_radius( i, p, r );
+        }
+        assert_eq!(collision_pairs(loose_quadtree.collision_check_parallel()), expected);
+    }
+}
+
+// the tree is kept between frames, objects move, change size and drop out
+#[test]
+fn bvh_polydisperse_moving() {
+    let mut bvh = Bvh::new(1.0).margin(0.5);
+    let mut particles = particles(2000);
+
+    for frame in 0..20 {
+        bvh.clear();
+
+        for p in &mut particles {
+            p.0 = p.0 + Vector::new(rand::random::<f64>() * 2.0 - 1.0, rand::random::<f64>() * 2.0 - 1.0);
+        }
+        particles[frame].1 *= 2.0;
+
+        let n = if frame % 4 == 3 { 1500 } else { particles.len() };
+        for i in 0..n {
+            bvh.insert_with_radius( i, particles[i].0, particles[i].1 );
+        }
+
+        let visible = particles[..n].to_vec();
+        let collisions = if frame % 2 == 0 { bvh.collision_check() } else { bvh.collision_check_parallel() };
+        assert_eq!(collision_pairs(collisions), collision_check(&visible));
+    }
+
+    // rebalancing keeps it close to log2(2000) = 11
+    assert!(bvh.height() < 25);
+}
+
+#[test]
+fn single_radius_insert() {
+    let positions: Vec<Vector> = (0..1000).map(|_| Vector::new(rand::random::<f64>() * 100.0, rand::random::<f64>() * 100.0)).collect();
+    let expected = collision_check(&positions.iter().map(|&p| (p, 1.0)).collect());
+
+    let mut loose_quadtree = LooseQuadtree::new(100.0, 100.0, 1.0);
+    let mut bvh = Bvh::new(1.0);
+    for (i, &p) in positions.iter().enumerate() {
+        loose_quadtree.insert( i, p );
+        bvh.insert( i, p );
+    }
+
+    assert_eq!(collision_pairs(loose_quadtree.collision_check()), expected);
+    assert_eq!(collision_pairs(bvh.collision_check()), expected);
+}
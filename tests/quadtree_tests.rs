@@ -78,7 +78,7 @@ fn equal_sets(set1: Vec<P>, set2: Vec<P>) -> bool {
     true
 }
 
-fn collision_pairs(collisions: Vec<Collision>) -> Vec<P> {
+fn collision_pairs(collisions: &Vec<Collision>) -> Vec<P> {
     let mut v = Vec::new();
     for c in collisions {
         if c.p1 < c.p2 {
@@ -188,7 +188,7 @@ fn quadtree_test_collisions_2x2_non_random() {
     let (c_n_o, c_n_m) = collision_check(2.0, &particles);
     
     let mut v1 = collision_pairs(c_o);
-    let mut v2 = collision_pairs(c_n_o);
+    let mut v2 = collision_pairs(&c_n_o);
     
     // let mut v3 = collision_pairs2(c_m);
     // let mut v4 = collision_pairs2(c_n_m);
@@ -221,7 +221,7 @@ fn test_collisions_2x2() {
     let (c_n_o, c_n_m) = collision_check(2.0, &particles);
     
     let mut v1 = collision_pairs(c_o);
-    let mut v2 = collision_pairs(c_n_o);
+    let mut v2 = collision_pairs(&c_n_o);
     
     v1.sort();
     v2.sort();
@@ -250,7 +250,7 @@ fn test_collisions_3x3() {
     let (c_n_o, c_n_m) = collision_check(2.0, &particles);
     
     let mut v1 = collision_pairs(c_o);
-    let mut v2 = collision_pairs(c_n_o);
+    let mut v2 = collision_pairs(&c_n_o);
     v1.sort();
     v2.sort();
     
@@ -282,7 +282,7 @@ fn test_collisions_10x10() {
     let (c_n_o, c_n_m) = collision_check(2.0, &particles);
     
     let mut v1 = collision_pairs(c_o);
-    let mut v2 = collision_pairs(c_n_o);
+    let mut v2 = collision_pairs(&c_n_o);
     v1.sort();
     v2.sort();
     
@@ -315,7 +315,7 @@ fn test_collisions_512x512() {
     let (c_n_o, c_n_m) = collision_check(2.0, &particles);
     
     let mut v1 = collision_pairs(c_o);
-    let mut v2 = collision_pairs(c_n_o);
+    let mut v2 = collision_pairs(&c_n_o);
     v1.sort();
     v2.sort();
     
@@ -347,7 +347,7 @@ fn test_collisions_parallel_512x512() {
     let (c_n_o, _) = collision_check(2.0, &particles);
     
     let mut v1 = collision_pairs(c_o);
-    let mut v2 = collision_pairs(c_n_o);
+    let mut v2 = collision_pairs(&c_n_o);
     v1.sort();
     v2.sort();
     
@@ -359,3 +359,39 @@ fn test_collisions_parallel_512x512() {
     
     assert_eq!(e, true)
 }
+
+// the tree is kept between frames, particles drift across leaves, clump together and
+// some drop out, it must still find the same pairs as a fresh tree
+#[test]
+fn test_collisions_incremental() {
+    let mut quadtree = Quadtree::with_thresholds(256.0, 256.0, 1.0, 8, 4.0).merge_limit(2).maximum_depth(6);
+
+    let mut particles = Vec::new();
+    for _ in 0..3000 {
+        particles.push( Vector::new(rand::random::<f64>() * 256.0, rand::random::<f64>() * 256.0) );
+    }
+
+    for frame in 0..20 {
+        quadtree.clear();
+
+        // pulled towards one corner so the tree has to divide there and merge elsewhere
+        for p in &mut particles {
+            *p = *p * 0.9 + Vector::new(rand::random::<f64>() * 2.0 - 1.0, rand::random::<f64>() * 2.0 - 1.0);
+        }
+
+        let n = if frame % 3 == 0 { particles.len() - 700 } else { particles.len() };
+        for i in 0..n {
+            quadtree.insert( i, particles[i] );
+        }
+
+        let mut fresh = Quadtree::with_thresholds(256.0, 256.0, 1.0, 8, 4.0);
+        for i in 0..n {
+            fresh.insert( i, particles[i] );
+        }
+
+        let v1: Vec<(usize, usize)> = quadtree.collision_check_parallel().iter().map(|c| (c.p1, c.p2)).collect();
+        let v2: Vec<(usize, usize)> = fresh.collision_check().iter().map(|c| (c.p1, c.p2)).collect();
+
+        assert_eq!(v1, v2);
+    }
+}
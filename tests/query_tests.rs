@@ -0,0 +1,110 @@
+extern crate boltzmann;
+extern crate rand;
+
+use boltzmann::spatial_hash::SpatialHash;
+use boltzmann::quadtree::Quadtree;
+use boltzmann::collision::*;
+use boltzmann::query::*;
+use boltzmann::vector::Vector;
+
+fn random_position() -> Vector {
+    Vector::new(rand::random::<f64>() * 100.0, rand::random::<f64>() * 100.0)
+}
+
+// nearest hit by brute force, None if nothing is hit
+fn ray_cast(particles: &Vec<Vector>, radius: f64, origin: Vector, direction: Vector, max_distance: f64) -> Option<(usize, f64)> {
+    let direction = direction.normalise();
+    let mut best: Option<(usize, f64)> = None;
+
+    for (i, &p) in particles.iter().enumerate() {
+        if let Some(t) = ray_circle(origin, direction, p, radius) {
+            if t <= max_distance && best.map_or(true, |(_, d)| t < d) {
+                best = Some((i, t));
+            }
+        }
+    }
+    best
+}
+
+// runs the same queries on the partition and by brute force
+fn check_queries<T: SpatialQuery>(spatial_partition: &T, particles: &Vec<Vector>, radius: f64) {
+    assert_eq!(spatial_partition.len(), particles.len());
+
+    for _ in 0..50 {
+        let centre = random_position();
+        let r = rand::random::<f64>() * 20.0;
+        let expected: Vec<usize> = (0..particles.len()).filter(|&i| particles[i].distance(centre) <= r).collect();
+        assert_eq!(spatial_partition.query_circle(centre, r), expected);
+
+        let (a, b) = (random_position(), random_position());
+        let (min, max) = (Vector::new(a.x.min(b.x), a.y.min(b.y)), Vector::new(a.x.max(b.x), a.y.max(b.y)));
+        let expected: Vec<usize> = (0..particles.len()).filter(|&i| {
+            let p = particles[i];
+            p.x >= min.x && p.x <= max.x && p.y >= min.y && p.y <= max.y
+        }).collect();
+        assert_eq!(spatial_partition.query_rectangle(min, max), expected);
+
+        let k = (rand::random::<f64>() * 20.0) as usize;
+        let mut expected: Vec<(f64, usize)> = particles.iter().enumerate().map(|(i, p)| (p.distance(centre), i)).filter(|&(d, _)| !d.is_nan()).collect();
+        expected.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        let expected: Vec<usize> = expected.into_iter().take(k).map(|(_, i)| i).collect();
+        assert_eq!(spatial_partition.nearest(centre, k), expected);
+
+        // from anywhere around the box, in any direction
+        let origin = Vector::new(rand::random::<f64>() * 140.0 - 20.0, rand::random::<f64>() * 140.0 - 20.0);
+        let angle = rand::random::<f64>() * 2.0 * std::f64::consts::PI;
+        let direction = Vector::new(angle.cos(), angle.sin()) * 3.0;
+        let max_distance = rand::random::<f64>() * 150.0;
+
+        let expected = ray_cast(particles, radius, origin, direction, max_distance);
+        let found = spatial_partition.ray_cast(origin, direction, max_distance);
+        assert_eq!(found.map(|h| h.index), expected.map(|(i, _)| i));
+        if let (Some(h), Some((_, t))) = (found, expected) {
+            assert!((h.distance - t).abs() < 1e-9);
+            assert!((h.point.distance(particles[h.index]) - radius).abs() < 1e-9 || h.distance == 0.0);
+        }
+    }
+}
+
+#[test]
+fn spatial_hash_queries() {
+    let mut spatial_hash = SpatialHash::new(100.0, 100.0, 20, 20, 0.5).unwrap();
+    let particles: Vec<Vector> = (0..2000).map(|_| random_position()).collect();
+
+    for (i, &p) in particles.iter().enumerate() {
+        spatial_hash.insert( i, p );
+    }
+
+    check_queries(&spatial_hash, &particles, 0.5);
+}
+
+// the quadtree is kept between frames, left out particles must not show up
+#[test]
+fn quadtree_queries() {
+    let mut quadtree = Quadtree::new(100.0, 100.0, 0.5);
+    let particles: Vec<Vector> = (0..2000).map(|_| random_position()).collect();
+
+    for (i, &p) in particles.iter().enumerate() {
+        quadtree.insert( i, p );
+    }
+    quadtree.collision_check();
+    check_queries(&quadtree, &particles, 0.5);
+
+    quadtree.clear();
+    let particles: Vec<Vector> = particles[..1500].iter().map(|&p| p + Vector::new(rand::random::<f64>() - 0.5, rand::random::<f64>() - 0.5)).collect();
+    for (i, &p) in particles.iter().enumerate() {
+        quadtree.insert( i, p );
+    }
+    check_queries(&quadtree, &particles, 0.5);
+}
+
+// objects at a NaN distance are left out rather than breaking the sort
+#[test]
+fn nearest_skips_nan_distances() {
+    let objects = vec![(0, Vector::new(1.0, 0.0)), (1, Vector::new(std::f64::NAN, 0.0)), (2, Vector::new(3.0, 0.0)), (3, Vector::new(2.0, 0.0))];
+    let found = nearest_by_circles(Vector::new(0.0, 0.0), 4, objects.len(), 1.0, |_| objects.clone());
+    assert_eq!(found, vec![0, 3, 2]);
+
+    let found = nearest_by_circles(Vector::new(std::f64::NAN, 0.0), 2, objects.len(), 1.0, |_| objects.clone());
+    assert!(found.is_empty());
+}
@@ -0,0 +1,107 @@
+extern crate boltzmann;
+
+use boltzmann::simulator::*;
+use boltzmann::spatial_hash::SpatialHash;
+use boltzmann::naive::Naive;
+use boltzmann::generator::*;
+use boltzmann::vector::Vector;
+use boltzmann::rules::*;
+
+fn gas(seed: u64) -> Simulator<SpatialHash> {
+    let hash = SpatialHash::new(100.0, 100.0, 10, 10, 1.0).unwrap();
+    let initial_conditions = InitialConditions::new(100.0, 100.0);
+    Simulator::from_initial_conditions(hash, &initial_conditions, 500, seed, 1.0, 0.0, 1.0, 100.0, 100.0, 0.001).unwrap()
+}
+
+fn rules(simulator: &mut Simulator<SpatialHash>, name: &str, rules: &[&str]) {
+    let id = simulator.bind_named_attribute::<RuleAttribute>(name).unwrap();
+    let (a, _) = simulator.attribute_by_id_and_rng_mut::<RuleAttribute>(id).unwrap();
+    a.rules = rules.iter().map(|r| Rule::parse(r).unwrap()).collect();
+}
+
+#[test]
+fn rules_are_parsed() {
+    let rule = Rule::parse("on collision if a == 1 and b == 0 then b = 0.5 with probability 0.3").unwrap();
+    assert_eq!(rule.event, Event::Collision);
+    assert_eq!(rule.assignments.len(), 1);
+    assert_eq!(rule.assignments[0].0, Variable::B);
+
+    let rule = Rule::parse("on step then a = -(a + 1) * 2 / dt, b = 1").unwrap_err();
+    assert!(rule.contains("`b`"), "{}", rule);
+
+    for &(text, message) in &[("on collide then a = 1", "`collision` or `step`"),
+                              ("on step if a == 1 a = 2", "expected `then`"),
+                              ("on step then c = 1", "`a` or `b`"),
+                              ("on step then a = c", "unknown variable `c`"),
+                              ("on step then a = (1", "expected `)`"),
+                              ("on step then a = 1 with 0.5", "expected `probability`"),
+                              ("on step then a = 1 $", "unexpected `$`")] {
+        let e = Rule::parse(text).unwrap_err();
+        assert!(e.contains(message), "{}: {}", text, e);
+    }
+}
+
+#[test]
+fn expressions_follow_precedence() {
+    let values = [2.0, 3.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0];
+    let eval = |text: &str| {
+        let rule = Rule::parse(&format!("on collision then a = {}", text)).unwrap();
+        rule.assignments[0].1.eval(&values)
+    };
+    assert_eq!(eval("1 + a * b"), 7.0);
+    assert_eq!(eval("(1 + a) * b"), 9.0);
+    assert_eq!(eval("a - b - 1"), -2.0);
+    assert_eq!(eval("a < b and not b < a"), 1.0);
+    assert_eq!(eval("a == 3 or b == 2"), 0.0);
+}
+
+#[test]
+fn collision_rules_spread_a_value() {
+    let mut s = gas(1);
+    rules(&mut s, "rumour", &["on collision if a == 1 and b == 0 then b = 1",
+                              "on collision if b == 1 and a == 0 then a = 1"]);
+    s.set_attribute(s.attributes().id("rumour").unwrap(), 1.0, 0.0);
+
+    for _ in 0..200 {
+        s.update();
+    }
+
+    let rumour = s.attribute::<RuleAttribute>().unwrap();
+    let heard = rumour.data().iter().filter(|&&v| v == 1.0).count();
+    assert!(heard > 1, "{} heard", heard);
+    assert!(rumour.data().iter().all(|&v| v == 0.0 || v == 1.0));
+}
+
+#[test]
+fn collision_rules_apply_both_ways_round() {
+    // the second particle is the infected one
+    let positions = vec![Vector::new(10.0, 10.0), Vector::new(12.5, 10.0)];
+    let velocities = vec![Vector::new(100.0, 0.0), Vector::new(-50.0, 0.0)];
+    let mut s = Simulator::new(Naive::new(1.0), positions, velocities, 1, 1.0, 0.0, 1.0, 100.0, 100.0, 0.001);
+    let id = s.bind_named_attribute::<RuleAttribute>("virus").unwrap();
+    {
+        let (virus, _) = s.attribute_by_id_and_rng_mut::<RuleAttribute>(id).unwrap();
+        virus.rules = vec![ Rule::parse("on collision if a == 1 and b == 0 then b = 1").unwrap() ];
+        virus.data_mut()[1] = 1.0;
+    }
+    for _ in 0..20 {
+        s.update();
+    }
+
+    assert_eq!(s.attribute::<RuleAttribute>().unwrap().data(), &vec![1.0, 1.0]);
+}
+
+#[test]
+fn step_rules_fire_with_their_probability() {
+    let mut s = gas(2);
+    rules(&mut s, "decay", &["on step if a == 0 then a = 1 with probability 0.1"]);
+
+    for _ in 0..10 {
+        s.update();
+    }
+
+    // 1 - 0.9^10 of them
+    let decay = s.attribute::<RuleAttribute>().unwrap();
+    let fraction = decay.data().iter().filter(|&&v| v == 1.0).count() as f64 / decay.data().len() as f64;
+    assert!((fraction - 0.651).abs() < 0.06, "{}", fraction);
+}
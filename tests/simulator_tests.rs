@@ -0,0 +1,111 @@
+extern crate boltzmann;
+extern crate rand;
+
+use boltzmann::simulator::*;
+use boltzmann::collision::*;
+use boltzmann::spatial_hash::SpatialHash;
+use boltzmann::quadtree::Quadtree;
+use boltzmann::sweep_and_prune::SweepAndPrune;
+use boltzmann::neighbour_list::NeighbourList;
+use boltzmann::cell_list::CellList;
+use boltzmann::bvh::Bvh;
+use boltzmann::loose_quadtree::LooseQuadtree;
+use boltzmann::generator::*;
+use boltzmann::vector::Vector;
+use boltzmann::attribute::*;
+
+use rand::Rng;
+
+fn simulator(seed: u64) -> Simulator<SpatialHash> {
+    let hash = SpatialHash::new(100.0, 100.0, 10, 10, 1.0).unwrap();
+    let initial_conditions = InitialConditions::new(100.0, 100.0).velocities(VelocityDistribution::Maxwellian(100.0));
+
+    Simulator::from_initial_conditions(hash, &initial_conditions, 1000, seed, 1.0, -10.0, 0.9, 100.0, 100.0, 0.001).unwrap()
+}
+
+#[test]
+fn simulator_same_seed_same_trajectory() {
+    let mut s1 = simulator(42);
+    let mut s2 = simulator(42);
+
+    for _ in 0..200 {
+        s1.update();
+        s2.update();
+    }
+
+    assert!(s1.particles == s2.particles);
+}
+
+#[test]
+fn simulator_different_seed_different_trajectory() {
+    let s1 = simulator(1);
+    let s2 = simulator(2);
+
+    assert!(s1.particles != s2.particles);
+}
+
+// sorted, partitions can return them in any order
+fn collision_pairs(collisions: &Vec<Collision>) -> Vec<(usize, usize)> {
+    let mut pairs: Vec<(usize, usize)> = collisions.iter().map(|c| (c.p1.min(c.p2), c.p1.max(c.p2))).collect();
+    pairs.sort();
+    pairs
+}
+
+#[test]
+fn simulator_removed_particles_keep_other_ids() {
+    let mut s = simulator(3);
+    let tag = s.bind_attribute::<tag_attr>().unwrap();
+    // the last particle
+    s.set_attribute(tag, 1.0, 1.0);
+
+    let last = s.particles.id[999];
+    let position = s.particles.position(999);
+    assert!(s.remove_particle(5));
+    assert!(!s.remove_particle(5));
+
+    assert_eq!(s.number_of_particles(), 999);
+    assert_eq!(s.particle_index(5), None);
+    let i = s.particle_index(last).unwrap();
+    assert!(s.particles.position(i) == position);
+    assert_eq!(s.attribute::<tag_attr>().unwrap().data()[i], 1.0);
+
+    let id = s.insert_particle(Vector::new(50.0, 50.0), Vector::zero());
+    assert_eq!(id, 1000);
+    assert_eq!(s.particle_index(id), Some(999));
+    assert_eq!(s.attribute::<tag_attr>().unwrap().data().len(), 1000);
+}
+
+#[test]
+fn simulator_partition_follows_insertions_and_removals() {
+    let partitions: Vec<Box<SpatialPartition>> = vec![
+        Box::new( SpatialHash::new(100.0, 100.0, 10, 10, 1.0).unwrap() ),
+        Box::new( Quadtree::new(100.0, 100.0, 1.0) ),
+        Box::new( SweepAndPrune::new(100.0, 100.0, 1.0) ),
+        Box::new( NeighbourList::new(SweepAndPrune::new(100.0, 100.0, 1.25), 1.0, 0.5) ),
+        Box::new( CellList::new(100.0, 100.0, 50, 50, 1.0).unwrap() ),
+        Box::new( Bvh::new(1.0) ),
+        Box::new( LooseQuadtree::new(100.0, 100.0, 1.0) ),
+    ];
+
+    for partition in partitions {
+        let initial_conditions = InitialConditions::new(100.0, 100.0).velocities(VelocityDistribution::Maxwellian(100.0));
+        let mut s = Simulator::from_initial_conditions(partition, &initial_conditions, 1000, 9, 1.0, -10.0, 0.9, 100.0, 100.0, 0.001).unwrap();
+
+        let mut rng = seeded_rng(9);
+        for step in 0..50 {
+            for _ in 0..10 {
+                let id = s.particles.id[rng.gen_range(0, s.number_of_particles())];
+                s.remove_particle(id);
+            }
+            for _ in 0..(if step < 25 { 15 } else { 5 }) {
+                s.insert_particle(Vector::new(rng.gen::<f64>() * 100.0, rng.gen::<f64>() * 100.0), Vector::zero());
+            }
+            s.update();
+
+            let particles = (0..s.number_of_particles()).map(|i| s.particles.particle(i)).collect();
+            let expected = naive_collision_check(1.0, &particles);
+            assert_eq!(collision_pairs(s.spatial_partition.collision_check()), collision_pairs(&expected), "step {}", step);
+        }
+        assert_eq!(s.number_of_particles(), 1000);
+    }
+}
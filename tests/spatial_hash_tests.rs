@@ -77,7 +77,7 @@ fn equal_sets(set1: Vec<P>, set2: Vec<P>) -> bool {
     true
 }
 
-fn collision_pairs(collisions: Vec<Collision>) -> Vec<P> {
+fn collision_pairs(collisions: &Vec<Collision>) -> Vec<P> {
     let mut v = Vec::new();
     for c in collisions {
         if c.p1 < c.p2 {
@@ -187,7 +187,7 @@ fn test_collisions_2x2_non_random() {
     let (c_n_o, c_n_m) = collision_check(1.0, &particles);
     
     let mut v1 = collision_pairs(c_o);
-    let mut v2 = collision_pairs(c_n_o);
+    let mut v2 = collision_pairs(&c_n_o);
     
     let mut v3 = collision_pairs2(c_m);
     let mut v4 = collision_pairs2(c_n_m);
@@ -219,7 +219,7 @@ fn test_collisions_2x2() {
     let (c_n_o, c_n_m) = collision_check(1.0, &particles);
     
     let mut v1 = collision_pairs(c_o);
-    let mut v2 = collision_pairs(c_n_o);
+    let mut v2 = collision_pairs(&c_n_o);
     
     let mut v3 = collision_pairs2(c_m);
     v1.sort();
@@ -250,7 +250,7 @@ fn test_collisions_3x3() {
     let (c_n_o, c_n_m) = collision_check(1.0, &particles);
     
     let mut v1 = collision_pairs(c_o);
-    let mut v2 = collision_pairs(c_n_o);
+    let mut v2 = collision_pairs(&c_n_o);
     v1.sort();
     v2.sort();
     
@@ -282,7 +282,7 @@ fn test_collisions_10x10() {
     let (c_n_o, c_n_m) = collision_check(2.0, &particles);
     
     let mut v1 = collision_pairs(c_o);
-    let mut v2 = collision_pairs(c_n_o);
+    let mut v2 = collision_pairs(&c_n_o);
     v1.sort();
     v2.sort();
     
@@ -316,7 +316,7 @@ fn test_collisions_512x512() {
     let (c_n_o, c_n_m) = collision_check(2.0, &particles);
     
     let mut v1 = collision_pairs(c_o);
-    let mut v2 = collision_pairs(c_n_o);
+    let mut v2 = collision_pairs(&c_n_o);
     v1.sort();
     v2.sort();
         
@@ -344,7 +344,7 @@ fn test_collisions_parallel_512x512() {
     let (c_n_o, _) = collision_check(2.0, &particles);
     
     let mut v1 = collision_pairs(c_o);
-    let mut v2 = collision_pairs(c_n_o);
+    let mut v2 = collision_pairs(&c_n_o);
     v1.sort();
     v2.sort();
         
@@ -352,3 +352,25 @@ fn test_collisions_parallel_512x512() {
     
     assert_eq!(e, true)
 }
+
+#[test]
+fn test_collisions_left_of_the_box() {
+    let mut spatial_hash = SpatialHash::new(100.0, 100.0, 10, 10, 4.0).unwrap();
+
+    // more than a cell left of the box, they reach into the column before the padding
+    let particles = vec![Vector::new(-12.0, 50.0), Vector::new(-14.0, 50.0), Vector::new(-12.0, -13.0), Vector::new(-15.0, -12.0)];
+    for (i, &p) in particles.iter().enumerate() {
+        spatial_hash.insert( i, p );
+    }
+    let c_o = spatial_hash.collision_check();
+
+    let (c_n_o, _) = collision_check(4.0, &particles);
+
+    let mut v1 = collision_pairs(c_o);
+    let mut v2 = collision_pairs(&c_n_o);
+    v1.sort();
+    v2.sort();
+
+    assert_eq!(v2.len(), 2);
+    assert!(equal_sets(v1, v2));
+}
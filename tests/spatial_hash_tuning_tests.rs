@@ -0,0 +1,73 @@
+extern crate boltzmann;
+extern crate rand;
+
+use boltzmann::spatial_hash::*;
+use boltzmann::collision::*;
+use boltzmann::vector::Vector;
+
+fn collision_check(radius: f64, particles: &Vec<Vector>) -> Vec<(usize, usize)> {
+    let mut collisions = Vec::new();
+
+    for i in 0..particles.len() {
+        for j in (i+1)..particles.len() {
+            if 2.0*radius - particles[i].distance( particles[j] ) > 0.0 {
+                collisions.push( (i, j) );
+            }
+        }
+    }
+
+    collisions
+}
+
+fn collision_pairs(collisions: &Vec<Collision>) -> Vec<(usize, usize)> {
+    collisions.iter().map(|c| (c.p1, c.p2)).collect()
+}
+
+#[test]
+fn spatial_hash_grid_for_density() {
+    // 10000 objects in 512x512 is about 26 units squared each, 4 per cell gives cells ~10 wide
+    assert_eq!(SpatialHash::grid_for_density(512.0, 512.0, 1.0, 10000), (50, 50));
+
+    // cells never get narrower than a diameter
+    assert_eq!(SpatialHash::grid_for_density(100.0, 100.0, 1.0, 1000000), (50, 50));
+
+    // and there is always at least one
+    assert_eq!(SpatialHash::grid_for_density(100.0, 10.0, 1.0, 0), (1, 1));
+    assert_eq!(SpatialHash::grid_for_density(10.0, 10.0, 20.0, 10), (1, 1));
+    assert!(SpatialHash::new(10.0, 10.0, 1, 1, 20.0).is_some());
+}
+
+// the grid follows the number of objects, the collisions found don't change with it
+#[test]
+fn spatial_hash_auto_tune_resizes() {
+    let mut spatial_hash = SpatialHash::auto(512.0, 512.0, 1.0, 100).auto_tune(true);
+    let coarse = (spatial_hash.number_of_columns, spatial_hash.number_of_rows);
+
+    let mut particles = Vec::new();
+    for _ in 0..5000 {
+        particles.push( Vector::new(rand::random::<f64>() * 512.0, rand::random::<f64>() * 512.0) );
+    }
+
+    for &n in &[100, 100, 5000, 5000] {
+        spatial_hash.clear();
+        for i in 0..n {
+            spatial_hash.insert( i, particles[i] );
+        }
+
+        let visible = particles[..n].to_vec();
+        assert_eq!(collision_pairs(spatial_hash.collision_check_parallel()), collision_check(1.0, &visible));
+    }
+
+    assert!(spatial_hash.number_of_columns > coarse.0 && spatial_hash.number_of_rows > coarse.1);
+
+    // enough checks for the timings to try a few other grids
+    let expected = collision_check(1.0, &particles);
+    for _ in 0..50 {
+        spatial_hash.clear();
+        for i in 0..particles.len() {
+            spatial_hash.insert( i, particles[i] );
+        }
+
+        assert_eq!(collision_pairs(spatial_hash.collision_check()), expected);
+    }
+}
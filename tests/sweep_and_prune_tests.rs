@@ -0,0 +1,74 @@
+extern crate boltzmann;
+extern crate rand;
+
+use boltzmann::sweep_and_prune::SweepAndPrune;
+use boltzmann::collision::*;
+use boltzmann::vector::Vector;
+
+fn collision_check(radius: f64, particles: &Vec<Vector>) -> Vec<(usize, usize)> {
+    let mut collisions = Vec::new();
+
+    for i in 0..particles.len() {
+        for j in (i+1)..particles.len() {
+            if 2.0*radius - particles[i].distance( particles[j] ) > 0.0 {
+                collisions.push( (i, j) );
+            }
+        }
+    }
+
+    collisions
+}
+
+fn collision_pairs(collisions: &Vec<Collision>) -> Vec<(usize, usize)> {
+    collisions.iter().map(|c| (c.p1, c.p2)).collect()
+}
+
+#[test]
+fn sweep_and_prune_test_collisions_512x512() {
+    let mut sweep_and_prune = SweepAndPrune::new(512.0, 512.0, 2.0);
+
+    let mut particles = Vec::new();
+
+    for i in 0..10000 {
+        let x = rand::random::<f64>() * 512.0;
+        let y = rand::random::<f64>() * 512.0;
+        let p = Vector::new(x, y);
+        particles.push( p );
+
+        sweep_and_prune.insert( i, p );
+    }
+
+    let expected = collision_check(2.0, &particles);
+
+    assert_eq!(collision_pairs(sweep_and_prune.collision_check()), expected);
+}
+
+// particles move a little between frames and some drop out, the kept order must still be sorted
+#[test]
+fn sweep_and_prune_test_collisions_moving_channel() {
+    let mut sweep_and_prune = SweepAndPrune::new(1024.0, 32.0, 1.0);
+
+    let mut particles = Vec::new();
+    for _ in 0..2000 {
+        particles.push( Vector::new(rand::random::<f64>() * 1024.0, rand::random::<f64>() * 32.0) );
+    }
+
+    for frame in 0..10 {
+        sweep_and_prune.clear();
+
+        for p in &mut particles {
+            *p = *p + Vector::new(rand::random::<f64>() * 4.0 - 2.0, rand::random::<f64>() * 4.0 - 2.0);
+        }
+
+        // every other frame leaves the last 500 particles out
+        let n = if frame % 2 == 0 { particles.len() } else { particles.len() - 500 };
+        for i in 0..n {
+            sweep_and_prune.insert( i, particles[i] );
+        }
+
+        let visible = particles[..n].to_vec();
+        let expected = collision_check(1.0, &visible);
+
+        assert_eq!(collision_pairs(sweep_and_prune.collision_check_parallel()), expected);
+    }
+}